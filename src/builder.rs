@@ -1,70 +1,352 @@
-use std::{fmt::Debug, marker::PhantomData, time::Duration};
+use std::{fmt::Debug, marker::PhantomData, ops::RangeInclusive, sync::Arc, time::Duration};
 
-use exponential_backoff::Backoff;
-use gloo::net::websocket::{futures::WebSocket, Message};
+use gloo::net::websocket::Message;
 
 use crate::{
-    constants::DEFAULT_STABLE_CONNECTION_TIMEOUT, info, Error, Socket, SocketInput, SocketOutput,
-    DEFAULT_BACKOFF_MAX, DEFAULT_BACKOFF_MIN, DEFAULT_MAX_RETRIES,
+    close_code::CloseCodeActions, constants::DEFAULT_STABLE_CONNECTION_TIMEOUT,
+    endpoint::Endpoints, info, queue::lanes, reliable::RetransmitBuffer, sequence::Sequencer,
+    stats::RttWindow, subscription::Subscriptions, suspend::Suspend, CloseAction, CoalesceKey,
+    Connector, EndpointStrategy, Error, ExponentialBackoff, GlooConnector, GlooTimer, Handshake,
+    Heartbeat, OverflowPolicy, PriorityLanes, ReconnectPolicy, Reliable, Sequencing, Signal,
+    Socket, SocketInput, SocketOutput, SuspendMode, Timer, Topics, Transport, UrlProvider,
+    DEFAULT_BACKOFF_MAX, DEFAULT_BACKOFF_MIN, DEFAULT_MAX_RETRIES, DEFAULT_QUEUE_CAPACITY,
+    DEFAULT_RTT_WINDOW,
 };
 
 /// Builder for [`Socket`]
 /// Uses the DEFAULT_* consts for backoff and retry config
 #[derive(Debug)]
-pub struct SocketBuilder<I, O> {
-    url: String,
+pub struct SocketBuilder<I, O, C = GlooConnector, T = GlooTimer>
+where
+    C: Connector,
+{
+    endpoints: Endpoints,
+    connector: C,
+    timer: T,
     backoff_min: Duration,
     backoff_max: Option<Duration>,
     max_retries: u32,
+    policy: Option<Box<dyn ReconnectPolicy>>,
+    url_provider: Option<Box<dyn UrlProvider>>,
+    handshake: Option<Box<dyn Handshake<C::Transport>>>,
+    close_actions: CloseCodeActions,
+    online: Option<Box<dyn Signal>>,
+    suspend: Option<Suspend>,
+    connect_timeout: Option<Duration>,
+    heartbeat: Option<Heartbeat>,
+    reliable: Option<Reliable>,
+    sequencing: Option<Sequencing<O>>,
+    subscriptions: Option<Subscriptions<I, O>>,
+    rtt_window: usize,
+    queue_capacity: usize,
+    overflow: OverflowPolicy,
+    priority_lanes: PriorityLanes,
+    coalesce: Option<Arc<dyn CoalesceKey<I>>>,
+    #[cfg(feature = "state-events")]
+    stats_interval: Option<Duration>,
     stable_timeout: Duration,
     _phantom: PhantomData<(I, O)>,
 }
 
-impl<I, O> SocketBuilder<I, O>
+impl<I, O, C, T> SocketBuilder<I, O, C, T>
 where
     I: SocketInput,
     O: SocketOutput,
     Message: TryFrom<I>,
     <Message as TryFrom<I>>::Error: Debug,
     <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
 {
     /// Create a new builder from the given url with other config set to defaults
-    pub fn new(url: String) -> Self {
+    pub fn new(url: String) -> Self
+    where
+        C: Default,
+        T: Default,
+    {
+        Self::new_with_connector(url, C::default())
+    }
+
+    /// Create a new builder from the given url which uses `connector` to open the connection
+    pub fn new_with_connector(url: String, connector: C) -> Self
+    where
+        T: Default,
+    {
         Self {
-            url,
+            endpoints: Endpoints::new(url),
+            connector,
+            timer: T::default(),
             backoff_min: DEFAULT_BACKOFF_MIN,
             backoff_max: DEFAULT_BACKOFF_MAX,
             max_retries: DEFAULT_MAX_RETRIES,
+            policy: None,
+            url_provider: None,
+            handshake: None,
+            close_actions: CloseCodeActions::default(),
+            online: None,
+            suspend: None,
+            connect_timeout: None,
+            heartbeat: None,
+            reliable: None,
+            sequencing: None,
+            subscriptions: None,
+            rtt_window: DEFAULT_RTT_WINDOW,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            overflow: OverflowPolicy::default(),
+            priority_lanes: PriorityLanes::default(),
+            coalesce: None,
+            #[cfg(feature = "state-events")]
+            stats_interval: None,
             stable_timeout: DEFAULT_STABLE_CONNECTION_TIMEOUT,
             _phantom: PhantomData,
         }
     }
 
     /// Update the builder url
+    ///
+    /// Replaces any endpoints set with [`Self::set_endpoints`]
     pub fn set_url(mut self, url: String) -> Self {
-        self.url = url;
+        self.endpoints.set_urls(vec![url]);
+        self
+    }
+
+    /// Connect to one of several `urls` instead of a single url
+    ///
+    /// Which one is used first and which one is moved to when it keeps failing depends on
+    /// [`Self::set_endpoint_strategy`]. The url passed to [`Self::new`] is replaced
+    pub fn set_endpoints(mut self, urls: Vec<String>) -> Self {
+        self.endpoints.set_urls(urls);
+        self
+    }
+
+    /// Call `provider` for the url before each connection attempt instead of using the endpoint
+    /// url directly
+    ///
+    /// The provider is passed the [`crate::Endpoint`] being connected to and returns a future so
+    /// it can fetch something first, like a short lived auth token. An error fails the attempt
+    /// with [`Error::UrlProvider`] and the socket retries after the backoff. Since the url isn't
+    /// available straight away [`Self::open`] doesn't connect when there is a provider, the first
+    /// attempt starts when the socket is first polled
+    pub fn set_url_provider(mut self, provider: impl UrlProvider + 'static) -> Self {
+        self.url_provider = Some(Box::new(provider));
+        self
+    }
+
+    /// Run `handshake` on every new connection once it opens, for example to authenticate
+    ///
+    /// The handshake is given the connection and hands it back when it's done. Nothing queued is
+    /// sent and the socket doesn't report [`crate::State::Open`] until then. An error fails the
+    /// attempt with [`Error::Handshake`] and the socket retries after the backoff. The connect
+    /// timeout, if set, covers the handshake too
+    pub fn set_handshake(mut self, handshake: impl Handshake<C::Transport> + 'static) -> Self {
+        self.handshake = Some(Box::new(handshake));
+        self
+    }
+
+    /// Update how the endpoint is chosen. Defaults to [`EndpointStrategy::Priority`]
+    pub fn set_endpoint_strategy(mut self, strategy: EndpointStrategy) -> Self {
+        self.endpoints.set_strategy(strategy);
+        self
+    }
+
+    /// Update how many attempts in a row have to fail on an endpoint before moving to the next
+    /// one (must be > 0). An attempt fails if it closes or errors before it opens
+    pub fn set_failover_after(mut self, failover_after: u32) -> Self {
+        self.endpoints.set_failover_after(failover_after);
+        self
+    }
+
+    /// Update how long the socket stays on a fallback endpoint once it's open before closing it
+    /// and reconnecting to the first endpoint. None never fails back
+    ///
+    /// Only used with [`EndpointStrategy::Priority`]
+    pub fn set_fail_back_after(mut self, fail_back_after: Option<Duration>) -> Self {
+        self.endpoints.set_fail_back_after(fail_back_after);
+        self
+    }
+
+    /// Update the timer used for the backoff and stable timeouts
+    pub fn set_timer(mut self, timer: T) -> Self {
+        self.timer = timer;
         self
     }
 
     /// Update the minimum backoff duration (must be > 0 millis)
+    ///
+    /// Only used by the default [`ExponentialBackoff`] policy
     pub fn set_backoff_min(mut self, backoff_min: Duration) -> Self {
         self.backoff_min = backoff_min;
         self
     }
 
-    /// Update the maximum backoff duration (if set must be < u32::MAX millis)
+    /// Update the maximum backoff duration
+    ///
+    /// Only used by the default [`ExponentialBackoff`] policy
     pub fn set_backoff_max(mut self, backoff_max: Option<Duration>) -> Self {
         self.backoff_max = backoff_max;
         self
     }
 
     /// Update the maximum number of retry attempts
+    ///
+    /// This applies whichever [`ReconnectPolicy`] is used
     pub fn set_max_retries(mut self, max_retries: u32) -> Self {
         self.max_retries = max_retries;
         self
     }
 
-    /// Update the stable timeout. Must be <= u32::MAX millis
+    /// Replace the default [`ExponentialBackoff`] with a different [`ReconnectPolicy`]
+    pub fn set_reconnect_policy(mut self, policy: impl ReconnectPolicy + 'static) -> Self {
+        self.policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Set what to do when the server closes the connection with `code`
+    ///
+    /// By default every code reconnects. If several actions match a code the one set last wins
+    pub fn set_close_code_action(self, code: u16, action: CloseAction) -> Self {
+        self.set_close_code_range_action(code..=code, action)
+    }
+
+    /// Set what to do when the server closes the connection with a code in `codes`
+    ///
+    /// For example `4000..=4999` to stop on all application defined codes. If several actions
+    /// match a code the one set last wins
+    pub fn set_close_code_range_action(
+        mut self,
+        codes: RangeInclusive<u16>,
+        action: CloseAction,
+    ) -> Self {
+        self.close_actions.insert(codes, action);
+        self
+    }
+
+    /// Pause reconnecting while `online` is false
+    ///
+    /// While offline the socket reports [`crate::State::Offline`] and waits without using up
+    /// retries. When it comes back online any pending backoff is cut short and the socket
+    /// reconnects straight away. In the browser use `BrowserOnline`
+    pub fn set_online_signal(mut self, online: impl Signal + 'static) -> Self {
+        self.online = Some(Box::new(online));
+        self
+    }
+
+    /// Suspend the socket once `visible` has been false for `grace`
+    ///
+    /// Depending on `mode` the connection is closed or just not reconnected if it drops. Either
+    /// way the socket reports [`crate::State::Suspended`] while it waits and reconnects straight
+    /// away when `visible` is true again. Waiting while suspended doesn't use up retries. In the
+    /// browser use `BrowserVisible`
+    pub fn set_visibility_signal(
+        mut self,
+        visible: impl Signal + 'static,
+        grace: Duration,
+        mode: SuspendMode,
+    ) -> Self {
+        self.suspend = Some(Suspend { visible: Box::new(visible), grace, mode });
+        self
+    }
+
+    /// Update the connect timeout. None (the default) waits as long as the transport does
+    ///
+    /// A connection attempt, including the first one made by [`Self::open`], that hasn't reached
+    /// [`crate::State::Open`] after this long is abandoned. The socket produces
+    /// [`Error::ConnectTimeout`] and reconnects after the backoff
+    pub fn set_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Send an application level heartbeat while the socket is open to detect connections that
+    /// have died without closing. Off by default
+    ///
+    /// If nothing is received within the heartbeat timeout of a ping the socket produces
+    /// [`Error::HeartbeatTimeout`] and reconnects after the backoff
+    pub fn set_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Turn on at-least-once delivery for outbound messages. Off by default
+    ///
+    /// Sent messages are kept until the server acknowledges them and resent after each reconnect.
+    /// See [`Reliable`]
+    pub fn set_reliable(mut self, reliable: Reliable) -> Self {
+        self.reliable = Some(reliable);
+        self
+    }
+
+    /// De-duplicate inbound messages and pass them on in sequence order. Off by default
+    ///
+    /// See [`Sequencing`]
+    pub fn set_sequencing(mut self, sequencing: Sequencing<O>) -> Self {
+        self.sequencing = Some(sequencing);
+        self
+    }
+
+    /// Route inbound messages to subscriptions by topic and subscribe to the active topics on
+    /// every connection. Off by default
+    ///
+    /// See [`Socket::subscribe`] and [`Topics`]
+    pub fn set_subscriptions(mut self, topics: impl Topics<I, O> + 'static) -> Self {
+        self.subscriptions = Some(Subscriptions::new(topics));
+        self
+    }
+
+    /// Update how many messages can be queued for sending (must be > 0)
+    ///
+    /// What happens when the queue is full depends on [`Self::set_overflow_policy`]
+    pub fn set_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    /// Update what happens when a message is sent while the queue is full. Defaults to
+    /// [`OverflowPolicy::Block`]
+    pub fn set_overflow_policy(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Split the outbound queue into priority lanes. Defaults to a single lane
+    ///
+    /// Each lane has its own queue with the configured capacity and overflow policy. Get their
+    /// sinks with [`Socket::get_lane_sink`]. See [`PriorityLanes`]
+    pub fn set_priority_lanes(mut self, priority_lanes: PriorityLanes) -> Self {
+        self.priority_lanes = priority_lanes;
+        self
+    }
+
+    /// Coalesce queued messages by the key `key` extracts from them. Off by default
+    ///
+    /// Sending a message that has the same key as one that's still queued replaces it, so after
+    /// an outage only the latest value for each key is sent. Replaced messages aren't reported as
+    /// dropped. Each priority lane is coalesced separately. See [`CoalesceKey`]
+    ///
+    /// A replacement doesn't wait for space when the queue is full, except through
+    /// [`futures::Sink::poll_ready`] on a [`crate::SocketSink`] which can't see the message
+    pub fn set_coalesce_key(mut self, key: impl CoalesceKey<I> + 'static) -> Self {
+        self.coalesce = Some(Arc::new(key));
+        self
+    }
+
+    /// Update how many of the most recent round trip time samples [`Socket::stats`] is
+    /// calculated from (must be > 0)
+    pub fn set_rtt_window(mut self, rtt_window: usize) -> Self {
+        self.rtt_window = rtt_window;
+        self
+    }
+
+    /// Produce [`crate::Event::Stats`] every `stats_interval` once there are round trip time
+    /// samples. None (the default) never produces them
+    #[cfg(feature = "state-events")]
+    pub fn set_stats_interval(mut self, stats_interval: Option<Duration>) -> Self {
+        self.stats_interval = stats_interval;
+        self
+    }
+
+    /// Update the stable timeout
     ///
     /// This determines how long a connection needs to stay open after a retry before it
     /// is considered stable and the retry counter is reset to 0
@@ -75,47 +357,129 @@ where
 
     /// Attempts to create a reconnecting websocket and do the initial open
     /// It's set up to error at this poing because the kind of errors that can occur here are likely
-    /// fatal (See [`gloo::net::websocket::futures::WebSocket::open`] for details). These could
+    /// fatal (See [`Connector::connect`] and [`gloo::net::websocket::futures::WebSocket::open`]
+    /// for details). These could
     /// be panics but the consumer may want to display the error to the user or fallback to
     /// plain http
-    pub fn open(self) -> Result<Socket<I, O>, Error<I, O>> {
-        let SocketBuilder { url, backoff_min, backoff_max, max_retries, stable_timeout, .. } = self;
+    pub fn open(self) -> Result<Socket<I, O, C, T>, Error<I, O>> {
+        let SocketBuilder {
+            mut endpoints,
+            mut connector,
+            timer,
+            backoff_min,
+            backoff_max,
+            max_retries,
+            policy,
+            url_provider,
+            handshake,
+            close_actions,
+            online,
+            suspend,
+            connect_timeout,
+            heartbeat,
+            reliable,
+            sequencing,
+            subscriptions,
+            rtt_window,
+            queue_capacity,
+            overflow,
+            priority_lanes,
+            coalesce,
+            #[cfg(feature = "state-events")]
+            stats_interval,
+            stable_timeout,
+            ..
+        } = self;
 
         if backoff_min == Duration::ZERO {
             return Err(Error::InvalidConfig("backoff_min must be > 0".to_string()));
         }
 
-        if let Some(max) = backoff_max.as_ref() {
-            if max.as_millis() > (u32::MAX as u128) {
-                return Err(Error::InvalidConfig(
-                    "backoff_max must be <= u32::MAX millis".to_string(),
-                ));
-            }
-        }
-
         if max_retries == 0 {
             return Err(Error::InvalidConfig("backoff_retries must be > 0".to_string()));
         }
 
-        if stable_timeout.as_millis() > (u32::MAX as u128) {
+        if queue_capacity == 0 {
+            return Err(Error::InvalidConfig("queue_capacity must be > 0".to_string()));
+        }
+
+        if priority_lanes.count == 0 {
+            return Err(Error::InvalidConfig("priority lane count must be > 0".to_string()));
+        }
+
+        if priority_lanes.starvation_limit == 0 {
+            return Err(Error::InvalidConfig("starvation_limit must be > 0".to_string()));
+        }
+
+        if overflow == OverflowPolicy::DropOlderThan(Duration::ZERO) {
+            return Err(Error::InvalidConfig("overflow ttl must be > 0".to_string()));
+        }
+
+        if rtt_window == 0 {
+            return Err(Error::InvalidConfig("rtt_window must be > 0".to_string()));
+        }
+
+        #[cfg(feature = "state-events")]
+        if stats_interval.is_some_and(|interval| interval.is_zero()) {
+            return Err(Error::InvalidConfig("stats_interval must be > 0".to_string()));
+        }
+
+        if heartbeat.as_ref().is_some_and(|h| h.interval.is_zero() || h.timeout.is_zero()) {
             return Err(Error::InvalidConfig(
-                "stable_timeout must be <= u32::MAX millis".to_string(),
+                "heartbeat interval and timeout must be > 0".to_string(),
             ));
         }
-        let stable_timeout_millis = stable_timeout.as_millis() as u32;
 
-        info!("Opening reconnecting websocket to {url}");
-        let socket = WebSocket::open(&url)?;
+        if reliable.as_ref().is_some_and(|r| r.capacity == 0) {
+            return Err(Error::InvalidConfig("reliable capacity must be > 0".to_string()));
+        }
+
+        endpoints.init().map_err(Error::InvalidConfig)?;
+
+        // With a url provider the first connect has to wait for the url so it's left to the
+        // socket
+        let socket = if url_provider.is_none() {
+            info!("Opening reconnecting websocket to {}", endpoints.url());
+            Some(connector.connect(endpoints.url())?)
+        } else {
+            None
+        };
+
+        let policy = policy.unwrap_or_else(|| {
+            Box::new(ExponentialBackoff::new(backoff_min, backoff_max, max_retries))
+        });
 
-        let backoff = Backoff::new(max_retries, backoff_min, backoff_max);
+        let clock = timer.clone();
+        let (sink_sender, sink_receiver) =
+            lanes(priority_lanes, queue_capacity, overflow, coalesce, move || clock.now());
 
         Ok(Socket {
-            url,
-            socket: Some(socket),
-            backoff,
+            sink_sender,
+            sink_receiver,
+            connect_deadline: socket.as_ref().and(connect_timeout).map(|t| timer.delay(t)),
+            socket,
+            policy,
+            url_provider,
+            handshake,
+            close_actions,
+            online,
+            suspend_timeout: suspend
+                .as_ref()
+                .filter(|suspend| !suspend.visible.get())
+                .map(|suspend| timer.delay(suspend.grace)),
+            suspend,
+            connect_timeout,
+            heartbeat,
+            rtt: RttWindow::new(rtt_window),
+            retransmit: reliable.map(RetransmitBuffer::new),
+            sequencer: sequencing.map(Sequencer::new),
+            subscriptions,
+            #[cfg(feature = "state-events")]
+            stats_interval,
             max_retries,
-            stable_timeout_millis,
-            ..Default::default()
+            stable_timeout,
+            endpoints,
+            ..Socket::new(String::new(), connector, timer)
         })
     }
 }
@@ -0,0 +1,40 @@
+use std::ops::RangeInclusive;
+
+/// What [`crate::Socket`] does when the server closes the connection with a given close code
+///
+/// Configured with [`crate::SocketBuilder::set_close_code_action`] and
+/// [`crate::SocketBuilder::set_close_code_range_action`]. Codes without an action reconnect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CloseAction {
+    /// Reconnect after the delay decided by the [`crate::ReconnectPolicy`]
+    #[default]
+    Reconnect,
+    /// Reconnect straight away without waiting. The attempt still counts towards the max retries
+    ReconnectImmediately,
+    /// Stop reconnecting. The [`crate::Socket`] stream produces [`crate::Error::ClosedByServer`]
+    /// and then ends
+    Stop,
+}
+
+/// The close code to action mapping set on the builder
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CloseCodeActions {
+    rules: Vec<(RangeInclusive<u16>, CloseAction)>,
+}
+
+impl CloseCodeActions {
+    pub(crate) fn insert(&mut self, codes: RangeInclusive<u16>, action: CloseAction) {
+        self.rules.push((codes, action));
+    }
+
+    /// The action for `code`. Later rules take precedence over earlier ones so a range can be set
+    /// and then individual codes in it overridden
+    pub(crate) fn action(&self, code: u16) -> CloseAction {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(codes, _)| codes.contains(&code))
+            .map(|(_, action)| *action)
+            .unwrap_or_default()
+    }
+}
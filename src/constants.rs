@@ -7,12 +7,50 @@ pub const DEFAULT_BACKOFF_MIN: Duration = Duration::from_millis(100);
 
 /// The maximum delay for exponential backoff
 /// See [`exponential_backoff::Backoff`] for details
-/// Must be <= u32::MAX millis
 pub const DEFAULT_BACKOFF_MAX: Option<Duration> = Some(Duration::from_secs(60));
 
 /// The maximum number of retries. The stream will close after this is exceeded
 pub const DEFAULT_MAX_RETRIES: u32 = u32::MAX;
 
+/// How often the heartbeat ping is sent. See [`crate::Heartbeat`]
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for traffic after a heartbeat ping before reconnecting. See
+/// [`crate::Heartbeat`]
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many round trip time samples [`crate::RttStats`] are calculated from
+pub const DEFAULT_RTT_WINDOW: usize = 32;
+
+/// How many attempts in a row have to fail on one endpoint before moving to the next
+pub const DEFAULT_FAILOVER_AFTER: u32 = 3;
+
+/// How long a fallback endpoint has to be stable before failing back to the preferred endpoint
+/// with [`crate::EndpointStrategy::Priority`]
+pub const DEFAULT_FAIL_BACK_AFTER: Option<Duration> = Some(Duration::from_secs(300));
+
+/// How many messages can be queued for sending before [`crate::Socket::send`] and
+/// [`crate::SocketSink`] wait for space
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// How many times in a row a lower priority lane with messages waiting can be passed over before
+/// it gets a turn. See [`crate::PriorityLanes`]
+pub const DEFAULT_STARVATION_LIMIT: usize = 16;
+
+/// How many sent messages can be waiting for acknowledgement with [`crate::Reliable`] delivery
+pub const DEFAULT_RETRANSMIT_CAPACITY: usize = 256;
+
+/// How many out of order inbound messages can be held back waiting for a missing one with
+/// [`crate::Sequencing`]
+pub const DEFAULT_REORDER_WINDOW: usize = 32;
+
+/// How long inbound messages are held back waiting for a missing one before it's given up on with
+/// [`crate::Sequencing`]
+pub const DEFAULT_GAP_TIMEOUT: Option<Duration> = Some(Duration::from_secs(5));
+
+/// How long a [`crate::RpcCall`] waits for its response unless changed
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// How long to wait before considering a retried connection stable again (and setting retries back
-/// to 0) Must be <= u32::MAX millis
+/// to 0)
 pub const DEFAULT_STABLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(90);
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::{DEFAULT_FAILOVER_AFTER, DEFAULT_FAIL_BACK_AFTER};
+
+/// How [`crate::Socket`] picks the next endpoint when the current one keeps failing
+///
+/// Set with [`crate::SocketBuilder::set_endpoint_strategy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EndpointStrategy {
+    /// Endpoints are in order of preference. Moves down the list on failure and fails back to the
+    /// first endpoint once the current one has been stable for the fail back period
+    #[default]
+    Priority,
+    /// Moves to the next endpoint in the list on failure, wrapping around at the end
+    RoundRobin,
+    /// Starts on a random endpoint and moves to a different random one on failure
+    Random,
+}
+
+/// One of the endpoints set with [`crate::SocketBuilder::set_endpoints`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// Position in the list of endpoints
+    pub index: usize,
+    /// The url of the endpoint
+    pub url: String,
+}
+
+/// The endpoints and the state needed to choose between them
+#[derive(Debug, Clone)]
+pub(crate) struct Endpoints {
+    urls: Vec<String>,
+    strategy: EndpointStrategy,
+    /// Consecutive failures before moving to the next endpoint
+    failover_after: u32,
+    /// How long a non preferred endpoint needs to be stable before failing back
+    fail_back_after: Option<Duration>,
+    current: usize,
+    /// Consecutive attempts on `current` that failed before opening
+    failures: u32,
+}
+
+impl Endpoints {
+    pub(crate) fn new(url: String) -> Self {
+        Self {
+            urls: vec![url],
+            strategy: EndpointStrategy::default(),
+            failover_after: DEFAULT_FAILOVER_AFTER,
+            fail_back_after: DEFAULT_FAIL_BACK_AFTER,
+            current: 0,
+            failures: 0,
+        }
+    }
+
+    pub(crate) fn set_urls(&mut self, urls: Vec<String>) {
+        self.urls = urls;
+        self.current = 0;
+    }
+
+    pub(crate) fn set_strategy(&mut self, strategy: EndpointStrategy) {
+        self.strategy = strategy;
+    }
+
+    pub(crate) fn set_failover_after(&mut self, failover_after: u32) {
+        self.failover_after = failover_after;
+    }
+
+    pub(crate) fn set_fail_back_after(&mut self, fail_back_after: Option<Duration>) {
+        self.fail_back_after = fail_back_after;
+    }
+
+    /// Check the config and pick the starting endpoint
+    pub(crate) fn init(&mut self) -> Result<(), String> {
+        if self.urls.is_empty() {
+            return Err("at least one endpoint is required".to_string());
+        }
+        if self.failover_after == 0 {
+            return Err("failover_after must be > 0".to_string());
+        }
+        self.current = match self.strategy {
+            EndpointStrategy::Random => rand::thread_rng().gen_range(0..self.urls.len()),
+            EndpointStrategy::Priority | EndpointStrategy::RoundRobin => 0,
+        };
+        Ok(())
+    }
+
+    pub(crate) fn is_multiple(&self) -> bool {
+        self.urls.len() > 1
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.urls[self.current]
+    }
+
+    pub(crate) fn current(&self) -> Endpoint {
+        Endpoint { index: self.current, url: self.url().to_string() }
+    }
+
+    /// The connection to the current endpoint opened
+    pub(crate) fn opened(&mut self) {
+        self.failures = 0;
+    }
+
+    /// A connection attempt to the current endpoint ended before it opened. Moves to the next
+    /// endpoint if it has failed too many times in a row
+    pub(crate) fn failed(&mut self) {
+        self.failures += 1;
+        if self.failures < self.failover_after || !self.is_multiple() {
+            return;
+        }
+
+        self.failures = 0;
+        self.current = match self.strategy {
+            EndpointStrategy::Priority | EndpointStrategy::RoundRobin => {
+                (self.current + 1) % self.urls.len()
+            },
+            EndpointStrategy::Random => {
+                // Pick from the others by skipping over the current one
+                let next = rand::thread_rng().gen_range(0..self.urls.len() - 1);
+                if next >= self.current {
+                    next + 1
+                } else {
+                    next
+                }
+            },
+        };
+    }
+
+    /// How long to stay on the current endpoint once it's open before failing back to the
+    /// preferred one. None if it is the preferred one or the strategy doesn't fail back
+    pub(crate) fn fail_back_after(&self) -> Option<Duration> {
+        match self.strategy {
+            EndpointStrategy::Priority if self.current != 0 => self.fail_back_after,
+            _ => None,
+        }
+    }
+
+    /// Go back to the preferred endpoint
+    pub(crate) fn fail_back(&mut self) {
+        self.current = 0;
+        self.failures = 0;
+    }
+}
@@ -1,11 +1,11 @@
-use std::fmt::Debug;
+use std::{convert::Infallible, fmt::Debug, time::Duration};
 
 use gloo::{
-    net::websocket::{Message, WebSocketError},
+    net::websocket::{events::CloseEvent, Message, WebSocketError},
     utils::errors::JsError,
 };
 
-use crate::{error, SocketInput, SocketOutput};
+use crate::{error, Gap, SocketInput, SocketOutput};
 
 /// Errors returned by [`crate::Socket`] and [`crate::SocketBuilder`]
 #[derive(Debug, thiserror::Error)]
@@ -34,6 +34,55 @@ where
     #[error("JsError: {0}")]
     JsError(#[from] JsError),
 
+    /// Errors from the underlying [`crate::native::TokioTransport`]
+    ///
+    /// Like [`Error::WebSocketError`] these are mostly various ways the websocket has disconnected
+    /// and are handled by reconnecting. Errors returned by [`crate::SocketBuilder::open`] (invalid
+    /// url) are fatal
+    #[cfg(feature = "native-tokio")]
+    #[error("TungsteniteError: {0}")]
+    TungsteniteError(tokio_tungstenite::tungstenite::Error),
+
+    /// A connection attempt didn't reach [`crate::State::Open`] within the timeout set with
+    /// [`crate::SocketBuilder::set_connect_timeout`]
+    ///
+    /// The attempt is abandoned and counts as a failed retry, the socket reconnects after the
+    /// backoff
+    #[error("ConnectTimeout: not open after {0:?}")]
+    ConnectTimeout(Duration),
+
+    /// Nothing was received within the [`crate::Heartbeat`] timeout after a heartbeat ping
+    ///
+    /// The connection is assumed dead and closed, the socket reconnects after the backoff
+    #[error("HeartbeatTimeout: nothing received {0:?} after ping")]
+    HeartbeatTimeout(Duration),
+
+    /// The [`crate::UrlProvider`] couldn't produce a url for a connection attempt
+    ///
+    /// Counts as a failed retry, the socket reconnects after the backoff
+    #[error("UrlProvider: {0}")]
+    UrlProvider(String),
+
+    /// The [`crate::Handshake`] failed on a new connection
+    ///
+    /// Counts as a failed retry, the socket reconnects after the backoff
+    #[error("Handshake: {0}")]
+    Handshake(String),
+
+    /// The server closed the connection with a code configured as [`crate::CloseAction::Stop`]
+    ///
+    /// This is fatal, the [`crate::Socket`] stream ends after producing it
+    #[error("ClosedByServer: code {}, reason: {:?}", .0.code, .0.reason)]
+    ClosedByServer(CloseEvent),
+
+    /// Inbound messages were never received and have been given up on. See [`crate::Sequencing`]
+    ///
+    /// Only produced without the `state-events` feature, which reports it as `Event::Gap` instead.
+    /// The connection is unaffected, the application may want to resync and then call
+    /// [`crate::Socket::reset_sequence`]
+    #[error("SequenceGap: messages {} to {} were never received", .0.first, .0.last)]
+    SequenceGap(Gap),
+
     /// Invalid configuration provided to [`crate::SocketBuilder`]
     ///
     /// These errors are only returned from the bulder and are all fatal
@@ -68,6 +117,35 @@ where
     }
 }
 
+#[cfg(feature = "native-tokio")]
+impl<I, O> From<tokio_tungstenite::tungstenite::Error> for Error<I, O>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+{
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        error!("TungsteniteError: {err:?}");
+        Self::TungsteniteError(err)
+    }
+}
+
+// Allows connectors that can't fail to start a connection to use `Infallible` as their error
+impl<I, O> From<Infallible> for Error<I, O>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+{
+    fn from(err: Infallible) -> Self {
+        match err {}
+    }
+}
+
 impl<I, O> Error<I, O>
 where
     I: SocketInput,
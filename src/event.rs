@@ -7,7 +7,7 @@ use crate::{Error, SocketInput, SocketOutput};
 
 cfg_if! {
     if #[cfg(feature = "state-events")] {
-        use crate::State;
+        use crate::{Endpoint, Gap, RttStats, State};
 
         /// [`futures::Stream::Item`] type for [`crate::Socket`] when `state-events` feature is enabled
         pub enum Event<I, O>
@@ -22,6 +22,18 @@ cfg_if! {
             Message(Result<O, Error<I, O>>),
             /// An update to the state of the underlying [`gloo::net::websocket::futures::WebSocket`]
             State(State),
+            /// The endpoint the socket has just connected to. Only produced when more than one
+            /// endpoint is set with [`crate::SocketBuilder::set_endpoints`]
+            Endpoint(Endpoint),
+            /// Round trip time statistics, produced periodically when an interval is set with
+            /// [`crate::SocketBuilder::set_stats_interval`] and there is at least one sample
+            Stats(RttStats),
+            /// A message that was dropped from the outbound queue without being sent, by the
+            /// [`crate::OverflowPolicy`] or because its ttl expired (see
+            /// [`crate::Socket::send_with_ttl`])
+            Dropped(I),
+            /// Inbound messages that were never received. See [`crate::Sequencing`]
+            Gap(Gap),
         }
 
         impl<I, O> From<Result<O, Error<I, O>>> for Event<I, O>
@@ -60,7 +72,7 @@ cfg_if! {
             <Message as TryFrom<I>>::Error: Debug,
             <O as TryFrom<Message>>::Error: Debug,
         {
-            poll.map(|option| option.map(|result| Event::<_, _>::from(result)))
+            poll.map(|option| option.map(Event::<_, _>::from))
         }
 
         pub(crate) fn map_err<I, O>(
@@ -0,0 +1,42 @@
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+};
+
+use crate::{MaybeSend, MaybeSendFuture};
+
+/// The future returned by [`Handshake::handshake`]. Resolves to the connection once the handshake
+/// is done or an error describing why it failed
+pub type HandshakeFuture<T> = Pin<Box<dyn MaybeSendFuture<Output = Result<T, String>>>>;
+
+/// Runs on every new connection once it opens, before anything queued is sent, for example to
+/// send an auth frame and wait for the server to accept it
+///
+/// Set with [`crate::SocketBuilder::set_handshake`]. Implemented for closures taking the
+/// connection (the [`crate::Connector`]'s [`crate::Transport`]) and returning a future of
+/// `Result<Transport, String>`
+pub trait Handshake<T>: MaybeSend {
+    /// Start the handshake on `connection`, which is handed back when it succeeds
+    ///
+    /// The handshake has the connection to itself, messages it receives aren't passed on to the
+    /// socket. An error fails the attempt and is passed to the [`crate::ReconnectPolicy`] like
+    /// any other failed attempt
+    fn handshake(&mut self, connection: T) -> HandshakeFuture<T>;
+}
+
+impl<T, F, Fut> Handshake<T> for F
+where
+    F: FnMut(T) -> Fut + MaybeSend,
+    Fut: Future<Output = Result<T, String>> + MaybeSend + 'static,
+{
+    fn handshake(&mut self, connection: T) -> HandshakeFuture<T> {
+        Box::pin(self(connection))
+    }
+}
+
+impl<T> Debug for dyn Handshake<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Handshake")
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    fmt::{self, Debug},
+    time::Duration,
+};
+
+use gloo::net::websocket::Message;
+
+use crate::{MaybeSend, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_HEARTBEAT_TIMEOUT};
+
+/// Recognises the server's reply to a heartbeat ping. See [`MaybeSend`]
+pub trait PongPredicate: Fn(&Message) -> bool + MaybeSend {}
+impl<F: Fn(&Message) -> bool + MaybeSend> PongPredicate for F {}
+
+/// Application level heartbeat config for detecting dead connections
+///
+/// While the socket is open `ping` is sent every `interval`. If nothing at all is received within
+/// `timeout` of a ping the connection is assumed dead and the socket reconnects. Messages matching
+/// the pong predicate are swallowed so they don't show up on the [`crate::Socket`] stream
+///
+/// Set with [`crate::SocketBuilder::set_heartbeat`]
+pub struct Heartbeat {
+    pub(crate) ping: Message,
+    pub(crate) is_pong: Box<dyn PongPredicate>,
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+}
+
+impl Heartbeat {
+    /// Create a heartbeat that sends `ping` and treats messages `is_pong` returns true for as the
+    /// reply. Uses [`DEFAULT_HEARTBEAT_INTERVAL`] and [`DEFAULT_HEARTBEAT_TIMEOUT`]
+    pub fn new(ping: Message, is_pong: impl PongPredicate + 'static) -> Self {
+        Self {
+            ping,
+            is_pong: Box::new(is_pong),
+            interval: DEFAULT_HEARTBEAT_INTERVAL,
+            timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+        }
+    }
+
+    /// Update how often the ping is sent
+    pub fn set_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Update how long to wait for traffic after a ping before reconnecting
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub(crate) fn is_pong(&self, message: &Message) -> bool {
+        (self.is_pong)(message)
+    }
+}
+
+impl Debug for Heartbeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heartbeat")
+            .field("ping", &self.ping)
+            .field("interval", &self.interval)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,792 @@
+//! JSON-RPC 2.0 client built on [`RpcClient`]
+//!
+//! [`JsonRpcClient`] wraps a [`Socket`] of [`Outgoing`] and [`Incoming`] messages. It allocates
+//! request ids, serializes params, matches responses (including batches) to calls and decodes
+//! results and error objects. Calls survive reconnects the same way [`RpcClient`] calls do. Server
+//! notifications come out of the [`Notifications`] stream and everything else that isn't a
+//! response is passed on to the client's stream
+//!
+//! ```rust,no_run
+//! use futures::StreamExt;
+//! use reconnecting_websocket::{jsonrpc::JsonRpcClient, SocketBuilder};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let socket = SocketBuilder::new("wss://example.com/rpc".to_string()).open()?;
+//! let mut client: JsonRpcClient = JsonRpcClient::new(socket);
+//!
+//! // Calls resolve while the client is being polled, from another task or with a select
+//! let sum = client.handle().call::<u64>("add", [1, 2]);
+//! # let _ = sum;
+//!
+//! let mut notifications = client.notifications();
+//! # let _ = notifications.next();
+//!
+//! while let Some(_event) = client.next().await {
+//!     // State changes come out of the stream
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::{self, Debug, Display},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use futures::{channel::mpsc, ready, stream::FusedStream, FutureExt, Stream, StreamExt};
+use gloo::net::websocket::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    debug, Connector, Correlation, Error, Event, GlooConnector, GlooTimer, RpcCall, RpcClient,
+    RpcError, RpcHandle, Socket, SocketSink, Timer, Transport,
+};
+
+/// The JSON-RPC version sent with every request
+const VERSION: &str = "2.0";
+
+/// A request or notification. Notifications have no id and get no response
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    /// The request id, None for a notification
+    pub id: Option<u64>,
+    /// The method to call
+    pub method: String,
+    /// Array or object params. None to leave them out
+    pub params: Option<Value>,
+}
+
+impl Request {
+    fn to_value(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("jsonrpc".to_string(), VERSION.into());
+        object.insert("method".to_string(), self.method.clone().into());
+        if let Some(params) = &self.params {
+            object.insert("params".to_string(), params.clone());
+        }
+        if let Some(id) = self.id {
+            object.insert("id".to_string(), id.into());
+        }
+        Value::Object(object)
+    }
+}
+
+/// A message sent to the server. The input type of the [`Socket`] a [`JsonRpcClient`] wraps
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outgoing {
+    /// A single request or notification
+    Request(Request),
+    /// A batch of requests and notifications
+    Batch(Vec<Request>),
+}
+
+impl TryFrom<Outgoing> for Message {
+    type Error = serde_json::Error;
+
+    fn try_from(outgoing: Outgoing) -> Result<Self, Self::Error> {
+        let value = match outgoing {
+            Outgoing::Request(request) => request.to_value(),
+            Outgoing::Batch(requests) => requests.iter().map(Request::to_value).collect(),
+        };
+        serde_json::to_string(&value).map(Message::Text)
+    }
+}
+
+/// A JSON-RPC error object returned by the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorObject {
+    /// The error code
+    pub code: i64,
+    /// A short description of the error
+    pub message: String,
+    /// Additional information about the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl Display for ErrorObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for ErrorObject {}
+
+/// The server's response to a call
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// The id of the call. Null if the server couldn't read it
+    pub id: Value,
+    /// The result or the error object
+    pub result: Result<Value, ErrorObject>,
+}
+
+/// A notification from the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    /// The notification method
+    pub method: String,
+    /// The params, if there were any
+    pub params: Option<Value>,
+}
+
+impl Notification {
+    /// Deserialize the params. Missing params deserialize from null
+    pub fn params_as<P: DeserializeOwned>(&self) -> Result<P, serde_json::Error> {
+        P::deserialize(self.params.as_ref().unwrap_or(&Value::Null))
+    }
+}
+
+/// A message received from the server. The output type of the [`Socket`] a [`JsonRpcClient`]
+/// wraps
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming {
+    /// The response to a call
+    Response(Response),
+    /// A notification (or a request, which the client doesn't answer)
+    Notification(Notification),
+    /// The responses to a batch
+    Batch(Vec<Incoming>),
+}
+
+impl Incoming {
+    fn from_value(value: Value) -> Result<Self, serde_json::Error> {
+        use serde::de::Error as _;
+
+        let mut object = match value {
+            Value::Array(items) => {
+                return items
+                    .into_iter()
+                    .map(Self::from_value)
+                    .collect::<Result<_, _>>()
+                    .map(Self::Batch)
+            },
+            Value::Object(object) => object,
+            other => {
+                return Err(serde_json::Error::custom(format!(
+                    "expected a JSON-RPC object or batch, got {other}"
+                )))
+            },
+        };
+
+        if let Some(method) = object.remove("method") {
+            return Ok(Self::Notification(Notification {
+                method: serde_json::from_value(method)?,
+                params: object.remove("params"),
+            }));
+        }
+
+        let id = object.remove("id").unwrap_or_default();
+        let result = match object.remove("error") {
+            Some(error) => Err(serde_json::from_value(error)?),
+            None => Ok(object.remove("result").unwrap_or_default()),
+        };
+        Ok(Self::Response(Response { id, result }))
+    }
+}
+
+impl TryFrom<Message> for Incoming {
+    type Error = serde_json::Error;
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        let value = match message {
+            Message::Text(text) => serde_json::from_str(&text)?,
+            Message::Bytes(bytes) => serde_json::from_slice(&bytes)?,
+        };
+        Self::from_value(value)
+    }
+}
+
+/// Errors returned by [`JsonRpcCall`] and [`JsonRpcBatch`]
+#[derive(Debug, thiserror::Error)]
+pub enum JsonRpcError {
+    /// The call failed before a response arrived. See [`RpcError`]
+    #[error("Rpc: {0}")]
+    Rpc(#[from] RpcError),
+
+    /// The server returned an error object
+    #[error("Server: {0}")]
+    Server(ErrorObject),
+
+    /// The params couldn't be serialized or the result couldn't be deserialized
+    #[error("Json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The response didn't match the call, a batch response to a single call or the other way
+    /// round
+    #[error("UnexpectedResponse: {0:?}")]
+    UnexpectedResponse(Incoming),
+}
+
+/// The correlation id of a call. Typed like [`id_key`] so a string id can't match it
+fn call_key(id: u64) -> String {
+    format!("n:{id}")
+}
+
+/// The correlation id of a response
+fn id_key(id: &Value) -> String {
+    match id {
+        Value::Number(id) => format!("n:{id}"),
+        Value::String(id) => format!("s:{id}"),
+        other => other.to_string(),
+    }
+}
+
+/// Batches are matched by the ids of all the calls in them
+fn is_batch_key(key: &str) -> bool {
+    key.starts_with("batch:")
+}
+
+/// The id of every response in a batch response, None for those without one. None if anything
+/// in it isn't a response
+fn response_ids(responses: &[Incoming]) -> Option<Vec<Option<String>>> {
+    responses
+        .iter()
+        .map(|response| match response {
+            Incoming::Response(response) => {
+                Some((!response.id.is_null()).then(|| id_key(&response.id)))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// If a batch response with `ids` answers every call in the batch with `key`, taking the ids it
+/// doesn't have to be the calls the server couldn't read the id of
+fn answers_batch(key: &str, ids: &[Option<String>]) -> bool {
+    let Some(calls) = key.strip_prefix("batch:") else {
+        return false;
+    };
+    let calls: Vec<_> = calls.split(',').collect();
+    calls.len() == ids.len() && ids.iter().flatten().all(|id| calls.contains(&id.as_str()))
+}
+
+/// Take the first response from a batch response with an id that `matches`
+fn take_response(
+    responses: &mut Vec<Incoming>,
+    matches: impl Fn(&Value) -> bool,
+) -> Option<Response> {
+    let index =
+        responses.iter().position(|r| matches!(r, Incoming::Response(r) if matches(&r.id)))?;
+    match responses.remove(index) {
+        Incoming::Response(response) => Some(response),
+        _ => None,
+    }
+}
+
+/// Batches are matched by the ids of all the calls in them
+fn batch_key(mut ids: Vec<String>) -> Option<String> {
+    if ids.is_empty() {
+        return None;
+    }
+    ids.sort_unstable();
+    Some(format!("batch:{}", ids.join(",")))
+}
+
+struct JsonRpcCorrelation;
+
+impl Correlation<Outgoing, Incoming> for JsonRpcCorrelation {
+    fn request_id(&self, request: &Outgoing) -> Option<String> {
+        match request {
+            Outgoing::Request(request) => request.id.map(call_key),
+            Outgoing::Batch(requests) => {
+                batch_key(requests.iter().filter_map(|r| r.id).map(call_key).collect())
+            },
+        }
+    }
+
+    fn response_id(&self, response: &Incoming) -> Option<String> {
+        match response {
+            Incoming::Response(response) => Some(id_key(&response.id)),
+            Incoming::Notification(_) => None,
+            Incoming::Batch(responses) => batch_key(
+                responses
+                    .iter()
+                    .filter_map(|response| match response {
+                        Incoming::Response(response) => Some(id_key(&response.id)),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Serialize params, leaving them out if they serialize to null
+fn to_params(params: impl Serialize) -> Result<Option<Value>, serde_json::Error> {
+    serde_json::to_value(params).map(|params| Some(params).filter(|params| !params.is_null()))
+}
+
+/// A batch of calls and notifications to send in one message with [`JsonRpcClient::batch`]
+#[derive(Debug, Default)]
+pub struct Batch {
+    /// Method, params and if it's a call
+    requests: Vec<(String, Option<Value>, bool)>,
+    /// The first params that couldn't be serialized
+    error: Option<serde_json::Error>,
+}
+
+impl Batch {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a call. Its result is at the same index as the calls in the batch response
+    pub fn call(self, method: &str, params: impl Serialize) -> Self {
+        self.push(method, params, true)
+    }
+
+    /// Add a notification
+    pub fn notify(self, method: &str, params: impl Serialize) -> Self {
+        self.push(method, params, false)
+    }
+
+    fn push(mut self, method: &str, params: impl Serialize, is_call: bool) -> Self {
+        match to_params(params) {
+            Ok(params) => self.requests.push((method.to_string(), params, is_call)),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            },
+        }
+        self
+    }
+}
+
+/// A JSON-RPC 2.0 client over a reconnecting [`Socket`]
+///
+/// Polled like the socket for calls to make progress. Responses are taken off the stream,
+/// [`Incoming::Notification`]s from the server go to [`Self::notifications`] and everything else
+/// is passed on. Calls from other tasks are made with a [`JsonRpcHandle`]
+pub struct JsonRpcClient<C = GlooConnector, T = GlooTimer>
+where
+    C: Connector,
+    T: Timer,
+{
+    rpc: RpcClient<Outgoing, Incoming, C, T>,
+    handle: JsonRpcHandle<T>,
+    /// Where to send server notifications. None until [`Self::notifications`] is called and once
+    /// the stream has ended
+    notify: Option<mpsc::UnboundedSender<Notification>>,
+}
+
+impl<C, T> JsonRpcClient<C, T>
+where
+    C: Connector,
+    Error<Outgoing, Incoming>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
+{
+    /// Make JSON-RPC calls over `socket`
+    pub fn new(socket: Socket<Outgoing, Incoming, C, T>) -> Self {
+        let sink = socket.get_sink();
+        let rpc = RpcClient::new(socket, JsonRpcCorrelation);
+        let handle =
+            JsonRpcHandle { rpc: rpc.handle(), sink, next_id: Arc::new(AtomicU64::new(1)) };
+        Self { rpc, handle, notify: None }
+    }
+
+    /// Update how long calls wait for a response. See [`RpcClient::set_timeout`]
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc = self.rpc.set_timeout(timeout);
+        self
+    }
+
+    /// Call `method` with `params` and wait for the result. See [`JsonRpcHandle::call`]
+    pub fn call<R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: impl Serialize,
+    ) -> JsonRpcCall<R, T> {
+        self.handle.call(method, params)
+    }
+
+    /// Send a notification. See [`JsonRpcHandle::notify`]
+    pub fn notify(&self, method: &str, params: impl Serialize) -> Result<(), JsonRpcError> {
+        self.handle.notify(method, params)
+    }
+
+    /// Send a batch and wait for the results. See [`JsonRpcHandle::batch`]
+    pub fn batch(&self, batch: Batch) -> JsonRpcBatch<T> {
+        self.handle.batch(batch)
+    }
+
+    /// Get a handle for making calls from other tasks
+    pub fn handle(&self) -> JsonRpcHandle<T> {
+        self.handle.clone()
+    }
+
+    /// The notifications the server sends, only received while the client is being polled
+    ///
+    /// Until this is called, and once the stream is dropped, notifications are passed on to the
+    /// client's stream instead. Calling again ends the stream returned before. It ends when the
+    /// client's stream does
+    pub fn notifications(&mut self) -> Notifications {
+        let (notify, receiver) = mpsc::unbounded();
+        if !self.rpc.is_terminated() {
+            self.notify = Some(notify);
+        }
+        Notifications { receiver }
+    }
+
+    /// The wrapped socket
+    pub fn socket(&self) -> &Socket<Outgoing, Incoming, C, T> {
+        self.rpc.socket()
+    }
+
+    /// The wrapped socket, for controlling reconnects
+    pub fn socket_mut(&mut self) -> &mut Socket<Outgoing, Incoming, C, T> {
+        self.rpc.socket_mut()
+    }
+
+    /// Send notifications to [`Self::notifications`] and match the responses that can't be matched
+    /// by id to the batch waiting for them. Returns the message if it's neither
+    fn route(&self, message: Incoming) -> Option<Incoming> {
+        match message {
+            Incoming::Notification(notification) => {
+                let Some(notify) = self.notify.as_ref() else {
+                    return Some(Incoming::Notification(notification));
+                };
+                notify
+                    .unbounded_send(notification)
+                    .err()
+                    .map(|e| Incoming::Notification(e.into_inner()))
+            },
+            // A server that can't read a batch answers it with one error object without an id. It
+            // could also be the answer to a single call the server couldn't read, so it's only
+            // matched if there's one batch waiting
+            message @ Incoming::Response(Response { id: Value::Null, result: Err(_) }) => {
+                debug!("error object without an id: {message:?}");
+                self.rpc.respond_only(is_batch_key, message)
+            },
+            // Calls in a batch the server couldn't read the id of are answered without one, so the
+            // ids don't match the batch's
+            Incoming::Batch(responses) => {
+                let ids = response_ids(&responses);
+                let message = Incoming::Batch(responses);
+                match ids {
+                    Some(ids) => self.rpc.respond_only(|key| answers_batch(key, &ids), message),
+                    None => Some(message),
+                }
+            },
+            other => Some(other),
+        }
+    }
+}
+
+impl<C, T> Debug for JsonRpcClient<C, T>
+where
+    C: Connector,
+    T: Timer,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonRpcClient").field("rpc", &self.rpc).finish_non_exhaustive()
+    }
+}
+
+impl<C, T> FusedStream for JsonRpcClient<C, T>
+where
+    C: Connector,
+    Error<Outgoing, Incoming>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
+{
+    fn is_terminated(&self) -> bool {
+        self.rpc.is_terminated()
+    }
+}
+
+impl<C, T> Stream for JsonRpcClient<C, T>
+where
+    C: Connector,
+    Error<Outgoing, Incoming>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
+{
+    type Item = Event<Outgoing, Incoming>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let Some(event) = ready!(self.rpc.poll_next_unpin(cx)) else {
+                // End the notifications stream
+                self.notify = None;
+                return Poll::Ready(None);
+            };
+
+            cfg_if! {
+                if #[cfg(feature = "state-events")] {
+                    let Event::Message(Ok(message)) = event else {
+                        return Poll::Ready(Some(event));
+                    };
+                    if let Some(message) = self.route(message) {
+                        return Poll::Ready(Some(Event::Message(Ok(message))));
+                    }
+                } else {
+                    let Ok(message) = event else {
+                        return Poll::Ready(Some(event));
+                    };
+                    if let Some(message) = self.route(message) {
+                        return Poll::Ready(Some(Ok(message)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The notifications from the server. Created by [`JsonRpcClient::notifications`]
+#[derive(Debug)]
+pub struct Notifications {
+    receiver: mpsc::UnboundedReceiver<Notification>,
+}
+
+impl Stream for Notifications {
+    type Item = Notification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl FusedStream for Notifications {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+/// A cloneable handle for making calls on a [`JsonRpcClient`]
+pub struct JsonRpcHandle<T> {
+    rpc: RpcHandle<Outgoing, Incoming, T>,
+    sink: SocketSink<Outgoing>,
+    /// Shared by every handle so ids are unique
+    next_id: Arc<AtomicU64>,
+}
+
+impl<T: Clone> Clone for JsonRpcHandle<T> {
+    fn clone(&self) -> Self {
+        Self { rpc: self.rpc.clone(), sink: self.sink.clone(), next_id: self.next_id.clone() }
+    }
+}
+
+impl<T> Debug for JsonRpcHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonRpcHandle").field("rpc", &self.rpc).finish_non_exhaustive()
+    }
+}
+
+impl<T: Timer> JsonRpcHandle<T> {
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Call `method` with `params` and wait for the result, deserialized as `R`
+    ///
+    /// Params that serialize to null, like `()`, are left out. Nothing is sent until the returned
+    /// future is first polled
+    pub fn call<R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: impl Serialize,
+    ) -> JsonRpcCall<R, T> {
+        let call = to_params(params).map(|params| {
+            let request = Request { id: Some(self.next_id()), method: method.to_string(), params };
+            self.rpc.call(Outgoing::Request(request))
+        });
+        JsonRpcCall { call: Some(call), _phantom: PhantomData }
+    }
+
+    /// Send a notification to `method` with `params`. There's no response
+    ///
+    /// Doesn't wait for space in the outbound queue, returning [`RpcError::QueueFull`] if the
+    /// [`crate::OverflowPolicy`] doesn't make space
+    pub fn notify(&self, method: &str, params: impl Serialize) -> Result<(), JsonRpcError> {
+        let request = Request { id: None, method: method.to_string(), params: to_params(params)? };
+        self.sink.try_send(Outgoing::Request(request)).map_err(|e| RpcError::from(e).into())
+    }
+
+    /// Send `batch` as a single message and wait for the results of its calls
+    ///
+    /// The results are in the order the calls were added. The server has to respond to every
+    /// call in the batch for the response to be matched. Responses without an id, from a server
+    /// that couldn't read the batch or some of the calls in it, are only matched when this is
+    /// the only batch waiting they could be for. A batch of only notifications resolves to no
+    /// results as soon as it's queued
+    pub fn batch(&self, batch: Batch) -> JsonRpcBatch<T> {
+        let Batch { requests, error } = batch;
+        if let Some(e) = error {
+            return JsonRpcBatch { state: BatchState::Failed(Some(e.into())) };
+        }
+
+        let mut ids = Vec::new();
+        let requests: Vec<_> = requests
+            .into_iter()
+            .map(|(method, params, is_call)| {
+                let id = is_call.then(|| self.next_id());
+                ids.extend(id);
+                Request { id, method, params }
+            })
+            .collect();
+
+        let state = if ids.is_empty() {
+            let sent = self.sink.try_send(Outgoing::Batch(requests)).map_err(RpcError::from);
+            BatchState::Failed(sent.err().map(JsonRpcError::from))
+        } else {
+            BatchState::Waiting { call: self.rpc.call(Outgoing::Batch(requests)), ids }
+        };
+        JsonRpcBatch { state }
+    }
+}
+
+/// A [`Future`] that resolves to the result of a JSON-RPC call
+///
+/// Created by [`JsonRpcHandle::call`]. See [`RpcCall`] for the details
+pub struct JsonRpcCall<R, T: Timer> {
+    /// None once complete
+    call: Option<Result<RpcCall<Outgoing, Incoming, T>, serde_json::Error>>,
+    _phantom: PhantomData<fn() -> R>,
+}
+
+impl<R, T: Timer> JsonRpcCall<R, T> {
+    /// Update how long to wait for the response. See [`RpcCall::set_timeout`]
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.call = self.call.map(|call| call.map(|call| call.set_timeout(timeout)));
+        self
+    }
+
+    /// Send the call again if the connection drops before the response arrives. See
+    /// [`RpcCall::set_idempotent`]
+    pub fn set_idempotent(mut self) -> Self {
+        self.call = self.call.map(|call| call.map(RpcCall::set_idempotent));
+        self
+    }
+}
+
+impl<R: DeserializeOwned, T: Timer> Future for JsonRpcCall<R, T> {
+    type Output = Result<R, JsonRpcError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let call = match self.call.as_mut() {
+            Some(Ok(call)) => call,
+            Some(Err(_)) | None => {
+                return Poll::Ready(match self.call.take() {
+                    Some(Err(e)) => Err(e.into()),
+                    _ => Err(RpcError::Closed.into()),
+                })
+            },
+        };
+
+        let response = ready!(call.poll_unpin(cx));
+        self.call = None;
+        Poll::Ready(match response? {
+            Incoming::Response(Response { result: Ok(result), .. }) => Ok(R::deserialize(result)?),
+            Incoming::Response(Response { result: Err(e), .. }) => Err(JsonRpcError::Server(e)),
+            other => Err(JsonRpcError::UnexpectedResponse(other)),
+        })
+    }
+}
+
+impl<R, T: Timer> Debug for JsonRpcCall<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonRpcCall").field("call", &self.call).finish()
+    }
+}
+
+enum BatchState<T: Timer> {
+    Waiting {
+        call: RpcCall<Outgoing, Incoming, T>,
+        /// The call ids in the order they were added
+        ids: Vec<u64>,
+    },
+    /// Resolves straight away to the error, or no results if None
+    Failed(Option<JsonRpcError>),
+    Done,
+}
+
+/// A [`Future`] that resolves to the results of the calls in a [`Batch`]
+///
+/// Created by [`JsonRpcHandle::batch`]
+pub struct JsonRpcBatch<T: Timer> {
+    state: BatchState<T>,
+}
+
+impl<T: Timer> JsonRpcBatch<T> {
+    /// Update how long to wait for the responses. See [`RpcCall::set_timeout`]
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        if let BatchState::Waiting { call, ids } = self.state {
+            self.state = BatchState::Waiting { call: call.set_timeout(timeout), ids };
+        }
+        self
+    }
+}
+
+impl<T: Timer> Future for JsonRpcBatch<T> {
+    type Output = Result<Vec<Result<Value, ErrorObject>>, JsonRpcError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (call, ids) = match &mut self.state {
+            BatchState::Waiting { call, ids } => (call, ids),
+            BatchState::Failed(e) => {
+                let result = e.take().map(Err).unwrap_or(Ok(Vec::new()));
+                self.state = BatchState::Done;
+                return Poll::Ready(result);
+            },
+            BatchState::Done => return Poll::Ready(Err(RpcError::Closed.into())),
+        };
+
+        let response = ready!(call.poll_unpin(cx));
+        let ids = std::mem::take(ids);
+        self.state = BatchState::Done;
+
+        let mut responses = match response? {
+            Incoming::Batch(responses) => responses,
+            // The server couldn't read the batch
+            Incoming::Response(Response { result: Err(e), .. }) => {
+                return Poll::Ready(Err(JsonRpcError::Server(e)))
+            },
+            other => return Poll::Ready(Err(JsonRpcError::UnexpectedResponse(other))),
+        };
+
+        let matched: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                let key = call_key(*id);
+                take_response(&mut responses, |r| id_key(r) == key)
+            })
+            .collect();
+        // Responses without an id answer the calls left over, in order. The batch only matches if
+        // there's a response for every call so there's always one to find
+        let results = matched
+            .into_iter()
+            .zip(ids)
+            .map(|(response, id)| {
+                match response.or_else(|| take_response(&mut responses, Value::is_null)) {
+                    Some(response) => response.result,
+                    None => Err(ErrorObject {
+                        code: -32603,
+                        message: format!("no response for call {id}"),
+                        data: None,
+                    }),
+                }
+            })
+            .collect();
+        Poll::Ready(Ok(results))
+    }
+}
+
+impl<T: Timer> Debug for JsonRpcBatch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match &self.state {
+            BatchState::Waiting { call, .. } => format!("{call:?}"),
+            BatchState::Failed(e) => format!("Failed({e:?})"),
+            BatchState::Done => "Done".to_string(),
+        };
+        f.debug_struct("JsonRpcBatch").field("state", &state).finish()
+    }
+}
@@ -1,5 +1,5 @@
 //! A wrapper around [`WebSocket`] that reconnects when the socket
-//! drops. Uses [`Backoff`] to determine the delay between reconnects
+//! drops. Uses a [`ReconnectPolicy`] to determine the delay between reconnects
 //!
 //! # Features
 //!
@@ -7,6 +7,11 @@
 //! * `state-events` - changes the Item type of the stream to be an enum that is either a message or
 //!   a
 //! status change Both are enabled by default
+//! * `native-tokio` - adds the [`native`] module with a [`Socket`] backed by tokio-tungstenite and
+//!   tokio timers for use outside the browser
+//! * `test-util` - adds the [`loopback`] module with an in-memory transport and [`VirtualTimer`], a
+//!   manually advanced clock, for deterministic tests
+//! * `jsonrpc` - adds the [`jsonrpc`] module with a JSON-RPC 2.0 client built on [`RpcClient`]
 //!
 //! # Usage
 //!
@@ -26,24 +31,157 @@
 //!    done in the builder so it fails fast if these fatal errors occur but the same kind of error
 //!    can also occur on any reconnect and be returned by the [`Socket`] [`Stream`] implementation
 //! 1. The returned [`Socket`] can then be polled to get incoming messages. [`Socket::send`] can be
-//!    called to send messages or [`Socket::get_sender`] can be used to get an [`UnboundedSender`].
+//!    called to send messages or [`Socket::get_sink`] can be used to get a [`SocketSink`].
 //!    [`Socket::close`] or dropping it will drop the inner [`WebSocket`] which sends a close frame
 //!    and cleans up the event handlers
 //!
+//! # Outbound queue
+//!
+//! Messages are queued until the socket is open and they can be sent. The queue holds
+//! [`DEFAULT_QUEUE_CAPACITY`] messages unless changed with [`SocketBuilder::set_queue_capacity`].
+//! When it's full [`Socket::send`] and the [`SocketSink`] wait for space, which is only made
+//! while the [`Socket`] is being polled, and [`Socket::try_send`] returns an error
+//!
+//! That can be changed with [`SocketBuilder::set_overflow_policy`] to reject the new message or
+//! drop the oldest or expired ones to make space. See [`OverflowPolicy`]. With `state-events`
+//! dropped messages are reported as `Event::Dropped`, without it they can be taken with
+//! `Socket::take_dropped` and [`Socket::dropped_count`] counts them either way
+//!
+//! Messages sent with [`Socket::send_with_ttl`] or [`SocketSink::send_with_ttl`] are dropped
+//! instead of sent if they are still queued when their ttl runs out
+//!
+//! With [`SocketBuilder::set_coalesce_key`] a message replaces a queued one with the same
+//! [`CoalesceKey`], so only the latest value for each key is sent after an outage
+//!
+//! [`SocketBuilder::set_priority_lanes`] splits the queue into [`PriorityLanes`], each with its
+//! own [`SocketSink`] from [`Socket::get_lane_sink`], so urgent messages aren't stuck behind a
+//! backlog. Lanes are drained highest priority first but one that has been passed over too many
+//! times in a row gets a turn
+//!
+//! # Transports
+//!
+//! By default [`Socket`] uses [`GlooConnector`] to open a browser [`WebSocket`] and [`GlooTimer`]
+//! for the delays between reconnects. Other transports
+//! can be plugged in by implementing [`Connector`] and [`Transport`] and passing the connector to
+//! [`SocketBuilder::new_with_connector`], other timers by implementing [`Timer`]. The reconnect,
+//! queueing and backoff logic is the same regardless of the transport
+//!
+//! # Reconnect policies
+//!
+//! The delay before each reconnect is decided by a [`ReconnectPolicy`] set with
+//! [`SocketBuilder::set_reconnect_policy`]. The policy is told the attempt number, the close code
+//! or error the connection ended with and how long it was open for and returns a delay or gives
+//! up. [`ExponentialBackoff`] (the default, configured by the builder backoff settings),
+//! [`ConstantBackoff`], [`LinearBackoff`], [`FibonacciBackoff`] and [`DecorrelatedJitterBackoff`]
+//! are provided
+//!
+//! Servers that close with a code meaning "don't come back" can be respected by mapping close codes
+//! or ranges to a [`CloseAction`] with [`SocketBuilder::set_close_code_action`] and
+//! [`SocketBuilder::set_close_code_range_action`]. [`CloseAction::Stop`] ends the stream with
+//! [`Error::ClosedByServer`]
+//!
+//! # Multiple endpoints
+//!
+//! [`SocketBuilder::set_endpoints`] connects to one of a list of URLs. How the first endpoint is
+//! picked and where the socket goes after [`SocketBuilder::set_failover_after`] consecutive
+//! failures on one is decided by the [`EndpointStrategy`]. With [`EndpointStrategy::Priority`]
+//! the socket fails back to the first endpoint once a fallback has been open for
+//! [`SocketBuilder::set_fail_back_after`]. [`Socket::endpoint`] returns the current endpoint and
+//! with `state-events` it's reported as `Event::Endpoint` each time the socket opens
+//!
+//! # Dynamic urls
+//!
+//! A [`UrlProvider`] set with [`SocketBuilder::set_url_provider`] is called before every
+//! connection attempt and returns a future of the url to connect to, so a fresh auth token can be
+//! fetched for each reconnect. Errors are returned as [`Error::UrlProvider`] and retried after the
+//! backoff like any other failed attempt
+//!
+//! # Handshake
+//!
+//! A [`Handshake`] set with [`SocketBuilder::set_handshake`] is given each new connection as soon
+//! as it opens, for example to send an auth frame and wait for the server's reply, and hands it
+//! back when it's done. Queued messages are held and [`State::Open`] isn't reported until then.
+//! Errors are returned as [`Error::Handshake`] and retried after the backoff
+//!
+//! # Heartbeat
+//!
+//! Browsers don't expose websocket ping/pong so a connection that died without closing can stay
+//! [`State::Open`]. A [`Heartbeat`] set with [`SocketBuilder::set_heartbeat`] sends an application
+//! level ping while open and reconnects with [`Error::HeartbeatTimeout`] if nothing is received
+//! within the timeout. Replies matching the pong predicate are not passed on to the stream
+//!
+//! The time between each ping and its pong is kept as a round trip time sample. [`Socket::stats`]
+//! returns [`RttStats`] over the most recent samples, which can also include samples measured by
+//! the application and added with [`Socket::record_rtt`]. With `state-events`,
+//! `SocketBuilder::set_stats_interval` produces them periodically as `Event::Stats`
+//!
+//! # Reliable delivery
+//!
+//! A message is gone once it's been handed to the inner socket, so anything in flight when the
+//! connection drops is lost. [`SocketBuilder::set_reliable`] turns on at-least-once delivery. A
+//! [`Reliable`] config's [`Envelope`] adds a sequence id to each outbound message and recognises
+//! the server's acknowledgements. Unacknowledged messages are kept and resent after each reconnect
+//! ahead of the rest of the queue, so the server has to ignore sequence ids it has already seen.
+//! [`Socket::unacked`] returns how many are waiting
+//!
+//! # Inbound sequencing
+//!
+//! Servers that number their messages can have them put in order with
+//! [`SocketBuilder::set_sequencing`]. A [`Sequencing`] config's function extracts the sequence
+//! number from each decoded message. Duplicates, like messages replayed after a reconnect, are
+//! dropped and messages that arrive early are held back within a window and a timeout waiting for
+//! the missing ones. Missing messages that never arrive are reported as `Event::Gap` with
+//! `state-events`, or [`Error::SequenceGap`] without it, so the application can resync, after which
+//! [`Socket::reset_sequence`] starts the sequence again
+//!
+//! # Subscriptions
+//!
+//! [`SocketBuilder::set_subscriptions`] takes a [`Topics`] implementation that builds subscribe and
+//! unsubscribe messages and finds the topic of inbound messages. [`Socket::subscribe`] returns a
+//! [`Subscription`] stream that receives the messages published to its topic instead of the
+//! socket stream. The active topics are subscribed to on every new connection ahead of anything
+//! else waiting to be sent and dropping a [`Subscription`] unsubscribes
+//!
+//! # Request/response
+//!
+//! [`RpcClient`] wraps a [`Socket`] for request/response traffic. [`RpcClient::call`] (or
+//! [`RpcHandle::call`] from another task) sends a request and resolves to the response with the
+//! same correlation id, found by a [`Correlation`]. The client is polled like the socket and
+//! passes on everything that isn't a response. Calls time out after [`DEFAULT_RPC_TIMEOUT`] or
+//! [`RpcCall::set_timeout`] and fail with [`RpcError::Disconnected`] when the connection their
+//! request was written to drops, unless [`RpcCall::set_idempotent`] was used so they're sent again
+//! after the reconnect
+//!
+//! With the `jsonrpc` feature, `jsonrpc::JsonRpcClient` does the same for JSON-RPC 2.0. It
+//! allocates ids, serializes params, decodes results and error objects, sends batches and sends
+//! server notifications to their own stream
+//!
+//! # Browser integration
+//!
+//! [`SocketBuilder::set_online_signal`] with `BrowserOnline` (wasm only) pauses reconnecting while
+//! the browser is offline (reported as [`State::Offline`]) without using up retries and reconnects
+//! as soon as it's back online. Other environments can provide their own [`Signal`]
+//!
+//! [`SocketBuilder::set_visibility_signal`] with `BrowserVisible` (wasm only) suspends the socket
+//! once the page has been hidden for a grace period, either closing the connection or only
+//! stopping reconnects depending on the [`SuspendMode`]. It's reported as [`State::Suspended`]
+//! and the socket reconnects straight away when the page is visible again
+//!
+//! Reconnecting can also be controlled directly. [`Socket::reconnect_now`] skips the rest of the
+//! backoff, [`Socket::pause`] disconnects and holds off reconnecting (reported as
+//! [`State::Paused`]) while keeping queued messages and [`Socket::resume`] starts connecting again
+//! with a fresh retry counter
+//!
 //! # Example
 //!
 //! `tests/reconnect.rs`
-//! ```rust
+//! ```rust,no_run
 #![doc = include_str!("../tests/reconnect.rs")]
 //! ```
 //! 
 //! [`WebSocket`]: gloo::net::websocket::futures::WebSocket
-//! [`Backoff`]: exponential_backoff::Backoff
 //! [`WebSocket::open`]: gloo::net::websocket::futures::WebSocket::open
 //! [`Stream`]: futures::Stream
-//! [`UnboundedSender`]: futures::channel::mpsc::UnboundedSender
-
-// TODO: Replace unbounded with a reasonable bounded channel
 
 #![warn(missing_docs)]
 
@@ -52,7 +190,7 @@ use std::fmt::Debug;
 use cfg_if::cfg_if;
 #[doc(inline)]
 /// Re-export of [`gloo::net::websocket::Message`].
-pub use gloo::net::websocket::Message;
+pub use gloo::net::websocket::{events::CloseEvent, Message};
 
 mod error;
 pub use error::Error;
@@ -64,7 +202,12 @@ mod event;
 pub use event::Event;
 
 mod constants;
-pub use constants::{DEFAULT_BACKOFF_MAX, DEFAULT_BACKOFF_MIN, DEFAULT_MAX_RETRIES};
+pub use constants::{
+    DEFAULT_BACKOFF_MAX, DEFAULT_BACKOFF_MIN, DEFAULT_FAILOVER_AFTER, DEFAULT_FAIL_BACK_AFTER,
+    DEFAULT_GAP_TIMEOUT, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_HEARTBEAT_TIMEOUT,
+    DEFAULT_MAX_RETRIES, DEFAULT_QUEUE_CAPACITY, DEFAULT_REORDER_WINDOW,
+    DEFAULT_RETRANSMIT_CAPACITY, DEFAULT_RPC_TIMEOUT, DEFAULT_RTT_WINDOW, DEFAULT_STARVATION_LIMIT,
+};
 
 mod builder;
 pub use builder::SocketBuilder;
@@ -73,7 +216,75 @@ mod state;
 pub use state::State;
 
 mod socket;
-pub use socket::{Socket, SocketSink};
+pub use socket::Socket;
+
+mod queue;
+pub use queue::{CoalesceKey, OverflowPolicy, PriorityLanes, SendError, SocketSink};
+
+mod close_code;
+pub use close_code::CloseAction;
+
+mod endpoint;
+pub use endpoint::{Endpoint, EndpointStrategy};
+
+mod handshake;
+pub use handshake::{Handshake, HandshakeFuture};
+
+mod heartbeat;
+pub use heartbeat::{Heartbeat, PongPredicate};
+
+mod reliable;
+pub use reliable::{Ack, Envelope, Reliable};
+
+mod rpc;
+pub use rpc::{Correlation, RpcCall, RpcClient, RpcError, RpcHandle};
+
+mod sequence;
+pub use sequence::{Gap, SequenceFn, Sequencing};
+
+mod stats;
+pub use stats::RttStats;
+
+mod subscription;
+pub use subscription::{SubscribeError, Subscription, Topics};
+
+mod url_provider;
+pub use url_provider::{MaybeSendFuture, UrlFuture, UrlProvider};
+
+mod policy;
+pub use policy::{
+    ConstantBackoff, DecorrelatedJitterBackoff, ExponentialBackoff, FibonacciBackoff,
+    LinearBackoff, ReconnectContext, ReconnectDecision, ReconnectPolicy,
+};
+
+mod signal;
+#[cfg(feature = "test-util")]
+pub use signal::ManualSignal;
+#[cfg(target_arch = "wasm32")]
+pub use signal::{BrowserOnline, BrowserVisible};
+pub use signal::{MaybeSend, MaybeSync, Signal};
+
+mod suspend;
+pub use suspend::SuspendMode;
+
+mod transport;
+pub use transport::{Connector, GlooConnector, GlooTransport, Transport};
+
+mod timer;
+#[cfg(feature = "native-tokio")]
+pub use timer::TokioTimer;
+pub use timer::{GlooDelay, GlooTimer, Timer};
+#[cfg(feature = "test-util")]
+pub use timer::{VirtualDelay, VirtualTimer};
+
+#[cfg(feature = "native-tokio")]
+pub mod native;
+
+#[cfg(feature = "test-util")]
+pub mod loopback;
+
+#[cfg(feature = "jsonrpc")]
+pub mod jsonrpc;
 
 mod dummy_tracing;
 
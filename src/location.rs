@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::fmt;
 
 use gloo::utils::errors::JsError;
 use web_sys::{
@@ -15,20 +15,20 @@ pub enum HttpProtocol {
     Https,
 }
 
-impl ToString for HttpProtocol {
-    fn to_string(&self) -> String {
+impl fmt::Display for HttpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use HttpProtocol::*;
         match self {
-            Http => "http".to_string(),
-            Https => "https".to_string(),
+            Http => f.write_str("http"),
+            Https => f.write_str("https"),
         }
     }
 }
 
-impl Into<WebSocketProtocol> for HttpProtocol {
-    fn into(self) -> WebSocketProtocol {
+impl From<HttpProtocol> for WebSocketProtocol {
+    fn from(value: HttpProtocol) -> Self {
         use HttpProtocol::*;
-        match self {
+        match value {
             Http => WebSocketProtocol::Ws,
             Https => WebSocketProtocol::Wss,
         }
@@ -44,12 +44,12 @@ pub enum WebSocketProtocol {
     Wss,
 }
 
-impl ToString for WebSocketProtocol {
-    fn to_string(&self) -> String {
+impl fmt::Display for WebSocketProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use WebSocketProtocol::*;
         match self {
-            Ws => "ws".to_string(),
-            Wss => "wss".to_string(),
+            Ws => f.write_str("ws"),
+            Wss => f.write_str("wss"),
         }
     }
 }
@@ -59,13 +59,9 @@ pub fn get_proto_and_host() -> Result<(HttpProtocol, String), JsError> {
     let location =
         window().ok_or(JsSysError::from(ReferenceError::new("window global was none")))?.location();
 
-    let host = location.host().map_err(|v| {
-        JsSysError::try_from(v).unwrap_or(JsSysError::new("failed to get location.host"))
-    })?;
+    let host = location.host().map_err(JsSysError::from)?;
 
-    let proto = location.protocol().map_err(|v| {
-        JsSysError::try_from(v).unwrap_or(JsSysError::new("faild to get location.protocol"))
-    })?;
+    let proto = location.protocol().map_err(JsSysError::from)?;
 
     let proto = match proto.as_ref() {
         "http:" => Ok(HttpProtocol::Http),
@@ -0,0 +1,392 @@
+//! In-memory [`Connector`] and [`Transport`] pair for deterministic tests
+//!
+//! [`loopback`] returns a [`LoopbackConnector`] to build the [`crate::Socket`] with and a
+//! [`LoopbackServer`] that plays the part of the remote end. Every connection attempt the socket
+//! makes shows up as a [`LoopbackPeer`] on the server which can accept it, refuse it, leave the
+//! handshake stalled, send messages, inject errors, close it with a chosen code and read what the
+//! client sent
+//!
+//! The client side mimics the browser [`gloo::net::websocket::futures::WebSocket`]. Errors are
+//! [`WebSocketError`]s and a close is reported as [`WebSocketError::ConnectionClose`] followed by
+//! the end of the stream
+//!
+//! Requires the `test-util` feature
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use futures::{Sink, Stream};
+use gloo::net::websocket::{events::CloseEvent, Message, WebSocketError};
+
+use crate::{Connector, State, Transport};
+
+/// Close code reported when a connection is refused or dropped without a close frame
+pub const ABNORMAL_CLOSURE: u16 = 1006;
+
+/// Create a connected [`LoopbackConnector`] and [`LoopbackServer`] pair
+pub fn loopback() -> (LoopbackConnector, LoopbackServer) {
+    let shared = Arc::new(Mutex::new(ServerShared::default()));
+    (LoopbackConnector { shared: shared.clone() }, LoopbackServer { shared })
+}
+
+#[derive(Debug, Default)]
+struct ServerShared {
+    /// Connections the server hasn't taken yet
+    incoming: VecDeque<LoopbackPeer>,
+    waker: Option<Waker>,
+    /// Urls of every connection attempt in order
+    urls: Vec<String>,
+    auto_accept: bool,
+}
+
+#[derive(Debug)]
+struct Connection {
+    state: State,
+    /// Items waiting to be produced by the client [`Stream`]
+    to_client: VecDeque<Result<Message, WebSocketError>>,
+    /// The client stream ends once `to_client` is drained
+    ended: bool,
+    client_stream_waker: Option<Waker>,
+    client_sink_waker: Option<Waker>,
+    /// While set the client [`Sink`] isn't ready to send
+    backpressure: bool,
+    /// Messages sent by the client
+    to_server: VecDeque<Message>,
+    server_waker: Option<Waker>,
+    /// The code and reason the client closed the connection with
+    client_close: Option<(Option<u16>, Option<String>)>,
+    /// The close event the server ended the connection with
+    close_event: Option<CloseEvent>,
+}
+
+impl Connection {
+    fn new() -> Self {
+        Self {
+            state: State::Connecting,
+            to_client: VecDeque::new(),
+            ended: false,
+            client_stream_waker: None,
+            client_sink_waker: None,
+            backpressure: false,
+            to_server: VecDeque::new(),
+            server_waker: None,
+            client_close: None,
+            close_event: None,
+        }
+    }
+
+    fn wake_client(&mut self) {
+        if let Some(waker) = self.client_stream_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.client_sink_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_server(&mut self) {
+        if let Some(waker) = self.server_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Queue the close for the client and end its stream
+    fn end(&mut self, errors: impl IntoIterator<Item = WebSocketError>) {
+        if self.ended {
+            return;
+        }
+        for error in errors {
+            if let WebSocketError::ConnectionClose(event) = &error {
+                self.close_event = Some(event.clone());
+            }
+            self.to_client.push_back(Err(error));
+        }
+        self.ended = true;
+        self.state = State::Closed;
+        self.wake_client();
+        self.wake_server();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panic in a test holding the lock shouldn't cascade into unrelated panics
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// [`Connector`] half of [`loopback`]. Cheap to clone
+#[derive(Debug, Clone)]
+pub struct LoopbackConnector {
+    shared: Arc<Mutex<ServerShared>>,
+}
+
+impl Connector for LoopbackConnector {
+    type Error = Infallible;
+    type Transport = LoopbackTransport;
+
+    fn connect(&mut self, url: &str) -> Result<Self::Transport, Self::Error> {
+        let connection = Arc::new(Mutex::new(Connection::new()));
+        let peer = LoopbackPeer { connection: connection.clone() };
+
+        let mut shared = lock(&self.shared);
+        shared.urls.push(url.to_string());
+        if shared.auto_accept {
+            peer.accept();
+        }
+        shared.incoming.push_back(peer);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+
+        Ok(LoopbackTransport { connection })
+    }
+}
+
+/// Client side of a loopback connection opened by [`LoopbackConnector`]
+#[derive(Debug)]
+pub struct LoopbackTransport {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Transport for LoopbackTransport {
+    type Error = WebSocketError;
+
+    fn state(&self) -> State {
+        lock(&self.connection).state
+    }
+
+    fn close(self, code: Option<u16>, reason: Option<&str>) {
+        let mut connection = lock(&self.connection);
+        if connection.client_close.is_none() {
+            connection.client_close = Some((code, reason.map(str::to_string)));
+        }
+    }
+
+    fn close_event(&self) -> Option<CloseEvent> {
+        lock(&self.connection).close_event.clone()
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        let mut connection = lock(&self.connection);
+        if connection.client_close.is_none() {
+            connection.client_close = Some((None, None));
+        }
+        connection.state = State::Closed;
+        connection.wake_server();
+    }
+}
+
+impl Stream for LoopbackTransport {
+    type Item = Result<Message, WebSocketError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut connection = lock(&self.connection);
+        if let Some(item) = connection.to_client.pop_front() {
+            Poll::Ready(Some(item))
+        } else if connection.ended {
+            Poll::Ready(None)
+        } else {
+            connection.client_stream_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Sink<Message> for LoopbackTransport {
+    type Error = WebSocketError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut connection = lock(&self.connection);
+        if connection.state == State::Connecting || connection.backpressure {
+            connection.client_sink_waker = Some(cx.waker().clone());
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        let mut connection = lock(&self.connection);
+        if connection.state != State::Open {
+            return Err(WebSocketError::ConnectionError);
+        }
+        connection.to_server.push_back(item);
+        connection.wake_server();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Server half of [`loopback`]. Cheap to clone
+///
+/// Implements [`Stream`] producing a [`LoopbackPeer`] for every connection attempt
+#[derive(Debug, Clone)]
+pub struct LoopbackServer {
+    shared: Arc<Mutex<ServerShared>>,
+}
+
+impl LoopbackServer {
+    /// Take the next connection attempt if there is one
+    pub fn try_next_connection(&self) -> Option<LoopbackPeer> {
+        lock(&self.shared).incoming.pop_front()
+    }
+
+    /// The number of connection attempts the client has made so far
+    pub fn connection_count(&self) -> usize {
+        lock(&self.shared).urls.len()
+    }
+
+    /// The urls of every connection attempt the client has made so far in order
+    pub fn urls(&self) -> Vec<String> {
+        lock(&self.shared).urls.clone()
+    }
+
+    /// When enabled new connections are accepted as soon as they are made
+    ///
+    /// They are still produced by the [`Stream`] so they can be interacted with
+    pub fn set_auto_accept(&self, auto_accept: bool) {
+        lock(&self.shared).auto_accept = auto_accept;
+    }
+}
+
+impl Stream for LoopbackServer {
+    type Item = LoopbackPeer;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = lock(&self.shared);
+        if let Some(peer) = shared.incoming.pop_front() {
+            Poll::Ready(Some(peer))
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// The server end of a single loopback connection. Cheap to clone
+///
+/// Until [`LoopbackPeer::accept`] or [`LoopbackPeer::refuse`] is called the client is stuck in
+/// [`State::Connecting`] which simulates a stalled handshake
+///
+/// Implements [`Stream`] producing the messages the client sent. The stream ends once the client
+/// has closed the connection and all messages have been read
+#[derive(Debug, Clone)]
+pub struct LoopbackPeer {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl LoopbackPeer {
+    /// Complete the handshake. The client moves to [`State::Open`]
+    pub fn accept(&self) {
+        let mut connection = lock(&self.connection);
+        if connection.state == State::Connecting {
+            connection.state = State::Open;
+            connection.wake_client();
+        }
+    }
+
+    /// Refuse the connection like a browser does when the handshake fails
+    ///
+    /// The client gets [`WebSocketError::ConnectionError`] followed by a
+    /// [`WebSocketError::ConnectionClose`] with code [`ABNORMAL_CLOSURE`]
+    pub fn refuse(&self) {
+        lock(&self.connection).end([
+            WebSocketError::ConnectionError,
+            WebSocketError::ConnectionClose(CloseEvent {
+                code: ABNORMAL_CLOSURE,
+                reason: String::new(),
+                was_clean: false,
+            }),
+        ]);
+    }
+
+    /// Close the connection with the given `code` and `reason`
+    ///
+    /// The client gets a [`WebSocketError::ConnectionClose`] and then the end of the stream
+    pub fn close(&self, code: u16, reason: &str) {
+        lock(&self.connection).end([WebSocketError::ConnectionClose(CloseEvent {
+            code,
+            reason: reason.to_string(),
+            was_clean: true,
+        })]);
+    }
+
+    /// Send a message to the client
+    ///
+    /// Returns false if the connection isn't open
+    pub fn send(&self, message: Message) -> bool {
+        let mut connection = lock(&self.connection);
+        if connection.state != State::Open {
+            return false;
+        }
+        connection.to_client.push_back(Ok(message));
+        connection.wake_client();
+        true
+    }
+
+    /// While enabled the client [`Sink`] isn't ready to send, like a full send buffer. Messages
+    /// back up in the client until it's disabled again
+    pub fn set_backpressure(&self, backpressure: bool) {
+        let mut connection = lock(&self.connection);
+        connection.backpressure = backpressure;
+        if !backpressure {
+            connection.wake_client();
+        }
+    }
+
+    /// Make the client stream produce `error` without closing the connection
+    pub fn inject_error(&self, error: WebSocketError) {
+        let mut connection = lock(&self.connection);
+        connection.to_client.push_back(Err(error));
+        connection.wake_client();
+    }
+
+    /// Take the next message the client sent if there is one
+    pub fn try_recv(&self) -> Option<Message> {
+        lock(&self.connection).to_server.pop_front()
+    }
+
+    /// Take all the messages the client has sent so far
+    pub fn drain(&self) -> Vec<Message> {
+        lock(&self.connection).to_server.drain(..).collect()
+    }
+
+    /// The state of the connection as seen by the client
+    pub fn state(&self) -> State {
+        lock(&self.connection).state
+    }
+
+    /// The code and reason the client closed the connection with. None if it hasn't
+    pub fn client_close(&self) -> Option<(Option<u16>, Option<String>)> {
+        lock(&self.connection).client_close.clone()
+    }
+}
+
+impl Stream for LoopbackPeer {
+    type Item = Message;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut connection = lock(&self.connection);
+        if let Some(message) = connection.to_server.pop_front() {
+            Poll::Ready(Some(message))
+        } else if connection.client_close.is_some() || connection.state == State::Closed {
+            Poll::Ready(None)
+        } else {
+            connection.server_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
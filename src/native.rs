@@ -0,0 +1,266 @@
+//! Native (non-wasm) backend built on [`tokio_tungstenite`] and tokio timers
+//!
+//! [`Socket`] and [`SocketBuilder`] are aliases of the crate level types so the [`crate::Event`],
+//! [`crate::State`], [`crate::Error`] and [`crate::SocketSink`] API is identical to the browser
+//! backend. They must be used from within a tokio runtime with the time driver enabled
+//!
+//! `wss://` urls require one of the TLS features of [`tokio_tungstenite`] to be enabled
+//!
+//! ```rust,no_run
+//! # use reconnecting_websocket::Message;
+//! # #[derive(Debug)] struct Input;
+//! # impl TryFrom<Input> for Message { type Error = (); fn try_from(_: Input) -> Result<Self, ()> { Err(()) } }
+//! # #[derive(Debug)] struct Output;
+//! # impl TryFrom<Message> for Output { type Error = (); fn try_from(_: Message) -> Result<Self, ()> { Err(()) } }
+//! use reconnecting_websocket::native::SocketBuilder;
+//!
+//! # async fn run() {
+//! let socket = SocketBuilder::<Input, Output>::new("ws://127.0.0.1:8080".to_string()).open();
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Sink, Stream};
+use gloo::net::websocket::{events::CloseEvent, Message};
+use tokio::{net::TcpStream, runtime::Handle};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        handshake::client::{Request, Response},
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message as TungsteniteMessage,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{trace, Connector, State, TokioTimer, Transport};
+
+/// [`crate::Socket`] using [`TokioConnector`] and [`TokioTimer`]
+pub type Socket<I, O> = crate::Socket<I, O, TokioConnector, TokioTimer>;
+
+/// [`crate::SocketBuilder`] using [`TokioConnector`] and [`TokioTimer`]
+pub type SocketBuilder<I, O> = crate::SocketBuilder<I, O, TokioConnector, TokioTimer>;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type ConnectFuture =
+    Pin<Box<dyn Future<Output = Result<(WsStream, Response), tungstenite::Error>> + Send>>;
+
+/// [`Connector`] that opens a [`TokioTransport`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioConnector;
+
+impl Connector for TokioConnector {
+    type Error = tungstenite::Error;
+    type Transport = TokioTransport;
+
+    fn connect(&mut self, url: &str) -> Result<Self::Transport, Self::Error> {
+        // Parse the url up front so invalid urls fail fast like they do in the browser
+        let request: Request = url.into_client_request()?;
+        Ok(TokioTransport {
+            inner: Inner::Connecting(Box::pin(connect_async(request))),
+            close_event: None,
+        })
+    }
+}
+
+enum Inner {
+    Connecting(ConnectFuture),
+    Open(Box<WsStream>),
+    Closing(Box<WsStream>),
+    Closed,
+}
+
+/// A [`tokio_tungstenite`] websocket connection
+///
+/// The connection handshake is driven by polling the [`Stream`] so it reports
+/// [`State::Connecting`] until then. Ping and pong frames are handled internally and not
+/// produced by the [`Stream`]
+pub struct TokioTransport {
+    inner: Inner,
+    /// The close frame the server sent, or an abnormal closure if the connection ended without
+    /// one
+    close_event: Option<CloseEvent>,
+}
+
+impl std::fmt::Debug for TokioTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokioTransport")
+            .field("state", &self.state())
+            .field("close_event", &self.close_event)
+            .finish()
+    }
+}
+
+impl TokioTransport {
+    /// Drive the connection handshake if it's still in progress
+    ///
+    /// Returns Ready(Ok) once the connection is no longer connecting
+    fn poll_connect(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), tungstenite::Error>> {
+        if let Inner::Connecting(future) = &mut self.inner {
+            match ready!(future.as_mut().poll(cx)) {
+                Ok((stream, _)) => {
+                    trace!("tungstenite connected");
+                    self.inner = Inner::Open(Box::new(stream));
+                    // Wake so the socket notices the state change even though there's no message
+                    cx.waker().wake_by_ref();
+                },
+                Err(e) => {
+                    self.set_closed();
+                    return Poll::Ready(Err(e));
+                },
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Mark the connection closed, recording an abnormal closure like the browser does if no
+    /// close frame was received
+    fn set_closed(&mut self) {
+        self.inner = Inner::Closed;
+        self.close_event.get_or_insert_with(|| CloseEvent {
+            code: CloseCode::Abnormal.into(),
+            reason: String::new(),
+            was_clean: false,
+        });
+    }
+}
+
+impl Transport for TokioTransport {
+    type Error = tungstenite::Error;
+
+    fn state(&self) -> State {
+        match self.inner {
+            Inner::Connecting(_) => State::Connecting,
+            Inner::Open(_) => State::Open,
+            Inner::Closing(_) => State::Closing,
+            Inner::Closed => State::Closed,
+        }
+    }
+
+    fn close(self, code: Option<u16>, reason: Option<&str>) {
+        let (Inner::Open(mut stream) | Inner::Closing(mut stream)) = self.inner else {
+            return;
+        };
+
+        // Closing is async so it has to be spawned. If there's no runtime the stream is just
+        // dropped which closes the TCP connection without a close frame
+        if let Ok(handle) = Handle::try_current() {
+            let frame = CloseFrame {
+                code: code.map(CloseCode::from).unwrap_or(CloseCode::Normal),
+                reason: reason.unwrap_or_default().to_string().into(),
+            };
+            handle.spawn(async move {
+                let _ = stream.close(Some(frame)).await;
+            });
+        }
+    }
+
+    fn close_event(&self) -> Option<CloseEvent> {
+        self.close_event.clone()
+    }
+}
+
+impl Stream for TokioTransport {
+    type Item = Result<Message, tungstenite::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Err(e) = ready!(self.poll_connect(cx)) {
+            return Poll::Ready(Some(Err(e)));
+        }
+
+        loop {
+            let stream = match &mut self.inner {
+                Inner::Open(stream) | Inner::Closing(stream) => stream,
+                // Returned if the connecting future failed (after the error) or the stream ended
+                Inner::Closed | Inner::Connecting(_) => return Poll::Ready(None),
+            };
+
+            match ready!(Pin::new(stream).poll_next(cx)) {
+                Some(Ok(message)) => match message {
+                    TungsteniteMessage::Text(text) => {
+                        return Poll::Ready(Some(Ok(Message::Text(text.to_string()))))
+                    },
+                    TungsteniteMessage::Binary(bytes) => {
+                        return Poll::Ready(Some(Ok(Message::Bytes(bytes.into()))))
+                    },
+                    TungsteniteMessage::Close(frame) => {
+                        trace!("tungstenite received close: {frame:?}");
+                        self.close_event = Some(match frame {
+                            Some(frame) => CloseEvent {
+                                code: frame.code.into(),
+                                reason: frame.reason.to_string(),
+                                was_clean: true,
+                            },
+                            None => CloseEvent {
+                                code: CloseCode::Status.into(),
+                                reason: String::new(),
+                                was_clean: true,
+                            },
+                        });
+                        // Tungstenite replies to the close and ends the stream
+                        self.inner = match std::mem::replace(&mut self.inner, Inner::Closed) {
+                            Inner::Open(stream) => Inner::Closing(stream),
+                            other => other,
+                        };
+                    },
+                    // Pings are answered by tungstenite
+                    TungsteniteMessage::Ping(_)
+                    | TungsteniteMessage::Pong(_)
+                    | TungsteniteMessage::Frame(_) => {},
+                },
+                Some(Err(
+                    tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed,
+                ))
+                | None => {
+                    self.set_closed();
+                    return Poll::Ready(None);
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+impl Sink<Message> for TokioTransport {
+    type Error = tungstenite::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_connect(cx))?;
+        match &mut self.inner {
+            Inner::Open(stream) => Pin::new(stream).poll_ready(cx),
+            _ => Poll::Ready(Err(tungstenite::Error::AlreadyClosed)),
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        let item = match item {
+            Message::Text(text) => TungsteniteMessage::text(text),
+            Message::Bytes(bytes) => TungsteniteMessage::binary(bytes),
+        };
+        match &mut self.inner {
+            Inner::Open(stream) => Pin::new(stream).start_send(item),
+            _ => Err(tungstenite::Error::AlreadyClosed),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.inner {
+            Inner::Open(stream) | Inner::Closing(stream) => Pin::new(stream).poll_flush(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.inner {
+            Inner::Open(stream) | Inner::Closing(stream) => Pin::new(stream).poll_close(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
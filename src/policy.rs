@@ -0,0 +1,175 @@
+use std::{fmt::Debug, time::Duration};
+
+use exponential_backoff::Backoff;
+use gloo::net::websocket::events::CloseEvent;
+use rand::Rng;
+
+use crate::{MaybeSend, DEFAULT_BACKOFF_MAX, DEFAULT_BACKOFF_MIN, DEFAULT_MAX_RETRIES};
+
+/// What [`crate::Socket`] knows about the connection that just ended, passed to
+/// [`ReconnectPolicy::next_delay`]
+#[derive(Debug, Clone, Default)]
+pub struct ReconnectContext {
+    /// The number of reconnect attempts made since the connection was last stable. 0 when the
+    /// connection has just dropped for the first time
+    pub attempt: u32,
+    /// The close code and reason if the remote end closed the connection
+    pub close: Option<CloseEvent>,
+    /// The last error the connection produced (formatted with [`Debug`])
+    pub error: Option<String>,
+    /// How long the connection was open for. None if it never opened
+    pub connected_for: Option<Duration>,
+}
+
+/// The result of [`ReconnectPolicy::next_delay`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectDecision {
+    /// Reconnect after the given delay
+    RetryAfter(Duration),
+    /// Stop reconnecting. The [`crate::Socket`] stream ends
+    GiveUp,
+}
+
+/// Decides how long [`crate::Socket`] waits before reconnecting, or if it should give up
+///
+/// Called once each time the connection ends or fails to open. The builder's max retries are
+/// enforced separately so policies don't need to count attempts themselves. See [`MaybeSend`]
+pub trait ReconnectPolicy: Debug + MaybeSend {
+    /// Decide what to do after the connection described by `context` ended
+    fn next_delay(&mut self, context: &ReconnectContext) -> ReconnectDecision;
+}
+
+/// Exponential backoff with jitter using [`Backoff`]. This is the default policy
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    backoff: Backoff,
+}
+
+impl ExponentialBackoff {
+    /// Create an exponential backoff starting at `min` and capped at `max` that gives up after
+    /// `max_retries`. See [`Backoff::new`]
+    pub fn new(min: Duration, max: Option<Duration>, max_retries: u32) -> Self {
+        Self { backoff: Backoff::new(max_retries, min, max) }
+    }
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self::new(DEFAULT_BACKOFF_MIN, DEFAULT_BACKOFF_MAX, DEFAULT_MAX_RETRIES)
+    }
+}
+
+impl ReconnectPolicy for ExponentialBackoff {
+    fn next_delay(&mut self, context: &ReconnectContext) -> ReconnectDecision {
+        self.backoff
+            .next(context.attempt)
+            .map(ReconnectDecision::RetryAfter)
+            .unwrap_or(ReconnectDecision::GiveUp)
+    }
+}
+
+/// Always waits the same delay
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantBackoff {
+    delay: Duration,
+}
+
+impl ConstantBackoff {
+    /// Create a policy that always waits `delay`
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
+
+impl ReconnectPolicy for ConstantBackoff {
+    fn next_delay(&mut self, _: &ReconnectContext) -> ReconnectDecision {
+        ReconnectDecision::RetryAfter(self.delay)
+    }
+}
+
+/// Waits `initial + step * attempt`, capped at `max` if set
+#[derive(Debug, Clone, Copy)]
+pub struct LinearBackoff {
+    initial: Duration,
+    step: Duration,
+    max: Option<Duration>,
+}
+
+impl LinearBackoff {
+    /// Create a policy that starts at `initial` and grows by `step` each attempt
+    pub fn new(initial: Duration, step: Duration, max: Option<Duration>) -> Self {
+        Self { initial, step, max }
+    }
+}
+
+impl ReconnectPolicy for LinearBackoff {
+    fn next_delay(&mut self, context: &ReconnectContext) -> ReconnectDecision {
+        let delay = self.initial.saturating_add(self.step.saturating_mul(context.attempt));
+        ReconnectDecision::RetryAfter(cap(delay, self.max))
+    }
+}
+
+/// Waits `unit` multiplied by the fibonacci sequence (1, 1, 2, 3, 5, ...), capped at `max` if
+/// set
+#[derive(Debug, Clone, Copy)]
+pub struct FibonacciBackoff {
+    unit: Duration,
+    max: Option<Duration>,
+}
+
+impl FibonacciBackoff {
+    /// Create a policy that waits multiples of `unit`
+    pub fn new(unit: Duration, max: Option<Duration>) -> Self {
+        Self { unit, max }
+    }
+}
+
+impl ReconnectPolicy for FibonacciBackoff {
+    fn next_delay(&mut self, context: &ReconnectContext) -> ReconnectDecision {
+        let (mut a, mut b) = (1u32, 1u32);
+        for _ in 0..context.attempt {
+            (a, b) = (b, a.saturating_add(b));
+        }
+        ReconnectDecision::RetryAfter(cap(self.unit.saturating_mul(a), self.max))
+    }
+}
+
+/// "Decorrelated jitter" from the AWS architecture blog post [Exponential Backoff And Jitter](https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/)
+///
+/// Each delay is random between `base` and 3 times the previous delay, capped at `max`. Spreads
+/// reconnects from many clients out better than plain exponential backoff
+#[derive(Debug, Clone, Copy)]
+pub struct DecorrelatedJitterBackoff {
+    base: Duration,
+    max: Duration,
+    previous: Duration,
+}
+
+impl DecorrelatedJitterBackoff {
+    /// Create a policy with delays between `base` and `max`
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max, previous: base }
+    }
+}
+
+impl ReconnectPolicy for DecorrelatedJitterBackoff {
+    fn next_delay(&mut self, context: &ReconnectContext) -> ReconnectDecision {
+        if context.attempt == 0 {
+            self.previous = self.base;
+        }
+
+        let upper = self.previous.saturating_mul(3).max(self.base);
+        let delay = if upper > self.base {
+            rand::thread_rng().gen_range(self.base..=upper)
+        } else {
+            self.base
+        };
+
+        self.previous = delay.min(self.max);
+        ReconnectDecision::RetryAfter(self.previous)
+    }
+}
+
+fn cap(delay: Duration, max: Option<Duration>) -> Duration {
+    max.map(|max| delay.min(max)).unwrap_or(delay)
+}
@@ -0,0 +1,646 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures::{future::poll_fn, Sink, Stream};
+use gloo::net::websocket::Message;
+
+use crate::{trace, warn, MaybeSend, MaybeSync, SocketInput, DEFAULT_STARVATION_LIMIT};
+
+/// What happens when a message is sent while the outbound queue is full
+///
+/// The queue fills up while the socket is disconnected. Set with
+/// [`crate::SocketBuilder::set_overflow_policy`]. With the `state-events` feature messages that are
+/// dropped are reported as `Event::Dropped`, without it they can be taken with
+/// `Socket::take_dropped` or `SocketSink::take_dropped`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for space. [`crate::Socket::send`] and [`SocketSink`] wait and
+    /// [`crate::Socket::try_send`] returns [`SendError::Full`]
+    #[default]
+    Block,
+    /// Reject the new message, returning it in [`SendError::Full`]
+    RejectNew,
+    /// Drop the oldest queued message to make space for the new one
+    DropOldest,
+    /// Drop every queued message that has been waiting longer than this to make space. If none
+    /// have, wait for space like [`OverflowPolicy::Block`]. Messages that have been waiting longer
+    /// than this are also dropped instead of being sent when the socket reconnects
+    DropOlderThan(Duration),
+}
+
+/// Priority lanes config for the outbound queue
+///
+/// Each lane is a separate queue with the configured capacity and [`OverflowPolicy`]. Lane 0 has
+/// the highest priority and the lanes are drained in order, so urgent messages don't wait behind a
+/// backlog of less important ones. A lane that has been passed over `starvation_limit` times in a
+/// row while it had messages waiting gets the next turn
+///
+/// [`crate::Socket::send`], [`crate::Socket::try_send`] and [`crate::Socket::get_sink`] use the
+/// last, lowest priority, lane. [`crate::Socket::get_lane_sink`] gets the others. Set with
+/// [`crate::SocketBuilder::set_priority_lanes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityLanes {
+    pub(crate) count: usize,
+    pub(crate) starvation_limit: usize,
+}
+
+impl PriorityLanes {
+    /// Create a config with `count` lanes (must be > 0). Uses [`DEFAULT_STARVATION_LIMIT`]
+    pub fn new(count: usize) -> Self {
+        Self { count, starvation_limit: DEFAULT_STARVATION_LIMIT }
+    }
+
+    /// Update how many times in a row a lane with messages waiting can be passed over for higher
+    /// priority lanes before it gets a turn (must be > 0)
+    pub fn set_starvation_limit(mut self, starvation_limit: usize) -> Self {
+        self.starvation_limit = starvation_limit;
+        self
+    }
+}
+
+impl Default for PriorityLanes {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// Extracts the coalescing key from an outbound message. A queued message with the same key is
+/// replaced by the new one, even when the queue is full. Messages it returns None for are always
+/// queued. See [`MaybeSend`] and [`MaybeSync`]
+///
+/// Set with [`crate::SocketBuilder::set_coalesce_key`]
+pub trait CoalesceKey<I>: Fn(&I) -> Option<String> + MaybeSend + MaybeSync {}
+impl<I, F: Fn(&I) -> Option<String> + MaybeSend + MaybeSync> CoalesceKey<I> for F {}
+
+impl<I> Debug for dyn CoalesceKey<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CoalesceKey")
+    }
+}
+
+/// A message that couldn't be queued for sending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError<I> {
+    /// The outbound queue is full. See [`OverflowPolicy`]
+    Full(I),
+    /// The socket has been closed
+    Closed(I),
+}
+
+impl<I> SendError<I> {
+    /// True if the queue was full
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// True if the socket has been closed
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+
+    /// Get back the message that wasn't queued
+    pub fn into_inner(self) -> I {
+        match self {
+            Self::Full(message) | Self::Closed(message) => message,
+        }
+    }
+}
+
+impl<I> Display for SendError<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("outbound queue is full"),
+            Self::Closed(_) => f.write_str("socket is closed"),
+        }
+    }
+}
+
+impl<I: Debug> std::error::Error for SendError<I> {}
+
+/// The time source the queue stamps messages with. See [`crate::Timer::now`]
+pub(crate) trait Clock: Fn() -> Duration + MaybeSend {}
+impl<F: Fn() -> Duration + MaybeSend> Clock for F {}
+
+struct Queued<I> {
+    message: I,
+    /// [`Clock`] time when it was queued
+    queued_at: Duration,
+    /// [`Clock`] time after which it's dropped instead of sent
+    expires_at: Option<Duration>,
+    /// The [`CoalesceKey`]
+    key: Option<String>,
+}
+
+struct Shared<I> {
+    messages: VecDeque<Queued<I>>,
+    /// The most recent `capacity` dropped messages waiting to be reported by the socket, or
+    /// without `state-events` waiting to be taken
+    dropped: VecDeque<I>,
+    /// How many messages have been dropped in total
+    dropped_count: u64,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    coalesce: Option<Arc<dyn CoalesceKey<I>>>,
+    closed: bool,
+    clock: Box<dyn Clock>,
+    /// The socket, waiting for messages to send or dropped messages to report
+    receiver: Option<Waker>,
+    /// Senders waiting for space, one waker for each
+    senders: Vec<Waker>,
+}
+
+impl<I> Shared<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    fn is_full(&self) -> bool {
+        self.messages.len() >= self.capacity
+    }
+
+    fn drop_message(&mut self, message: I) {
+        warn!("Dropping queued message: {message:?}");
+        self.dropped_count += 1;
+        // Nothing drains them unless the socket is polled or they're taken
+        if self.dropped.len() >= self.capacity {
+            self.dropped.pop_front();
+        }
+        self.dropped.push_back(message);
+        self.wake_receiver();
+    }
+
+    /// Drop messages that have been queued longer than the [`OverflowPolicy::DropOlderThan`] ttl.
+    /// Returns true if any were dropped
+    fn drop_expired(&mut self) -> bool {
+        let OverflowPolicy::DropOlderThan(ttl) = self.overflow else {
+            return false;
+        };
+
+        let now = (self.clock)();
+        let mut dropped = false;
+        // Messages are queued in order so the oldest are at the front
+        while self.messages.front().is_some_and(|q| now.saturating_sub(q.queued_at) >= ttl) {
+            if let Some(queued) = self.messages.pop_front() {
+                self.drop_message(queued.message);
+                dropped = true;
+            }
+        }
+        dropped
+    }
+
+    /// Queue `message`, applying the overflow policy if the queue is full. `reserved` is true if
+    /// the sender has already waited for space with [`Sink::poll_ready`]. It expires `ttl` after
+    /// it's queued if there is one
+    fn push(
+        &mut self,
+        message: I,
+        reserved: bool,
+        ttl: Option<Duration>,
+    ) -> Result<(), SendError<I>> {
+        if self.closed {
+            return Err(SendError::Closed(message));
+        }
+
+        // The message replacing a queued one with the same key goes to the back so the queue
+        // stays in the order messages were queued. It doesn't need any more space
+        let key = self.key(&message);
+        let replaced = self.replaces(key.as_deref()).and_then(|index| self.messages.remove(index));
+        if let Some(_replaced) = replaced {
+            trace!("Replacing queued message: {:?}", _replaced.message);
+        } else if self.is_full() {
+            match self.overflow {
+                OverflowPolicy::RejectNew => return Err(SendError::Full(message)),
+                OverflowPolicy::DropOldest => {
+                    if let Some(queued) = self.messages.pop_front() {
+                        self.drop_message(queued.message);
+                    }
+                },
+                OverflowPolicy::Block | OverflowPolicy::DropOlderThan(_) => {
+                    self.drop_expired();
+                    // Another sender could have taken the space between poll_ready and
+                    // start_send. Going over the capacity slightly is better than losing the
+                    // message
+                    if self.is_full() && !reserved {
+                        return Err(SendError::Full(message));
+                    }
+                },
+            }
+        }
+
+        let queued_at = (self.clock)();
+        // A ttl too long to represent never expires
+        let expires_at = ttl.and_then(|ttl| queued_at.checked_add(ttl));
+        self.messages.push_back(Queued { message, queued_at, expires_at, key });
+        self.wake_receiver();
+        Ok(())
+    }
+
+    /// The [`CoalesceKey`] of `message`
+    fn key(&self, message: &I) -> Option<String> {
+        self.coalesce.as_ref().and_then(|coalesce| coalesce(message))
+    }
+
+    /// The index of the queued message with `key`
+    fn replaces(&self, key: Option<&str>) -> Option<usize> {
+        let key = key?;
+        self.messages.iter().position(|q| q.key.as_deref() == Some(key))
+    }
+
+    /// Ready when `message` can be pushed without waiting. Without the message, as in
+    /// [`Sink::poll_ready`], it waits for space even if the message would replace a queued one
+    fn poll_space(&mut self, message: Option<&I>, cx: &mut Context<'_>) -> Poll<()> {
+        // Closed errors and overflow policies that don't wait are handled by push
+        if self.closed
+            || matches!(self.overflow, OverflowPolicy::RejectNew | OverflowPolicy::DropOldest)
+        {
+            return Poll::Ready(());
+        }
+
+        // Replacing a queued message doesn't need any more space
+        if message.is_some_and(|message| self.replaces(self.key(message).as_deref()).is_some()) {
+            return Poll::Ready(());
+        }
+
+        self.drop_expired();
+        if self.is_full() {
+            // A sender polled again before it's woken is already waiting
+            if !self.senders.iter().any(|waker| waker.will_wake(cx.waker())) {
+                self.senders.push(cx.waker().clone());
+            }
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    fn wake_receiver(&mut self) {
+        if let Some(waker) = self.receiver.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_senders(&mut self) {
+        for waker in self.senders.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.wake_receiver();
+        self.wake_senders();
+    }
+}
+
+fn lock<I>(shared: &Mutex<Shared<I>>) -> MutexGuard<'_, Shared<I>> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Create the outbound queue with a lane for each priority. Returns the sink for the lowest
+/// priority lane
+pub(crate) fn lanes<I>(
+    lanes: PriorityLanes,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    coalesce: Option<Arc<dyn CoalesceKey<I>>>,
+    clock: impl Clock + Clone + 'static,
+) -> (SocketSink<I>, Lanes<I>) {
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..lanes.count)
+        .map(|_| queue(capacity, overflow, coalesce.clone(), clock.clone()))
+        .unzip();
+    let lanes = Lanes {
+        skipped: vec![0; receivers.len()],
+        senders,
+        receivers,
+        starvation_limit: lanes.starvation_limit,
+    };
+    // The builder checks there's at least one lane
+    let sender = lanes.senders.last().expect("at least one lane").clone();
+    (sender, lanes)
+}
+
+/// Create a single outbound queue
+fn queue<I>(
+    capacity: usize,
+    overflow: OverflowPolicy,
+    coalesce: Option<Arc<dyn CoalesceKey<I>>>,
+    clock: impl Clock + 'static,
+) -> (SocketSink<I>, QueueReceiver<I>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        messages: VecDeque::new(),
+        dropped: VecDeque::new(),
+        dropped_count: 0,
+        capacity,
+        overflow,
+        coalesce,
+        closed: false,
+        clock: Box::new(clock),
+        receiver: None,
+        senders: Vec::new(),
+    }));
+    (SocketSink { shared: shared.clone() }, QueueReceiver { shared })
+}
+
+/// A handle that implements [`Sink`] for sending messages from the client to the server
+///
+/// Cheap and safe to clone (internally it's a handle to the socket's outbound queue).
+/// [`Sink::poll_ready`] returns [`Poll::Pending`] while the queue is full and the
+/// [`OverflowPolicy`] is to wait for space. It can't see the message so it waits even if the
+/// message would replace a queued one with the same [`CoalesceKey`].
+/// [`SocketSink::send_with_ttl`] and [`crate::Socket::send`] don't
+pub struct SocketSink<I> {
+    shared: Arc<Mutex<Shared<I>>>,
+}
+
+impl<I> Clone for SocketSink<I> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<I> Debug for SocketSink<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shared = lock(&self.shared);
+        f.debug_struct("SocketSink")
+            .field("queued", &shared.messages.len())
+            .field("capacity", &shared.capacity)
+            .field("overflow", &shared.overflow)
+            .field("closed", &shared.closed)
+            .finish()
+    }
+}
+
+impl<I> SocketSink<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    /// Queue `message` without waiting for space
+    pub(crate) fn try_send(&self, message: I) -> Result<(), SendError<I>> {
+        lock(&self.shared).push(message, false, None)
+    }
+
+    /// How many messages have been dropped from this sink's queue without being sent, by the
+    /// [`OverflowPolicy`] or because their ttl expired
+    pub fn dropped_count(&self) -> u64 {
+        lock(&self.shared).dropped_count
+    }
+
+    /// Take the messages dropped from this sink's queue without being sent, oldest first. Only the
+    /// most recent `capacity` are kept
+    ///
+    /// With `state-events` they're reported as `Event::Dropped` instead
+    #[cfg(not(feature = "state-events"))]
+    pub fn take_dropped(&self) -> Vec<I> {
+        lock(&self.shared).dropped.drain(..).collect()
+    }
+
+    /// Queue `message` for sending, waiting for space like [`futures::SinkExt::send`]
+    ///
+    /// If it's still queued `ttl` after it was queued, because the socket was disconnected or
+    /// busy, it's dropped instead of being sent. With `state-events` that's reported as
+    /// `Event::Dropped`, without it see `Self::take_dropped`
+    pub async fn send_with_ttl(&mut self, message: I, ttl: Duration) -> Result<(), SendError<I>> {
+        self.queue(message, Some(ttl)).await
+    }
+
+    /// Queue `message`, waiting for space unless it replaces a queued message with the same
+    /// [`CoalesceKey`]. It expires `ttl` after it's queued if there is one
+    pub(crate) async fn queue(
+        &self,
+        message: I,
+        ttl: Option<Duration>,
+    ) -> Result<(), SendError<I>> {
+        poll_fn(|cx| lock(&self.shared).poll_space(Some(&message), cx)).await;
+        lock(&self.shared).push(message, true, ttl)
+    }
+}
+
+impl<I> Sink<I> for SocketSink<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    type Error = SendError<I>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        lock(&self.shared).poll_space(None, cx).map(Ok)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: I) -> Result<(), Self::Error> {
+        lock(&self.shared).push(msg, true, None)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        lock(&self.shared).close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The socket's end of the outbound queue
+pub(crate) struct QueueReceiver<I> {
+    shared: Arc<Mutex<Shared<I>>>,
+}
+
+impl<I> Debug for QueueReceiver<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shared = lock(&self.shared);
+        f.debug_struct("QueueReceiver")
+            .field("queued", &shared.messages.len())
+            .field("dropped", &shared.dropped.len())
+            .finish()
+    }
+}
+
+impl<I> QueueReceiver<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    /// The next dropped message that hasn't been reported yet
+    #[cfg_attr(not(feature = "state-events"), allow(dead_code))]
+    pub(crate) fn poll_dropped(&mut self, cx: &mut Context<'_>) -> Poll<I> {
+        let mut shared = lock(&self.shared);
+        match shared.dropped.pop_front() {
+            Some(message) => Poll::Ready(message),
+            None => {
+                shared.receiver = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+
+    /// Stop accepting messages. Waiting senders are woken and get [`SendError::Closed`]
+    pub(crate) fn close(&mut self) {
+        lock(&self.shared).close();
+    }
+}
+
+impl<I> QueueReceiver<I> {
+    fn is_empty(&self) -> bool {
+        lock(&self.shared).messages.is_empty()
+    }
+
+    /// Closed and there's nothing left to send
+    fn is_done(&self) -> bool {
+        let shared = lock(&self.shared);
+        shared.closed && shared.messages.is_empty()
+    }
+}
+
+impl<I> Stream for QueueReceiver<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    type Item = I;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        let mut shared = lock(&self.shared);
+        let mut dropped = shared.drop_expired();
+
+        // Messages past their own ttl are dropped as they come off the queue
+        let now = (shared.clock)();
+        while shared.messages.front().is_some_and(|q| q.expires_at.is_some_and(|at| now >= at)) {
+            if let Some(queued) = shared.messages.pop_front() {
+                shared.drop_message(queued.message);
+                dropped = true;
+            }
+        }
+
+        match shared.messages.pop_front() {
+            Some(queued) => {
+                shared.wake_senders();
+                Poll::Ready(Some(queued.message))
+            },
+            None if shared.closed => Poll::Ready(None),
+            None if dropped => {
+                shared.wake_senders();
+                shared.receiver = Some(cx.waker().clone());
+                Poll::Pending
+            },
+            None => {
+                shared.receiver = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<I> Drop for QueueReceiver<I> {
+    fn drop(&mut self) {
+        let mut shared = lock(&self.shared);
+        shared.closed = true;
+        for waker in shared.senders.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// The socket's end of the outbound queue. Drains the priority lanes in order
+pub(crate) struct Lanes<I> {
+    senders: Vec<SocketSink<I>>,
+    receivers: Vec<QueueReceiver<I>>,
+    /// How many times in a row each lane has been passed over while it had messages waiting
+    skipped: Vec<usize>,
+    starvation_limit: usize,
+}
+
+impl<I> Debug for Lanes<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lanes")
+            .field("receivers", &self.receivers)
+            .field("skipped", &self.skipped)
+            .field("starvation_limit", &self.starvation_limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I> Lanes<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    /// The sink for `lane`. None if there isn't one
+    pub(crate) fn sink(&self, lane: usize) -> Option<SocketSink<I>> {
+        self.senders.get(lane).cloned()
+    }
+
+    /// The next dropped message from any lane that hasn't been reported yet
+    #[cfg_attr(not(feature = "state-events"), allow(dead_code))]
+    pub(crate) fn poll_dropped(&mut self, cx: &mut Context<'_>) -> Poll<I> {
+        for receiver in self.receivers.iter_mut() {
+            if let ready @ Poll::Ready(_) = receiver.poll_dropped(cx) {
+                return ready;
+            }
+        }
+        Poll::Pending
+    }
+
+    /// How many messages have been dropped from every lane
+    pub(crate) fn dropped_count(&self) -> u64 {
+        self.senders.iter().map(SocketSink::dropped_count).sum()
+    }
+
+    /// Take the dropped messages from every lane, highest priority lane first
+    #[cfg(not(feature = "state-events"))]
+    pub(crate) fn take_dropped(&self) -> Vec<I> {
+        self.senders.iter().flat_map(SocketSink::take_dropped).collect()
+    }
+
+    /// Stop accepting messages on every lane
+    pub(crate) fn close(&mut self) {
+        for receiver in self.receivers.iter_mut() {
+            receiver.close();
+        }
+    }
+}
+
+impl<I> Stream for Lanes<I>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+{
+    type Item = I;
+
+    /// Ends once every lane is closed and empty
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        let this = &mut *self;
+        let starving = this.skipped.iter().position(|skipped| *skipped >= this.starvation_limit);
+
+        for lane in starving.into_iter().chain(0..this.receivers.len()) {
+            if let Poll::Ready(Some(message)) = Pin::new(&mut this.receivers[lane]).poll_next(cx) {
+                this.skipped[lane] = 0;
+                for (other, receiver) in this.receivers.iter().enumerate() {
+                    if other != lane && !receiver.is_empty() {
+                        this.skipped[other] += 1;
+                    }
+                }
+                return Poll::Ready(Some(message));
+            }
+        }
+
+        if this.receivers.iter().all(QueueReceiver::is_done) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
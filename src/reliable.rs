@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug},
+};
+
+use gloo::net::websocket::Message;
+
+use crate::{trace, MaybeSend, DEFAULT_RETRANSMIT_CAPACITY};
+
+/// What an acknowledgement from the server covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ack {
+    /// Every message up to and including this sequence id
+    Through(u64),
+    /// Only the message with this sequence id
+    Only(u64),
+}
+
+/// Adds sequence ids to outbound messages and recognises the server's acknowledgements for
+/// [`Reliable`] delivery. See [`MaybeSend`]
+pub trait Envelope: MaybeSend {
+    /// Wrap an outbound `message` with its sequence id. Sequence ids start at 1 and increase by
+    /// one for each message
+    fn wrap(&mut self, seq: u64, message: Message) -> Message;
+
+    /// If `message` is an acknowledgement, what it acknowledges. Acknowledgements are not passed
+    /// on to the [`crate::Socket`] stream
+    fn ack(&mut self, message: &Message) -> Option<Ack>;
+}
+
+/// At-least-once delivery config for outbound messages
+///
+/// Each message taken from the outbound queue is wrapped by the [`Envelope`] with a sequence id
+/// and kept in a retransmit buffer until the server acknowledges it. After each reconnect the
+/// unacknowledged messages are resent, in order, before anything else in the queue. The server
+/// can see the same message more than once and should use the sequence id to ignore duplicates
+///
+/// While the buffer is full no more messages are taken from the outbound queue, so it fills up and
+/// the [`crate::OverflowPolicy`] applies
+///
+/// Set with [`crate::SocketBuilder::set_reliable`]
+pub struct Reliable {
+    pub(crate) envelope: Box<dyn Envelope>,
+    pub(crate) capacity: usize,
+}
+
+impl Reliable {
+    /// Create a config that wraps messages with `envelope`. Uses [`DEFAULT_RETRANSMIT_CAPACITY`]
+    pub fn new(envelope: impl Envelope + 'static) -> Self {
+        Self { envelope: Box::new(envelope), capacity: DEFAULT_RETRANSMIT_CAPACITY }
+    }
+
+    /// Update how many unacknowledged messages can be kept for resending (must be > 0)
+    pub fn set_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+}
+
+impl Debug for Reliable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reliable").field("capacity", &self.capacity).finish_non_exhaustive()
+    }
+}
+
+/// Sent messages waiting to be acknowledged
+pub(crate) struct RetransmitBuffer {
+    envelope: Box<dyn Envelope>,
+    capacity: usize,
+    next_seq: u64,
+    /// Wrapped messages in sequence order
+    unacked: VecDeque<(u64, Message)>,
+    /// How many of the oldest unacked messages have been sent on the current connection. The rest
+    /// are waiting to be resent
+    sent: usize,
+}
+
+impl RetransmitBuffer {
+    pub(crate) fn new(reliable: Reliable) -> Self {
+        let Reliable { envelope, capacity } = reliable;
+        Self { envelope, capacity, next_seq: 1, unacked: VecDeque::new(), sent: 0 }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.unacked.len()
+    }
+
+    /// True if no more messages can be sent until some are acknowledged
+    pub(crate) fn is_full(&self) -> bool {
+        self.unacked.len() >= self.capacity
+    }
+
+    /// Resend everything unacknowledged on the new connection
+    pub(crate) fn reconnected(&mut self) {
+        self.sent = 0;
+    }
+
+    /// The next unacknowledged message that hasn't been sent on the current connection
+    pub(crate) fn next_resend(&mut self) -> Option<Message> {
+        let (_, message) = self.unacked.get(self.sent)?;
+        self.sent += 1;
+        Some(message.clone())
+    }
+
+    /// Give `message` the next sequence id and keep it until it's acknowledged. Only call when
+    /// [`Self::next_resend`] returns None
+    pub(crate) fn wrap(&mut self, message: Message) -> Message {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let message = self.envelope.wrap(seq, message);
+        self.unacked.push_back((seq, message.clone()));
+        self.sent += 1;
+        message
+    }
+
+    /// Drop the messages `message` acknowledges. Returns false if it isn't an acknowledgement
+    pub(crate) fn ack(&mut self, message: &Message) -> bool {
+        let Some(ack) = self.envelope.ack(message) else {
+            return false;
+        };
+
+        let mut index = 0;
+        let mut removed_sent = 0;
+        self.unacked.retain(|(seq, _)| {
+            let keep = match ack {
+                Ack::Through(through) => *seq > through,
+                Ack::Only(only) => *seq != only,
+            };
+            // Removing one that has been sent on this connection moves the rest down
+            if !keep && index < self.sent {
+                removed_sent += 1;
+            }
+            index += 1;
+            keep
+        });
+        self.sent -= removed_sent;
+        trace!("{ack:?}, {} still unacknowledged", self.unacked.len());
+        true
+    }
+}
+
+impl Debug for RetransmitBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetransmitBuffer")
+            .field("capacity", &self.capacity)
+            .field("next_seq", &self.next_seq)
+            .field("unacked", &self.unacked.len())
+            .field("sent", &self.sent)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,616 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use futures::{
+    channel::oneshot,
+    ready,
+    stream::{FusedStream, Stream},
+    FutureExt, Sink, StreamExt,
+};
+use gloo::net::websocket::Message;
+
+use crate::{
+    debug, trace, Connector, Error, Event, GlooConnector, GlooTimer, MaybeSend, SendError, Socket,
+    SocketInput, SocketOutput, SocketSink, State, Timer, Transport, DEFAULT_RPC_TIMEOUT,
+};
+
+/// Finds the correlation ids that match responses to requests for [`RpcClient`]. See
+/// [`MaybeSend`]
+pub trait Correlation<I, O>: MaybeSend {
+    /// The id of an outbound request. None if it doesn't have one, which fails the call with
+    /// [`RpcError::MissingId`]
+    fn request_id(&self, request: &I) -> Option<String>;
+
+    /// The id of the request an inbound message is the response to. None if it isn't a response,
+    /// in which case it's passed on to the [`RpcClient`] stream
+    fn response_id(&self, response: &O) -> Option<String>;
+}
+
+/// Finds the correlation id of an outbound request for the socket so it can say which requests
+/// it has written. See [`MaybeSend`]
+pub(crate) trait RequestId<I>: Fn(&I) -> Option<String> + MaybeSend {}
+impl<I, F: Fn(&I) -> Option<String> + MaybeSend> RequestId<I> for F {}
+
+/// Errors returned by [`RpcCall`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RpcError {
+    /// No response within the call timeout. See [`RpcCall::set_timeout`]
+    #[error("Timeout: no response after {0:?}")]
+    Timeout(Duration),
+
+    /// The connection dropped after the request was written to it and before the response
+    /// arrived. The request may or may not have reached the server. Calls made with
+    /// [`RpcCall::set_idempotent`] are sent again instead
+    #[error("Disconnected: the connection dropped before the response arrived")]
+    Disconnected,
+
+    /// The socket stream has ended or the [`RpcClient`] was dropped
+    #[error("Closed: the socket is closed")]
+    Closed,
+
+    /// The outbound queue was full. See [`crate::OverflowPolicy`]
+    #[error("QueueFull: the outbound queue is full")]
+    QueueFull,
+
+    /// [`Correlation::request_id`] didn't find an id in the request
+    #[error("MissingId: no correlation id in the request")]
+    MissingId,
+
+    /// A call with the same correlation id is already waiting for a response
+    #[error("DuplicateId: a call with id {0} is already pending")]
+    DuplicateId(String),
+}
+
+impl<I> From<SendError<I>> for RpcError {
+    fn from(err: SendError<I>) -> Self {
+        match err {
+            SendError::Full(_) => Self::QueueFull,
+            SendError::Closed(_) => Self::Closed,
+        }
+    }
+}
+
+/// A copy of an idempotent request kept for sending again after a reconnect
+struct Reissue<I> {
+    request: I,
+    /// [`Clone::clone`] for `I`. A fn pointer so `I` only has to be [`Clone`] for idempotent calls
+    clone: fn(&I) -> I,
+}
+
+struct PendingCall<I, O> {
+    response: oneshot::Sender<Result<O, RpcError>>,
+    reissue: Option<Reissue<I>>,
+    /// Set once the request has been written to the connection
+    written: bool,
+    /// Set when the connection dropped and the request needs sending again once it's open
+    reissue_due: bool,
+}
+
+/// State shared between the [`RpcClient`], its handles and the calls
+struct Calls<I, O> {
+    /// Shared with the socket, which finds the ids of the requests it writes
+    correlation: Arc<Mutex<Box<dyn Correlation<I, O>>>>,
+    pending: HashMap<String, PendingCall<I, O>>,
+    timeout: Duration,
+    closed: bool,
+}
+
+impl<I, O> Calls<I, O> {
+    /// Complete the call `message` is the response to. Returns the message if it isn't the
+    /// response to a pending call
+    fn respond(&mut self, message: O) -> Option<O> {
+        let Some(id) = lock(&self.correlation).response_id(&message) else {
+            return Some(message);
+        };
+
+        match self.pending.remove(&id) {
+            Some(call) => {
+                trace!("response to call {id}");
+                // The call could have been dropped since the response arrived
+                let _ = call.response.send(Ok(message));
+                None
+            },
+            None => {
+                debug!("response to call {id} that isn't pending");
+                Some(message)
+            },
+        }
+    }
+
+    /// Complete the call with `message` if it's the only one whose request was written and whose
+    /// id `matches`. Returns the message if there isn't exactly one
+    #[cfg(feature = "jsonrpc")]
+    fn respond_only(&mut self, matches: impl Fn(&str) -> bool, message: O) -> Option<O> {
+        let mut ids = self.pending.iter().filter(|(id, call)| call.written && matches(id));
+        let (Some((id, _)), None) = (ids.next(), ids.next()) else {
+            return Some(message);
+        };
+
+        let id = id.clone();
+        trace!("response to call {id}");
+        if let Some(call) = self.pending.remove(&id) {
+            let _ = call.response.send(Ok(message));
+        }
+        None
+    }
+
+    /// The socket has written the requests with `ids` to the connection
+    fn written(&mut self, ids: impl IntoIterator<Item = String>) {
+        for id in ids {
+            if let Some(call) = self.pending.get_mut(&id) {
+                trace!("call {id} written");
+                call.written = true;
+            }
+        }
+    }
+
+    /// The connection dropped. Fail the calls whose request was written to it unless they can be
+    /// sent again. Requests that haven't been written yet are still queued and go out on the next
+    /// connection
+    fn disconnected(&mut self) {
+        let mut failed = Vec::new();
+        for (id, call) in self.pending.iter_mut().filter(|(_, call)| call.written) {
+            call.written = false;
+            call.reissue_due = call.reissue.is_some();
+            if !call.reissue_due {
+                failed.push(id.clone());
+            }
+        }
+        for id in failed {
+            if let Some(call) = self.pending.remove(&id) {
+                debug!("call {id} failed, disconnected");
+                let _ = call.response.send(Err(RpcError::Disconnected));
+            }
+        }
+    }
+
+    /// The socket has reconnected. Send the idempotent calls again
+    fn reconnected(&mut self, sink: &SocketSink<I>)
+    where
+        I: SocketInput,
+        Message: TryFrom<I>,
+        <Message as TryFrom<I>>::Error: Debug,
+    {
+        let mut failed = Vec::new();
+        for (id, call) in self.pending.iter_mut().filter(|(_, call)| call.reissue_due) {
+            let Some(reissue) = call.reissue.as_ref() else {
+                continue;
+            };
+            debug!("reissuing call {id}");
+            call.reissue_due = false;
+            if let Err(e) = sink.try_send((reissue.clone)(&reissue.request)) {
+                failed.push((id.clone(), RpcError::from(e)));
+            }
+        }
+
+        for (id, e) in failed {
+            if let Some(call) = self.pending.remove(&id) {
+                let _ = call.response.send(Err(e));
+            }
+        }
+    }
+
+    /// Fail every pending call and any made from now on
+    fn close(&mut self) {
+        self.closed = true;
+        for (_, call) in self.pending.drain() {
+            let _ = call.response.send(Err(RpcError::Closed));
+        }
+    }
+}
+
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Request/response calls over a [`Socket`]
+///
+/// Wraps the socket's [`Stream`], which still has to be polled for calls to make progress.
+/// Responses are matched to calls by the ids the [`Correlation`] finds and everything else is
+/// passed on to the stream. Calls are made with [`Self::call`] or from other tasks with a
+/// [`RpcHandle`]
+///
+/// When the connection drops, calls whose request was written to it fail with
+/// [`RpcError::Disconnected`] unless they were made with [`RpcCall::set_idempotent`], in which case
+/// they're sent again after the socket reconnects. Requests still queued are sent on the next
+/// connection as usual
+pub struct RpcClient<I, O, C = GlooConnector, T = GlooTimer>
+where
+    C: Connector,
+    T: Timer,
+{
+    socket: Socket<I, O, C, T>,
+    calls: Arc<Mutex<Calls<I, O>>>,
+    /// If the socket was open the last time it was polled
+    open: bool,
+    /// The socket's connection count the last time it was polled
+    connection: u64,
+}
+
+impl<I, O, C, T> RpcClient<I, O, C, T>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
+{
+    /// Make calls over `socket`, matching responses to them with `correlation`. Calls time out
+    /// after [`DEFAULT_RPC_TIMEOUT`]
+    pub fn new(
+        mut socket: Socket<I, O, C, T>,
+        correlation: impl Correlation<I, O> + 'static,
+    ) -> Self
+    where
+        I: 'static,
+        O: 'static,
+    {
+        let correlation: Arc<Mutex<Box<dyn Correlation<I, O>>>> =
+            Arc::new(Mutex::new(Box::new(correlation)));
+        let request_correlation = correlation.clone();
+        socket.request_id =
+            Some(Box::new(move |request: &I| lock(&request_correlation).request_id(request)));
+        let calls = Calls {
+            correlation,
+            pending: HashMap::new(),
+            timeout: DEFAULT_RPC_TIMEOUT,
+            closed: false,
+        };
+        Self {
+            open: socket.state == State::Open,
+            connection: socket.connection,
+            socket,
+            calls: Arc::new(Mutex::new(calls)),
+        }
+    }
+
+    /// Update how long calls wait for a response unless set for the call with
+    /// [`RpcCall::set_timeout`]
+    pub fn set_timeout(self, timeout: Duration) -> Self {
+        lock(&self.calls).timeout = timeout;
+        self
+    }
+
+    /// Send `request` and wait for the response. See [`RpcCall`]
+    pub fn call(&self, request: I) -> RpcCall<I, O, T> {
+        self.handle().call(request)
+    }
+
+    /// Get a handle for making calls from other tasks
+    pub fn handle(&self) -> RpcHandle<I, O, T> {
+        RpcHandle {
+            calls: self.calls.clone(),
+            sink: self.socket.get_sink(),
+            timer: self.socket.timer.clone(),
+        }
+    }
+
+    /// The wrapped socket
+    pub fn socket(&self) -> &Socket<I, O, C, T> {
+        &self.socket
+    }
+
+    /// The wrapped socket, for sending messages that aren't calls or controlling reconnects
+    pub fn socket_mut(&mut self) -> &mut Socket<I, O, C, T> {
+        &mut self.socket
+    }
+
+    /// Note the requests the socket has written, then fail calls or send them again when the
+    /// connection drops and reopens. Returns true if the socket has just reopened
+    ///
+    /// The socket can drop the connection and reconnect within one poll so drops are noticed by
+    /// the connection count rather than the state
+    fn track_connection(&mut self) -> bool {
+        let mut calls = lock(&self.calls);
+        let connection = self.socket.connection;
+        let (current, dropped): (Vec<_>, Vec<_>) =
+            self.socket.written_ids.drain(..).partition(|(c, _)| *c == connection);
+
+        calls.written(dropped.into_iter().map(|(_, id)| id));
+        let reconnected = connection != self.connection;
+        if reconnected {
+            self.connection = connection;
+            calls.disconnected();
+        }
+        calls.written(current.into_iter().map(|(_, id)| id));
+
+        let open = self.socket.state == State::Open;
+        let reopened = open && (reconnected || !self.open);
+        self.open = open;
+        if reopened {
+            calls.reconnected(&self.socket.sink_sender);
+        }
+        reopened
+    }
+
+    /// Complete the call `event` is the response to. Returns the event if it isn't a response
+    fn respond(&self, event: Event<I, O>) -> Option<Event<I, O>> {
+        cfg_if! {
+            if #[cfg(feature = "state-events")] {
+                let Event::Message(Ok(message)) = event else {
+                    return Some(event);
+                };
+                lock(&self.calls).respond(message).map(|message| Event::Message(Ok(message)))
+            } else {
+                let Ok(message) = event else {
+                    return Some(event);
+                };
+                lock(&self.calls).respond(message).map(Ok)
+            }
+        }
+    }
+
+    /// Complete the call with `message` if it's the only one whose request has been written and
+    /// whose correlation id `matches`, for responses that can't be matched by id. Returns the
+    /// message if there isn't exactly one
+    #[cfg(feature = "jsonrpc")]
+    pub(crate) fn respond_only(&self, matches: impl Fn(&str) -> bool, message: O) -> Option<O> {
+        lock(&self.calls).respond_only(matches, message)
+    }
+}
+
+impl<I, O, C, T> Debug for RpcClient<I, O, C, T>
+where
+    C: Connector,
+    T: Timer,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcClient")
+            .field("socket", &self.socket)
+            .field("pending", &lock(&self.calls).pending.len())
+            .field("open", &self.open)
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
+
+impl<I, O, C, T> Drop for RpcClient<I, O, C, T>
+where
+    C: Connector,
+    T: Timer,
+{
+    fn drop(&mut self) {
+        lock(&self.calls).close();
+    }
+}
+
+impl<I, O, C, T> FusedStream for RpcClient<I, O, C, T>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
+{
+    fn is_terminated(&self) -> bool {
+        self.socket.is_terminated()
+    }
+}
+
+impl<I, O, C, T> Stream for RpcClient<I, O, C, T>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
+{
+    type Item = Event<I, O>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let poll = self.socket.poll_next_unpin(cx);
+            let reopened = self.track_connection();
+
+            match poll {
+                Poll::Ready(Some(event)) => {
+                    if let Some(event) = self.respond(event) {
+                        return Poll::Ready(Some(event));
+                    }
+                },
+                Poll::Ready(None) => {
+                    lock(&self.calls).close();
+                    return Poll::Ready(None);
+                },
+                // Poll again to send the reissued calls
+                Poll::Pending if reopened => {},
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A cloneable handle for making calls on a [`RpcClient`]
+pub struct RpcHandle<I, O, T> {
+    calls: Arc<Mutex<Calls<I, O>>>,
+    sink: SocketSink<I>,
+    timer: T,
+}
+
+impl<I, O, T: Clone> Clone for RpcHandle<I, O, T> {
+    fn clone(&self) -> Self {
+        Self { calls: self.calls.clone(), sink: self.sink.clone(), timer: self.timer.clone() }
+    }
+}
+
+impl<I, O, T> Debug for RpcHandle<I, O, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcHandle").field("sink", &self.sink).finish_non_exhaustive()
+    }
+}
+
+impl<I, O, T: Timer> RpcHandle<I, O, T> {
+    /// Send `request` and wait for the response. See [`RpcCall`]
+    pub fn call(&self, request: I) -> RpcCall<I, O, T> {
+        RpcCall {
+            calls: self.calls.clone(),
+            sink: self.sink.clone(),
+            timer: self.timer.clone(),
+            request: Some(request),
+            reissue: None,
+            timeout: None,
+            id: None,
+            response: None,
+            deadline: None,
+        }
+    }
+}
+
+/// A [`Future`] that sends a request and resolves to its response
+///
+/// Created by [`RpcClient::call`] and [`RpcHandle::call`]. Nothing is sent until it's first polled.
+/// Dropping it abandons the call
+pub struct RpcCall<I, O, T: Timer> {
+    calls: Arc<Mutex<Calls<I, O>>>,
+    sink: SocketSink<I>,
+    timer: T,
+    /// The request until it has been queued for sending
+    request: Option<I>,
+    reissue: Option<Reissue<I>>,
+    timeout: Option<Duration>,
+    /// Set while the call is registered as pending
+    id: Option<String>,
+    response: Option<oneshot::Receiver<Result<O, RpcError>>>,
+    deadline: Option<T::Delay>,
+}
+
+impl<I, O, T: Timer> RpcCall<I, O, T> {
+    /// Update how long to wait for the response. Defaults to the [`RpcClient::set_timeout`]
+    /// timeout. Includes any time spent waiting for the socket to reconnect
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send the request again if the connection drops before the response arrives instead of
+    /// failing with [`RpcError::Disconnected`]. Only use this when handling the request more than
+    /// once is harmless
+    pub fn set_idempotent(mut self) -> Self
+    where
+        I: Clone,
+    {
+        self.reissue = self
+            .request
+            .as_ref()
+            .map(|request| Reissue { request: request.clone(), clone: I::clone });
+        self
+    }
+
+    /// Stop waiting for the response
+    fn finish(&mut self) {
+        if let Some(id) = self.id.take() {
+            lock(&self.calls).pending.remove(&id);
+        }
+    }
+}
+
+impl<I, O, T> Future for RpcCall<I, O, T>
+where
+    I: SocketInput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    O: Unpin,
+    T: Timer,
+{
+    type Output = Result<O, RpcError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        // Register the call before sending so the response can't arrive first
+        if this.response.is_none() {
+            let Some(request) = this.request.as_ref() else {
+                return Poll::Ready(Err(RpcError::Closed));
+            };
+
+            let mut calls = lock(&this.calls);
+            if calls.closed {
+                return Poll::Ready(Err(RpcError::Closed));
+            }
+            let Some(id) = lock(&calls.correlation).request_id(request) else {
+                return Poll::Ready(Err(RpcError::MissingId));
+            };
+            if calls.pending.contains_key(&id) {
+                return Poll::Ready(Err(RpcError::DuplicateId(id)));
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let call = PendingCall {
+                response: sender,
+                reissue: this.reissue.take(),
+                written: false,
+                reissue_due: false,
+            };
+            calls.pending.insert(id.clone(), call);
+            let timeout = *this.timeout.get_or_insert(calls.timeout);
+            trace!("calling {id}");
+            this.id = Some(id);
+            this.response = Some(receiver);
+            this.deadline = Some(this.timer.delay(timeout));
+        }
+
+        if this.deadline.as_mut().is_some_and(|deadline| deadline.poll_unpin(cx).is_ready()) {
+            this.finish();
+            return Poll::Ready(Err(RpcError::Timeout(this.timeout.unwrap_or_default())));
+        }
+
+        if let Some(request) = this.request.take() {
+            let mut sink = Pin::new(&mut this.sink);
+            let sent = match sink.as_mut().poll_ready(cx) {
+                Poll::Pending => {
+                    this.request = Some(request);
+                    return Poll::Pending;
+                },
+                Poll::Ready(ready) => ready.and_then(|()| sink.start_send(request)),
+            };
+            if let Err(e) = sent {
+                this.finish();
+                return Poll::Ready(Err(e.into()));
+            }
+        }
+
+        let Some(response) = this.response.as_mut() else {
+            return Poll::Ready(Err(RpcError::Closed));
+        };
+        let result = match ready!(response.poll_unpin(cx)) {
+            Ok(result) => result,
+            Err(oneshot::Canceled) => Err(RpcError::Closed),
+        };
+        // Already removed by whatever completed it
+        this.id = None;
+        Poll::Ready(result)
+    }
+}
+
+impl<I, O, T: Timer> Debug for RpcCall<I, O, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcCall")
+            .field("id", &self.id)
+            .field("timeout", &self.timeout)
+            .field("sent", &self.request.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I, O, T: Timer> Drop for RpcCall<I, O, T> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
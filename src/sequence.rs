@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{self, Debug},
+    time::Duration,
+};
+
+use crate::{trace, warn, MaybeSend, DEFAULT_GAP_TIMEOUT, DEFAULT_REORDER_WINDOW};
+
+/// Extracts the sequence number from an inbound message. Messages it returns None for aren't
+/// sequenced and are passed straight through. See [`MaybeSend`]
+pub trait SequenceFn<O>: Fn(&O) -> Option<u64> + MaybeSend {}
+impl<O, F: Fn(&O) -> Option<u64> + MaybeSend> SequenceFn<O> for F {}
+
+/// A range of inbound sequence numbers that were never received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// The first missing sequence number
+    pub first: u64,
+    /// The last missing sequence number (inclusive)
+    pub last: u64,
+}
+
+/// Inbound sequencing config
+///
+/// Messages are passed on in sequence order. Duplicates (sequence numbers that have already been
+/// passed on) are dropped, for example when the server replays messages after a reconnect.
+/// Messages that arrive ahead of a missing one are held back until it arrives. Once more than the
+/// window are held back, or they've waited for the gap timeout, the missing messages are given up
+/// on, reported as a [`Gap`], and the held back messages are passed on. With `state-events` the gap
+/// is an `Event::Gap`, without it an [`crate::Error::SequenceGap`]
+///
+/// The first sequenced message received sets where the sequence starts. Set with
+/// [`crate::SocketBuilder::set_sequencing`]
+pub struct Sequencing<O> {
+    pub(crate) extract: Box<dyn SequenceFn<O>>,
+    pub(crate) window: usize,
+    pub(crate) gap_timeout: Option<Duration>,
+}
+
+impl<O> Sequencing<O> {
+    /// Create a config that gets sequence numbers from messages with `extract`. Uses
+    /// [`DEFAULT_REORDER_WINDOW`] and [`DEFAULT_GAP_TIMEOUT`]
+    pub fn new(extract: impl SequenceFn<O> + 'static) -> Self {
+        Self {
+            extract: Box::new(extract),
+            window: DEFAULT_REORDER_WINDOW,
+            gap_timeout: DEFAULT_GAP_TIMEOUT,
+        }
+    }
+
+    /// Update how many out of order messages can be held back waiting for a missing one. 0
+    /// reports gaps as soon as they're seen
+    pub fn set_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Update how long messages can be held back waiting for a missing one. None waits until the
+    /// window is full
+    pub fn set_gap_timeout(mut self, gap_timeout: Option<Duration>) -> Self {
+        self.gap_timeout = gap_timeout;
+        self
+    }
+}
+
+impl<O> Debug for Sequencing<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sequencing")
+            .field("window", &self.window)
+            .field("gap_timeout", &self.gap_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Something the sequencer has ready to pass on
+pub(crate) enum Sequenced<O> {
+    Message(O),
+    Gap(Gap),
+}
+
+/// Puts inbound messages in order
+pub(crate) struct Sequencer<O> {
+    extract: Box<dyn SequenceFn<O>>,
+    window: usize,
+    gap_timeout: Option<Duration>,
+    /// The sequence number expected next. None until the first sequenced message
+    next: Option<u64>,
+    /// Messages that arrived ahead of the expected one
+    held: BTreeMap<u64, O>,
+    ready: VecDeque<Sequenced<O>>,
+}
+
+impl<O> Sequencer<O> {
+    pub(crate) fn new(sequencing: Sequencing<O>) -> Self {
+        let Sequencing { extract, window, gap_timeout } = sequencing;
+        Self {
+            extract,
+            window,
+            gap_timeout,
+            next: None,
+            held: BTreeMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// How long messages can be held back waiting for a missing one
+    pub(crate) fn gap_timeout(&self) -> Option<Duration> {
+        self.gap_timeout
+    }
+
+    /// The missing sequence number messages are being held back for, if any
+    pub(crate) fn waiting_for(&self) -> Option<u64> {
+        self.next.filter(|_| !self.held.is_empty())
+    }
+
+    /// Add a message received from the server
+    pub(crate) fn push(&mut self, message: O) {
+        let Some(seq) = (self.extract)(&message) else {
+            self.ready.push_back(Sequenced::Message(message));
+            return;
+        };
+
+        let next = *self.next.get_or_insert(seq);
+        if seq < next || self.held.contains_key(&seq) {
+            trace!("dropping duplicate message {seq}");
+            return;
+        }
+
+        self.held.insert(seq, message);
+        self.release(next);
+    }
+
+    /// Pass on held messages that are in sequence, giving up on missing ones when too many are
+    /// held
+    fn release(&mut self, mut next: u64) {
+        loop {
+            while let Some(message) = self.held.remove(&next) {
+                self.ready.push_back(Sequenced::Message(message));
+                next += 1;
+            }
+
+            let Some(&first_held) = self.held.keys().next() else {
+                break;
+            };
+            if self.held.len() <= self.window {
+                break;
+            }
+
+            next = self.skip_to(next, first_held);
+        }
+        self.next = Some(next);
+    }
+
+    /// Give up on the messages being waited for and pass on the held ones after them
+    pub(crate) fn skip_gap(&mut self) {
+        let (Some(next), Some(&first_held)) = (self.next, self.held.keys().next()) else {
+            return;
+        };
+        let next = self.skip_to(next, first_held);
+        self.release(next);
+    }
+
+    /// Report the messages from `next` up to `first_held` as missing
+    fn skip_to(&mut self, next: u64, first_held: u64) -> u64 {
+        let gap = Gap { first: next, last: first_held - 1 };
+        warn!("Missing inbound messages: {gap:?}");
+        self.ready.push_back(Sequenced::Gap(gap));
+        first_held
+    }
+
+    /// The next message or gap to pass on
+    pub(crate) fn pop(&mut self) -> Option<Sequenced<O>> {
+        self.ready.pop_front()
+    }
+
+    /// Forget the sequence so the next sequenced message starts it again. Held messages are
+    /// dropped
+    pub(crate) fn reset(&mut self) {
+        self.next = None;
+        self.held.clear();
+    }
+}
+
+impl<O> Debug for Sequencer<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sequencer")
+            .field("window", &self.window)
+            .field("gap_timeout", &self.gap_timeout)
+            .field("next", &self.next)
+            .field("held", &self.held.len())
+            .field("ready", &self.ready.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt::Debug;
+
+use futures::Stream;
+
+/// [`Send`] on native targets. Implemented for everything on wasm where the browser types that
+/// implement [`Signal`] can't be sent between threads
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+/// [`Send`] on native targets. Implemented for everything on wasm where the browser types that
+/// implement [`Signal`] can't be sent between threads
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// [`Sync`] on native targets. Implemented for everything on wasm. See [`MaybeSend`]
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSync: Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Sync> MaybeSync for T {}
+
+/// [`Sync`] on native targets. Implemented for everything on wasm. See [`MaybeSend`]
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSync {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSync for T {}
+
+/// A boolean condition in the environment that [`crate::Socket`] watches, such as whether the
+/// browser is online or the page is visible
+///
+/// The [`Stream`] produces the new value each time it changes. It must wake the task that last
+/// polled it when that happens
+pub trait Signal: Stream<Item = bool> + Debug + MaybeSend + Unpin {
+    /// The current value
+    fn get(&self) -> bool;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use browser::{BrowserOnline, BrowserVisible};
+
+#[cfg(target_arch = "wasm32")]
+mod browser {
+    use std::{
+        cell::RefCell,
+        fmt::{self, Debug},
+        pin::Pin,
+        rc::Rc,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures::Stream;
+    use gloo::{
+        events::EventListener,
+        utils::{document, window},
+    };
+    use web_sys::VisibilityState;
+
+    use super::Signal;
+
+    #[derive(Debug)]
+    struct Shared {
+        value: bool,
+        /// Set when `value` changed since the stream last produced it
+        changed: bool,
+        waker: Option<Waker>,
+    }
+
+    impl Shared {
+        fn new(value: bool) -> Rc<RefCell<Self>> {
+            Rc::new(RefCell::new(Self { value, changed: false, waker: None }))
+        }
+
+        fn set(&mut self, value: bool) {
+            if self.value != value {
+                self.value = value;
+                self.changed = true;
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+
+        fn poll_changed(&mut self, cx: &mut Context<'_>) -> Poll<Option<bool>> {
+            if std::mem::take(&mut self.changed) {
+                Poll::Ready(Some(self.value))
+            } else {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// [`Signal`] that is true while the browser is online, using `navigator.onLine` and the window
+    /// `online` and `offline` events
+    ///
+    /// See [`crate::SocketBuilder::set_online_signal`]
+    pub struct BrowserOnline {
+        shared: Rc<RefCell<Shared>>,
+        _listeners: [EventListener; 2],
+    }
+
+    impl BrowserOnline {
+        /// Start listening to the window `online` and `offline` events
+        ///
+        /// Panics if there is no window
+        pub fn new() -> Self {
+            let window = window();
+            let shared = Shared::new(window.navigator().on_line());
+
+            let listener = |event, online| {
+                let shared = shared.clone();
+                EventListener::new(&window, event, move |_| shared.borrow_mut().set(online))
+            };
+            let listeners = [listener("online", true), listener("offline", false)];
+
+            Self { shared, _listeners: listeners }
+        }
+    }
+
+    impl Default for BrowserOnline {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Debug for BrowserOnline {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("BrowserOnline").field("online", &self.get()).finish()
+        }
+    }
+
+    impl Signal for BrowserOnline {
+        fn get(&self) -> bool {
+            self.shared.borrow().value
+        }
+    }
+
+    impl Stream for BrowserOnline {
+        type Item = bool;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.shared.borrow_mut().poll_changed(cx)
+        }
+    }
+
+    /// [`Signal`] that is true while the page is visible, using the Page Visibility API
+    /// (`document.visibilityState` and the `visibilitychange` event)
+    ///
+    /// See [`crate::SocketBuilder::set_visibility_signal`]
+    pub struct BrowserVisible {
+        shared: Rc<RefCell<Shared>>,
+        _listener: EventListener,
+    }
+
+    impl BrowserVisible {
+        /// Start listening to the document `visibilitychange` event
+        ///
+        /// Panics if there is no document
+        pub fn new() -> Self {
+            let document = document();
+            let is_visible = |document: &web_sys::Document| {
+                document.visibility_state() == VisibilityState::Visible
+            };
+            let shared = Shared::new(is_visible(&document));
+
+            let listener = {
+                let shared = shared.clone();
+                let target = document.clone();
+                EventListener::new(&target, "visibilitychange", move |_| {
+                    shared.borrow_mut().set(is_visible(&document))
+                })
+            };
+
+            Self { shared, _listener: listener }
+        }
+    }
+
+    impl Default for BrowserVisible {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Debug for BrowserVisible {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("BrowserVisible").field("visible", &self.get()).finish()
+        }
+    }
+
+    impl Signal for BrowserVisible {
+        fn get(&self) -> bool {
+            self.shared.borrow().value
+        }
+    }
+
+    impl Stream for BrowserVisible {
+        type Item = bool;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.shared.borrow_mut().poll_changed(cx)
+        }
+    }
+}
+
+#[cfg(feature = "test-util")]
+pub use manual::ManualSignal;
+
+#[cfg(feature = "test-util")]
+mod manual {
+    use std::{
+        pin::Pin,
+        sync::{Arc, Mutex, MutexGuard},
+        task::{Context, Poll, Waker},
+    };
+
+    use futures::Stream;
+
+    use super::Signal;
+
+    #[derive(Debug)]
+    struct Shared {
+        value: bool,
+        changed: bool,
+        waker: Option<Waker>,
+    }
+
+    /// A [`Signal`] set by hand to simulate the browser in tests. Clones share the same value
+    ///
+    /// Requires the `test-util` feature
+    #[derive(Debug, Clone)]
+    pub struct ManualSignal {
+        shared: Arc<Mutex<Shared>>,
+    }
+
+    impl ManualSignal {
+        /// Create a signal with the given initial value
+        pub fn new(value: bool) -> Self {
+            Self { shared: Arc::new(Mutex::new(Shared { value, changed: false, waker: None })) }
+        }
+
+        fn lock(&self) -> MutexGuard<'_, Shared> {
+            self.shared.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        /// Change the value, waking the socket if it's different
+        pub fn set(&self, value: bool) {
+            let waker = {
+                let mut shared = self.lock();
+                if shared.value == value {
+                    return;
+                }
+                shared.value = value;
+                shared.changed = true;
+                shared.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Signal for ManualSignal {
+        fn get(&self) -> bool {
+            self.lock().value
+        }
+    }
+
+    impl Stream for ManualSignal {
+        type Item = bool;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let mut shared = self.lock();
+            if std::mem::take(&mut shared.changed) {
+                Poll::Ready(Some(shared.value))
+            } else {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -4,42 +4,48 @@ use std::{
     marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use cfg_if::cfg_if;
-use exponential_backoff::Backoff;
 use futures::{
-    channel::mpsc::{self, SendError, TrySendError, UnboundedReceiver, UnboundedSender},
     ready,
     stream::{self, Fuse, FusedStream},
-    Sink, Stream, StreamExt,
-};
-use gloo::{
-    net::websocket::{futures::WebSocket, Message, WebSocketError},
-    timers::future::TimeoutFuture,
+    FutureExt, Sink, Stream, StreamExt,
 };
+use gloo::net::websocket::{events::CloseEvent, Message};
 
 use crate::{
+    close_code::CloseCodeActions,
     constants::DEFAULT_STABLE_CONNECTION_TIMEOUT,
-    debug, error,
+    debug,
+    endpoint::Endpoints,
+    error,
     event::{map_err, map_poll},
-    info, trace, Error, Event, SocketInput, SocketOutput, State, DEFAULT_BACKOFF_MAX,
-    DEFAULT_BACKOFF_MIN, DEFAULT_MAX_RETRIES,
+    handshake::HandshakeFuture,
+    info,
+    queue::{lanes, Lanes},
+    reliable::RetransmitBuffer,
+    rpc::RequestId,
+    sequence::{Sequenced, Sequencer},
+    stats::RttWindow,
+    subscription::Subscriptions,
+    suspend::{Suspend, SuspendMode},
+    trace, CloseAction, Connector, Endpoint, Error, Event, ExponentialBackoff, GlooConnector,
+    GlooTimer, Handshake, Heartbeat, OverflowPolicy, PriorityLanes, ReconnectContext,
+    ReconnectDecision, ReconnectPolicy, RttStats, SendError, Signal, SocketInput, SocketOutput,
+    SocketSink, State, SubscribeError, Subscription, Timer, Transport, UrlFuture, UrlProvider,
+    DEFAULT_MAX_RETRIES, DEFAULT_QUEUE_CAPACITY, DEFAULT_RTT_WINDOW,
 };
 
 /// Enum to track which sub future/stream we polled most recently
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub(crate) enum NextPoll {
+    #[default]
     Socket,
     Channel,
 }
 
-impl Default for NextPoll {
-    fn default() -> Self {
-        Self::Socket
-    }
-}
-
 impl NextPoll {
     fn next(self) -> NextPoll {
         use NextPoll::*;
@@ -83,157 +89,348 @@ impl Iterator for NextPollIter {
     }
 }
 
-/// A handle that implements [`Sink`] for sending messages from the client to the server
-///
-/// Cheap and safe to clone (internally it's a channel sender)
-#[derive(Debug, Clone)]
-pub struct SocketSink<I> {
-    sender: UnboundedSender<I>,
-}
-
-impl<I> From<UnboundedSender<I>> for SocketSink<I> {
-    fn from(sender: UnboundedSender<I>) -> Self {
-        Self { sender }
-    }
-}
-
-impl<I> Sink<I> for SocketSink<I>
-where
-    I: SocketInput,
-    Message: TryFrom<I>,
-    <Message as TryFrom<I>>::Error: Debug,
-{
-    type Error = SendError;
-
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        UnboundedSender::poll_ready(&self.sender, cx)
-    }
-
-    fn start_send(self: Pin<&mut Self>, msg: I) -> Result<(), Self::Error> {
-        self.sender.unbounded_send(msg).map_err(TrySendError::into_send_error)
-    }
-
-    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
-    }
-
-    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.sender.close_channel();
-        Poll::Ready(Ok(()))
-    }
-}
-
 /// A wrapper around [`WebSocket`] that reconnects when the socket
-/// drops. Uses [`Backoff`] to determine the delay between reconnects
+/// drops. Uses a [`ReconnectPolicy`] to determine the delay between reconnects which defaults to
+/// [`ExponentialBackoff`]
+///
+/// The connection itself is opened by the [`Connector`] `C` which defaults to [`GlooConnector`].
+/// The delays between reconnects are provided by the [`Timer`] `T` which defaults to
+/// [`GlooTimer`]
 ///
 /// See the [`crate`] documentation for usage and examples
 ///
 /// An error returned by the [`Stream`] aren't necessarily fatal. Check [`Error`] for more detail.
 /// `Poll::Ready(None)` is the main fatal case that requires a new instance of [`Socket`]
-pub struct Socket<I, O> {
-    /// The server URL to connect to on reconnect
-    pub(crate) url: String,
+///
+/// [`WebSocket`]: gloo::net::websocket::futures::WebSocket
+pub struct Socket<I, O, C = GlooConnector, T = GlooTimer>
+where
+    C: Connector,
+    T: Timer,
+{
+    /// The server URLs to connect to on reconnect and which one is in use
+    pub(crate) endpoints: Endpoints,
+    /// When set, called before each connection attempt for the url to connect to
+    pub(crate) url_provider: Option<Box<dyn UrlProvider>>,
+    /// The url being produced by the url provider for the next connection attempt
+    pub(crate) url_future: Option<UrlFuture>,
+    /// Running while connected to a fallback endpoint. When it elapses the socket fails back to
+    /// the preferred endpoint
+    pub(crate) fail_back_deadline: Option<T::Delay>,
+    /// Set when the socket opens so the endpoint is reported after the [`State::Open`] event
+    #[cfg(feature = "state-events")]
+    pub(crate) report_endpoint: bool,
+    /// Opens the inner socket on each reconnect
+    pub(crate) connector: C,
     /// The sending end of the input message channel
     /// Retained to implement [`Self::get_sink`] and [`Self::send`]
-    pub(crate) sink_sender: UnboundedSender<I>,
-    /// The receiving side of the input message channel
+    pub(crate) sink_sender: SocketSink<I>,
+    /// The receiving side of the input message channel, a queue for each priority lane
     /// Polled by the [`Stream`] implementation
-    pub(crate) sink_receiver: UnboundedReceiver<I>,
-    /// The inner socket, None when a reconnect is pending
-    pub(crate) socket: Option<WebSocket>,
+    pub(crate) sink_receiver: Lanes<I>,
+    /// The inner socket, None when a reconnect is pending or the handshake has it
+    pub(crate) socket: Option<C::Transport>,
+    /// When set, run on each new connection before it's reported as open
+    pub(crate) handshake: Option<Box<dyn Handshake<C::Transport>>>,
+    /// The running handshake, which has the inner socket until it's done
+    pub(crate) handshake_future: Option<HandshakeFuture<C::Transport>>,
+    /// Set once the handshake has succeeded on the current connection
+    pub(crate) handshaken: bool,
     /// A queued message that needs to be sent as soon as the socket is [`State::Open`] This
     /// happens when the inner socket exists but hasn't yet fully connected. When in this
-    /// state the [`WebSocket`] [`Sink`] implementation returns [`Poll::Pending`]. Since we
+    /// state the [`Transport`] [`Sink`] implementation returns [`Poll::Pending`]. Since we
     /// can't reliably know that with any certainty until we've already created the
     /// [`Message`] from the input channel and called [`Sink::poll_ready`]. Calling
     /// [`Sink::poll_ready`] before creating the [`Message`] isn't really an option because we
     ///  have no way of undoing anything the Sink does to prepare a slot for us to send to -
-    /// in this specific case, [`WebSocket`] doesn't actually do anything that needs to be
+    /// in the case of the gloo websocket, it doesn't actually do anything that needs to be
     /// reversed but we can't rely on that always being the case. See
     /// <https://github.com/rust-lang/futures-rs/issues/2109> for a discussion about this
     /// problem. So what we do is take the [`Message`] but don't try and send it directly,
     /// instead calling [`Sink::poll_ready`] and only sending it if this returns [`Poll::Ready`]
     pub(crate) queued_message: Option<Message>,
+    /// What was left in [`Self::queued_message`] when the connection dropped, with its
+    /// correlation id. Sent on the next connection once the subscriptions have been replayed
+    pub(crate) unsent: Option<(Message, Option<String>)>,
     pub(crate) state: State,
-    pub(crate) backoff: Backoff,
+    /// Decides the delay before each reconnect
+    pub(crate) policy: Box<dyn ReconnectPolicy>,
+    /// Set when the policy gave up. The next poll closes the socket
+    pub(crate) gave_up: bool,
+    /// What to do when the server closes the connection with a particular code
+    pub(crate) close_actions: CloseCodeActions,
+    /// Set when the server closed with a [`CloseAction::Stop`] code. The next poll returns
+    /// [`Error::ClosedByServer`] and closes the socket
+    pub(crate) stopped: Option<CloseEvent>,
+    /// When set reconnects are paused while it's false
+    pub(crate) online: Option<Box<dyn Signal>>,
+    /// When set the socket is suspended after the page has been hidden for the grace period
+    pub(crate) suspend: Option<Suspend>,
+    /// The grace period, running while the page is hidden
+    pub(crate) suspend_timeout: Option<T::Delay>,
+    /// Set once the grace period has elapsed until the page is visible again
+    pub(crate) suspended: bool,
+    /// Set by [`Self::pause`] until [`Self::resume`]
+    pub(crate) paused: bool,
     pub(crate) max_retries: u32,
     pub(crate) retry: u32,
+    /// [`Timer::now`] when the inner socket last opened
+    pub(crate) opened_at: Option<Duration>,
+    /// The last error produced by the inner socket or connector, passed to the policy
+    pub(crate) last_error: Option<String>,
+    /// When set, pings are sent while open to detect dead connections
+    pub(crate) heartbeat: Option<Heartbeat>,
+    /// Running while open until the next heartbeat ping is due
+    pub(crate) heartbeat_ping: Option<T::Delay>,
+    /// Running after a heartbeat ping until anything is received
+    pub(crate) heartbeat_deadline: Option<T::Delay>,
+    /// Set when a heartbeat ping is due but another message is already queued to be sent
+    pub(crate) ping_due: bool,
+    /// Set while the queued message is a heartbeat ping the transport hasn't accepted yet
+    pub(crate) ping_queued: bool,
+    /// [`Timer::now`] when the transport accepted the last heartbeat ping, until its pong arrives
+    pub(crate) ping_sent_at: Option<Duration>,
+    /// Recent round trip time samples
+    pub(crate) rtt: RttWindow,
+    /// When set, sent messages are kept until acknowledged and resent after reconnecting
+    pub(crate) retransmit: Option<RetransmitBuffer>,
+    /// When set, received messages are de-duplicated and put in order
+    pub(crate) sequencer: Option<Sequencer<O>>,
+    /// Running while the sequencer holds messages back, with the sequence number they wait for
+    pub(crate) gap_deadline: Option<(u64, T::Delay)>,
+    /// When set, inbound messages are routed to subscriptions by topic and the active topics are
+    /// subscribed to on every connection
+    pub(crate) subscriptions: Option<Subscriptions<I, O>>,
+    /// Set by [`crate::RpcClient`] to find the correlation id of each request taken off the queue
+    pub(crate) request_id: Option<Box<dyn RequestId<I>>>,
+    /// The correlation id of the request [`Self::poll_outbound`] last took off the queue
+    pub(crate) dequeued_id: Option<String>,
+    /// The correlation id of the request in [`Self::queued_message`]
+    pub(crate) queued_id: Option<String>,
+    /// Correlation ids of requests the transport has accepted and the [`Self::connection`] they
+    /// were written to, until [`crate::RpcClient`] takes them
+    pub(crate) written_ids: Vec<(u64, String)>,
+    /// Bumped every time the connection is dropped, so [`crate::RpcClient`] can tell a reconnect
+    /// happened even if the socket is open again by the time it looks
+    pub(crate) connection: u64,
+    /// How often to produce [`Event::Stats`]
+    #[cfg(feature = "state-events")]
+    pub(crate) stats_interval: Option<Duration>,
+    /// Running until the next [`Event::Stats`] is due
+    #[cfg(feature = "state-events")]
+    pub(crate) stats_timer: Option<T::Delay>,
+    /// How long a connection attempt can stay [`State::Connecting`]
+    pub(crate) connect_timeout: Option<Duration>,
+    /// Running while a connection attempt is [`State::Connecting`] if there is a connect timeout
+    pub(crate) connect_deadline: Option<T::Delay>,
     /// When socket.is_none this is a reconnect timeout
     /// When socket.is_some this is a connection stable after retry timeout
-    pub(crate) timeout: Fuse<stream::Once<TimeoutFuture>>,
+    pub(crate) timeout: Fuse<stream::Once<T::Delay>>,
+    /// Creates the timeouts
+    pub(crate) timer: T,
     pub(crate) next_poll: NextPoll,
     pub(crate) closed: bool,
     /// How long to wait after reconnecting before resetting retries to 0
-    pub(crate) stable_timeout_millis: u32,
+    pub(crate) stable_timeout: Duration,
     pub(crate) _phantom: PhantomData<(I, O)>,
 }
 
-impl<I, O> Default for Socket<I, O>
+impl<I, O, C, T> Default for Socket<I, O, C, T>
 where
     I: SocketInput,
     O: SocketOutput,
     Message: TryFrom<I>,
     <Message as TryFrom<I>>::Error: Debug,
     <O as TryFrom<Message>>::Error: Debug,
+    C: Connector + Default,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer + Default,
 {
     fn default() -> Self {
-        let (sender, receiver) = mpsc::unbounded();
-        Self {
-            url: String::new(),
-            sink_sender: sender,
-            sink_receiver: receiver,
-            socket: None,
-            queued_message: None,
-            state: State::Connecting,
-            backoff: Backoff::new(DEFAULT_MAX_RETRIES, DEFAULT_BACKOFF_MIN, DEFAULT_BACKOFF_MAX),
-            max_retries: DEFAULT_MAX_RETRIES,
-            retry: 0,
-            timeout: stream::once(TimeoutFuture::new(0)).fuse(),
-            next_poll: NextPoll::Socket,
-            closed: false,
-            stable_timeout_millis: DEFAULT_STABLE_CONNECTION_TIMEOUT.as_millis() as u32,
-            _phantom: PhantomData,
-        }
+        Self::new(String::new(), C::default(), T::default())
     }
 }
 
-impl<I, O> fmt::Debug for Socket<I, O> {
+impl<I, O, C, T> fmt::Debug for Socket<I, O, C, T>
+where
+    C: Connector,
+    T: Timer,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Socket")
-            .field("url", &self.url)
+            .field("endpoints", &self.endpoints)
+            .field("url_provider", &self.url_provider)
+            .field("url_future.is_some", &self.url_future.is_some())
             .field("sink_sender", &self.sink_sender)
             .field("sink_receiver", &self.sink_receiver)
             .field("socket.is_some", &self.socket.is_some())
+            .field("handshake", &self.handshake)
+            .field("handshake_future.is_some", &self.handshake_future.is_some())
             .field("state", &self.state)
-            .field("backoff", &self.backoff)
+            .field("policy", &self.policy)
+            .field("gave_up", &self.gave_up)
+            .field("close_actions", &self.close_actions)
+            .field("stopped", &self.stopped)
+            .field("online", &self.online)
+            .field("suspend", &self.suspend)
+            .field("suspended", &self.suspended)
+            .field("paused", &self.paused)
             .field("max_retries", &self.max_retries)
             .field("retry", &self.retry)
-            .field("timeout", &self.timeout)
+            .field("heartbeat", &self.heartbeat)
+            .field("rtt", &self.rtt)
+            .field("retransmit", &self.retransmit)
+            .field("sequencer", &self.sequencer)
+            .field("subscriptions", &self.subscriptions)
+            .field("connect_timeout", &self.connect_timeout)
             .field("next_poll", &self.next_poll)
             .field("closed", &self.closed)
             .finish()
     }
 }
 
-impl<I, O> Socket<I, O>
+impl<I, O, C, T> Socket<I, O, C, T>
 where
     I: SocketInput,
     O: SocketOutput,
     Message: TryFrom<I>,
     <Message as TryFrom<I>>::Error: Debug,
     <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
 {
-    /// Send the given `message` for sending
+    /// Create a disconnected socket with the default config
+    pub(crate) fn new(url: String, connector: C, timer: T) -> Self {
+        let clock = timer.clone();
+        let (sender, receiver) = lanes(
+            PriorityLanes::default(),
+            DEFAULT_QUEUE_CAPACITY,
+            OverflowPolicy::default(),
+            None,
+            move || clock.now(),
+        );
+        Self {
+            endpoints: Endpoints::new(url),
+            url_provider: None,
+            url_future: None,
+            fail_back_deadline: None,
+            #[cfg(feature = "state-events")]
+            report_endpoint: false,
+            connector,
+            sink_sender: sender,
+            sink_receiver: receiver,
+            socket: None,
+            handshake: None,
+            handshake_future: None,
+            handshaken: false,
+            queued_message: None,
+            unsent: None,
+            state: State::Connecting,
+            policy: Box::new(ExponentialBackoff::default()),
+            gave_up: false,
+            close_actions: CloseCodeActions::default(),
+            stopped: None,
+            online: None,
+            suspend: None,
+            suspend_timeout: None,
+            suspended: false,
+            paused: false,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry: 0,
+            opened_at: None,
+            last_error: None,
+            heartbeat: None,
+            heartbeat_ping: None,
+            heartbeat_deadline: None,
+            ping_due: false,
+            ping_queued: false,
+            ping_sent_at: None,
+            rtt: RttWindow::new(DEFAULT_RTT_WINDOW),
+            retransmit: None,
+            sequencer: None,
+            gap_deadline: None,
+            subscriptions: None,
+            request_id: None,
+            dequeued_id: None,
+            queued_id: None,
+            written_ids: Vec::new(),
+            connection: 0,
+            #[cfg(feature = "state-events")]
+            stats_interval: None,
+            #[cfg(feature = "state-events")]
+            stats_timer: None,
+            connect_timeout: None,
+            connect_deadline: None,
+            timeout: stream::once(timer.delay(Duration::ZERO)).fuse(),
+            timer,
+            next_poll: NextPoll::Socket,
+            closed: false,
+            stable_timeout: DEFAULT_STABLE_CONNECTION_TIMEOUT,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Send the given `message` for sending, waiting for space if the outbound queue is full
     ///
-    /// Internally it is added to a channel which is polled by the [`Stream`] implementation
-    /// when the underlying [`WebSocket`] is open and ready to transmit it
-    pub async fn send(&mut self, message: I) -> Result<(), TrySendError<I>> {
-        self.sink_sender.unbounded_send(message)
+    /// Internally it is added to a queue which is polled by the [`Stream`] implementation
+    /// when the underlying [`Transport`] is open and ready to transmit it. What happens when the
+    /// queue is full depends on the [`OverflowPolicy`]
+    ///
+    /// Space in the queue is only made while the socket is polled. This borrows the socket so it
+    /// can't be polled until this completes, which never happens if the queue is full and it waits.
+    /// If the queue could be full use [`Self::try_send`] or send from another task with
+    /// [`Self::get_sink`] instead
+    pub async fn send(&mut self, message: I) -> Result<(), SendError<I>> {
+        self.sink_sender.queue(message, None).await
+    }
+
+    /// Send the given `message` like [`Self::send`] but drop it instead of sending it if it's
+    /// still queued `ttl` after it was queued, for messages that are worthless once they're stale
+    ///
+    /// Expired messages are dropped when they reach the front of the queue. With `state-events`
+    /// they're reported as `Event::Dropped`, without it see `Self::take_dropped`
+    pub async fn send_with_ttl(&mut self, message: I, ttl: Duration) -> Result<(), SendError<I>> {
+        self.sink_sender.send_with_ttl(message, ttl).await
+    }
+
+    /// Queue the given `message` for sending without waiting
+    ///
+    /// Returns the message in the error if the outbound queue is full and the [`OverflowPolicy`]
+    /// doesn't make space for it
+    pub fn try_send(&mut self, message: I) -> Result<(), SendError<I>> {
+        self.sink_sender.try_send(message)
     }
 
     /// Get a sink handle for sending messages from the client to the server
+    ///
+    /// With [`PriorityLanes`] it sends on the last, lowest priority, lane
     pub fn get_sink(&self) -> SocketSink<I> {
-        self.sink_sender.clone().into()
+        self.sink_sender.clone()
+    }
+
+    /// Get a sink handle for sending messages on priority `lane`, 0 being the highest priority.
+    /// None if there isn't a lane with that number
+    ///
+    /// See [`crate::SocketBuilder::set_priority_lanes`]
+    pub fn get_lane_sink(&self, lane: usize) -> Option<SocketSink<I>> {
+        self.sink_receiver.sink(lane)
+    }
+
+    /// How many messages have been dropped from the outbound queue without being sent, by the
+    /// [`OverflowPolicy`] or because their ttl expired
+    pub fn dropped_count(&self) -> u64 {
+        self.sink_receiver.dropped_count()
+    }
+
+    /// Take the messages dropped from the outbound queue without being sent, by the
+    /// [`OverflowPolicy`] or because their ttl expired, oldest first for each lane. Only the most
+    /// recent queue capacity of them are kept for each lane
+    ///
+    /// With `state-events` they're reported as `Event::Dropped` instead
+    #[cfg(not(feature = "state-events"))]
+    pub fn take_dropped(&self) -> Vec<I> {
+        self.sink_receiver.take_dropped()
     }
 
     /// Close the inner socket with the given `code` and `reason`
@@ -242,39 +439,448 @@ where
     ///
     /// This is mainly an implementation detail but it's exposed so it can be used in test code
     /// to force a reconnect. If used in this way it's worth noting that the Closing/Closed state
-    /// events won't be emitted
+    /// events won't be emitted. [`Self::reconnect_now`], [`Self::pause`] and [`Self::resume`]
+    /// are the intended way to control reconnecting
     pub fn close_socket(&mut self, code: Option<u16>, reason: Option<&str>) {
+        let close_event = self.drop_socket(code, reason);
+
+        if self.closed {
+            return;
+        }
+
+        // The attempt failed before it opened
+        if self.opened_at.is_none() {
+            self.endpoints.failed();
+        }
+
+        let context = ReconnectContext {
+            attempt: self.retry,
+            close: close_event,
+            error: self.last_error.take(),
+            connected_for: self.opened_at.take().map(|at| self.timer.now().saturating_sub(at)),
+        };
+
+        let action = context
+            .close
+            .as_ref()
+            .map(|close| self.close_actions.action(close.code))
+            .unwrap_or_default();
+        match action {
+            CloseAction::Reconnect => {},
+            CloseAction::ReconnectImmediately => {
+                debug!("Close code configured to reconnect immediately. retry: {}", self.retry);
+                self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+                return;
+            },
+            CloseAction::Stop => {
+                // The next poll of the stream will close it and error
+                debug!("Close code configured to stop: {:?}", context.close);
+                self.stopped = context.close;
+                self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+                return;
+            },
+        }
+
+        match self.policy.next_delay(&context) {
+            ReconnectDecision::RetryAfter(timeout) => {
+                debug!("Backoff retry: {}, timeout: {:.3}s", self.retry, timeout.as_secs_f32());
+                self.timeout = stream::once(self.timer.delay(timeout)).fuse();
+            },
+            ReconnectDecision::GiveUp => {
+                // The next poll of the stream will close it, no need to have a real timeout
+                debug!("Reconnect policy gave up after retry: {}", self.retry);
+                self.gave_up = true;
+                self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+            },
+        }
+    }
+
+    /// Take and close the inner socket without scheduling a reconnect
+    ///
+    /// Returns the close event the server closed the connection with, if it did
+    fn drop_socket(&mut self, code: Option<u16>, reason: Option<&str>) -> Option<CloseEvent> {
         // Take and drop the socket
-        if let Some(socket) = self.socket.take() {
+        let close_event = self.socket.take().and_then(|socket| {
+            let close_event = socket.close_event();
             // Attempt to send the close but don't fail if it can't be sent (the socket could be
             // dead already)
-            let _ = socket.close(code, reason);
-        }
+            socket.close(code, reason);
+            close_event
+        });
+
+        // Dropping the handshake closes the socket it has
+        self.handshake_future = None;
+        self.handshaken = false;
 
         // Update our state
         self.state = State::Closed;
+        self.connection += 1;
+        self.connect_deadline = None;
+        self.fail_back_deadline = None;
+        self.heartbeat_ping = None;
+        self.heartbeat_deadline = None;
+        self.ping_due = false;
+        // A ping that hasn't been written belongs to the old connection
+        if self.ping_queued {
+            self.queued_message = None;
+        }
+        self.ping_queued = false;
+        if let Some(message) = self.queued_message.take() {
+            self.unsent = Some((message, self.queued_id.take()));
+        }
+        self.ping_sent_at = None;
+        if let Some(subscriptions) = self.subscriptions.as_ref() {
+            subscriptions.disconnected();
+        }
 
-        if let Some(timeout) = self.backoff.next(self.retry) {
-            debug!("Backoff retry: {}, timeout: {:.3}s", self.retry, timeout.as_secs_f32());
-            let millis = timeout.as_millis() as u32;
-            self.timeout = stream::once(TimeoutFuture::new(millis)).fuse();
-        } else {
-            // If we have exceeded our retries the next poll of the stream will close it and error
-            // no need to have a timeout in that case
-            self.timeout = Self::default().timeout;
+        close_event
+    }
+
+    /// The endpoint the socket is connected to, or will connect to next if it isn't connected
+    pub fn endpoint(&self) -> Endpoint {
+        self.endpoints.current()
+    }
+
+    /// Round trip time statistics over the most recent samples. None until there is a sample
+    ///
+    /// Samples are taken from the heartbeat (see [`crate::SocketBuilder::set_heartbeat`]) and
+    /// [`Self::record_rtt`]. They are kept across reconnects
+    pub fn stats(&self) -> Option<RttStats> {
+        self.rtt.stats()
+    }
+
+    /// Add a round trip time sample measured by the application, for example the time between a
+    /// request and its response
+    pub fn record_rtt(&mut self, rtt: Duration) {
+        self.rtt.record(rtt);
+    }
+
+    /// How many messages sent with reliable delivery are waiting to be acknowledged by the server.
+    /// Always 0 unless [`crate::SocketBuilder::set_reliable`] was used
+    pub fn unacked(&self) -> usize {
+        self.retransmit.as_ref().map(|r| r.len()).unwrap_or_default()
+    }
+
+    /// Forget the inbound sequence numbers seen so far so the next sequenced message starts the
+    /// sequence again, for example after resyncing with the server because of a gap. Messages
+    /// held back waiting for missing ones are dropped. Does nothing unless
+    /// [`crate::SocketBuilder::set_sequencing`] was used
+    pub fn reset_sequence(&mut self) {
+        if let Some(sequencer) = self.sequencer.as_mut() {
+            sequencer.reset();
         }
     }
 
+    /// Subscribe to `topic`, returning a stream of the messages published to it
+    ///
+    /// The subscribe message is sent straight away if the socket is open and again on every new
+    /// connection, ahead of anything else waiting to be sent. Dropping the [`Subscription`] sends
+    /// the unsubscribe message. Requires [`crate::SocketBuilder::set_subscriptions`]
+    pub fn subscribe(&mut self, topic: &str) -> Result<Subscription<O>, SubscribeError> {
+        self.subscriptions.as_ref().ok_or(SubscribeError::NotEnabled)?.subscribe(topic)
+    }
+
+    /// Reconnect straight away if the socket is waiting to reconnect, skipping the rest of the
+    /// backoff
+    ///
+    /// Does nothing if the socket is connected or connecting, or if reconnecting is paused by
+    /// [`Self::pause`], the online signal or the visibility signal. Takes effect the next time the
+    /// socket is polled
+    pub fn reconnect_now(&mut self) {
+        if !self.has_connection() {
+            debug!("Reconnect now. Skipping backoff");
+            self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+        }
+    }
+
+    /// Disconnect and don't reconnect until [`Self::resume`] is called
+    ///
+    /// Messages sent while paused are queued and sent after resuming. The socket reports
+    /// [`State::Paused`] while paused
+    pub fn pause(&mut self) {
+        info!("Pausing socket");
+        self.paused = true;
+        self.url_future = None;
+        if self.has_connection() {
+            self.drop_socket(Some(1000), Some("paused"));
+            self.opened_at = None;
+        }
+    }
+
+    /// Start connecting again after [`Self::pause`] with a fresh retry counter
+    ///
+    /// Takes effect the next time the socket is polled
+    pub fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+        info!("Resuming socket");
+        self.paused = false;
+        self.retry = 0;
+        self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+    }
+
     /// Permanently close the reconnecting socket. No further reconnects will be possible
     ///
     /// The socket implements [`FusedStream`] so polling it after close won't panic
     pub fn close(&mut self, code: Option<u16>, reason: Option<&str>) {
         self.closed = true;
-        let _ = self.close_socket(code, reason);
+        self.sink_receiver.close();
+        if let Some(subscriptions) = self.subscriptions.as_ref() {
+            subscriptions.close();
+        }
+        self.close_socket(code, reason);
+    }
+
+    /// True while connecting, handshaking or open. False while waiting to reconnect
+    fn has_connection(&self) -> bool {
+        self.socket.is_some() || self.handshake_future.is_some()
+    }
+
+    /// Abandon the attempt if it's been connecting for too long
+    fn poll_connect_deadline(&mut self, cx: &mut Context<'_>) -> Option<Error<I, O>> {
+        if !self.connect_deadline.as_mut().is_some_and(|d| d.poll_unpin(cx).is_ready()) {
+            return None;
+        }
+
+        let timeout = self.connect_timeout.unwrap_or_default();
+        error!("connect timed out after {:.3}s", timeout.as_secs_f32());
+        self.last_error = Some(format!("ConnectTimeout({timeout:?})"));
+        self.close_socket(None, None);
+        Some(Error::ConnectTimeout(timeout))
+    }
+
+    /// Hand the inner socket to the handshake once it opens. Returns true if it was handed over
+    fn start_handshake(&mut self) -> bool {
+        let Some(handshake) = self.handshake.as_mut() else {
+            return false;
+        };
+        if self.handshaken || self.socket.as_ref().is_none_or(|s| s.state() != State::Open) {
+            return false;
+        }
+
+        info!("Socket open. Starting handshake");
+        // Checked above
+        let socket = self.socket.take().unwrap();
+        self.handshake_future = Some(handshake.handshake(socket));
+        true
+    }
+
+    /// False if there is an online signal and it says we're offline
+    fn is_online(&self) -> bool {
+        self.online.as_ref().map(|online| online.get()).unwrap_or(true)
+    }
+
+    /// Track the page visibility and suspend once it's been hidden for the grace period
+    fn poll_suspend(&mut self, cx: &mut Context<'_>) {
+        let Some(suspend) = self.suspend.as_mut() else {
+            return;
+        };
+
+        let mut visible = None;
+        while let Poll::Ready(Some(is_visible)) = suspend.visible.poll_next_unpin(cx) {
+            visible = Some(is_visible);
+        }
+
+        match visible {
+            Some(false) if self.suspend_timeout.is_none() && !self.suspended => {
+                debug!("Page hidden. Suspending in {:.3}s", suspend.grace.as_secs_f32());
+                self.suspend_timeout = Some(self.timer.delay(suspend.grace));
+            },
+            Some(true) => {
+                self.suspend_timeout = None;
+                if self.suspended {
+                    info!("Page visible. Resuming");
+                    self.suspended = false;
+                    // Fields instead of has_connection because suspend is borrowed
+                    if self.socket.is_none() && self.handshake_future.is_none() {
+                        self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+                    }
+                }
+            },
+            _ => {},
+        }
+
+        let Some(timeout) = self.suspend_timeout.as_mut() else {
+            return;
+        };
+        if timeout.poll_unpin(cx).is_pending() {
+            return;
+        }
+
+        info!("Page hidden for the grace period. Suspending");
+        self.suspend_timeout = None;
+        self.suspended = true;
+        if suspend.mode == SuspendMode::Close
+            && (self.socket.is_some() || self.handshake_future.is_some())
+        {
+            self.drop_socket(Some(1000), Some("page hidden"));
+            self.opened_at = None;
+        }
+    }
+
+    /// Queue heartbeat pings when they're due and return the timeout error if nothing has been
+    /// received since the last one
+    fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> Option<Error<I, O>> {
+        let heartbeat = self.heartbeat.as_ref()?;
+
+        if self.heartbeat_deadline.as_mut().is_some_and(|d| d.poll_unpin(cx).is_ready()) {
+            let timeout = heartbeat.timeout;
+            error!("nothing received {:.3}s after heartbeat ping", timeout.as_secs_f32());
+            self.last_error = Some(format!("HeartbeatTimeout({timeout:?})"));
+            self.close_socket(None, None);
+            return Some(Error::HeartbeatTimeout(timeout));
+        }
+
+        if self.heartbeat_ping.as_mut().is_some_and(|d| d.poll_unpin(cx).is_ready()) {
+            self.ping_due = true;
+            self.heartbeat_ping = Some(self.timer.delay(heartbeat.interval));
+        }
+
+        // The ping goes out ahead of the input channel through the queued message slot. If that's
+        // taken it's sent once the slot is free. The pong timeout and round trip time start once
+        // the transport accepts it
+        if self.ping_due && self.queued_message.is_none() {
+            trace!("queuing heartbeat ping");
+            self.ping_due = false;
+            self.ping_queued = true;
+            self.queued_message = Some(heartbeat.ping.clone());
+        }
+
+        None
+    }
+
+    /// Start timing the pong once the transport has accepted the heartbeat ping
+    fn ping_sent(&mut self) {
+        let Some(heartbeat) = self.heartbeat.as_ref() else {
+            return;
+        };
+        self.ping_queued = false;
+        self.ping_sent_at = Some(self.timer.now());
+        if self.heartbeat_deadline.is_none() {
+            self.heartbeat_deadline = Some(self.timer.delay(heartbeat.timeout));
+        }
+    }
+
+    /// Returns the stats each time the stats interval elapses
+    #[cfg(feature = "state-events")]
+    fn poll_stats(&mut self, cx: &mut Context<'_>) -> Option<RttStats> {
+        let interval = self.stats_interval?;
+        loop {
+            let delay = self.stats_timer.get_or_insert_with(|| self.timer.delay(interval));
+            if delay.poll_unpin(cx).is_pending() {
+                return None;
+            }
+
+            // Polled straight away by the next loop or the next poll if there's something to
+            // report
+            self.stats_timer = Some(self.timer.delay(interval));
+            if let Some(stats) = self.rtt.stats() {
+                return Some(stats);
+            }
+        }
+    }
+
+    /// The next in order message or gap the sequencer has ready that isn't for a subscription.
+    /// Pending if there isn't one
+    fn poll_sequenced(&mut self, cx: &mut Context<'_>) -> Poll<Option<Event<I, O>>> {
+        loop {
+            while let Some(next) = self.sequencer.as_mut().and_then(|sequencer| sequencer.pop()) {
+                match next {
+                    Sequenced::Message(message) => {
+                        if let Some(message) = self.route(message) {
+                            return map_poll(Poll::Ready(Some(Ok(message))));
+                        }
+                    },
+                    #[cfg(feature = "state-events")]
+                    Sequenced::Gap(gap) => return Poll::Ready(Some(Event::Gap(gap))),
+                    #[cfg(not(feature = "state-events"))]
+                    Sequenced::Gap(gap) => return map_err(Error::SequenceGap(gap)),
+                }
+            }
+
+            if !self.poll_gap_deadline(cx) {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Give up on the missing message the sequencer is waiting for once messages have been held
+    /// back for the gap timeout. Returns true if it was given up on
+    fn poll_gap_deadline(&mut self, cx: &mut Context<'_>) -> bool {
+        let Some(sequencer) = self.sequencer.as_mut() else {
+            return false;
+        };
+        let Some((seq, timeout)) = sequencer.waiting_for().zip(sequencer.gap_timeout()) else {
+            self.gap_deadline = None;
+            return false;
+        };
+
+        // Start again whenever the missing message changes
+        let deadline = match &mut self.gap_deadline {
+            Some((waiting_for, deadline)) if *waiting_for == seq => deadline,
+            gap_deadline => &mut gap_deadline.insert((seq, self.timer.delay(timeout))).1,
+        };
+        if deadline.poll_unpin(cx).is_pending() {
+            return false;
+        }
+
+        self.gap_deadline = None;
+        sequencer.skip_gap();
+        true
+    }
+
+    /// Send an inbound message to its topic's subscription. Returns it if there isn't one
+    fn route(&self, message: O) -> Option<O> {
+        match self.subscriptions.as_ref() {
+            Some(subscriptions) => subscriptions.route(message),
+            None => Some(message),
+        }
+    }
+
+    /// The next message to send. Subscription changes go first, then the message the last
+    /// connection didn't get to send, then with reliable delivery the unacknowledged messages
+    /// being resent after a reconnect, then anything from the input channel
+    fn poll_outbound(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Message, Error<I, O>>>> {
+        if let Some(change) = self.subscriptions.as_ref().and_then(|s| s.poll_change(cx)) {
+            return Poll::Ready(Self::map_channel_input(Some(change)));
+        }
+
+        if let Some((message, id)) = self.unsent.take() {
+            trace!("sending message left from the last connection: {message:?}");
+            self.dequeued_id = id;
+            return Poll::Ready(Some(Ok(message)));
+        }
+
+        let Some(retransmit) = self.retransmit.as_mut() else {
+            let input = ready!(Pin::new(&mut self.sink_receiver).poll_next(cx));
+            self.dequeued_id =
+                self.request_id.as_ref().zip(input.as_ref()).and_then(|(id, input)| id(input));
+            return Poll::Ready(Self::map_channel_input(input));
+        };
+
+        if let Some(message) = retransmit.next_resend() {
+            trace!("resending unacknowledged message: {message:?}");
+            return Poll::Ready(Some(Ok(message)));
+        }
+
+        if retransmit.is_full() {
+            // Woken by the socket when the acknowledgements arrive
+            trace!("retransmit buffer full, waiting for acknowledgements");
+            return Poll::Pending;
+        }
+
+        let input = ready!(Pin::new(&mut self.sink_receiver).poll_next(cx));
+        self.dequeued_id =
+            self.request_id.as_ref().zip(input.as_ref()).and_then(|(id, input)| id(input));
+        Poll::Ready(Self::map_channel_input(input).map(|r| r.map(|m| retransmit.wrap(m))))
     }
 
     fn map_socket_output(
-        output: Option<Result<Message, WebSocketError>>,
+        output: Option<Result<Message, <C::Transport as Transport>::Error>>,
     ) -> Option<Result<O, Error<I, O>>> {
         output.map(|result| {
             result
@@ -303,26 +909,32 @@ where
     }
 }
 
-impl<I, O> FusedStream for Socket<I, O>
+impl<I, O, C, T> FusedStream for Socket<I, O, C, T>
 where
     I: SocketInput,
     O: SocketOutput,
     Message: TryFrom<I>,
     <Message as TryFrom<I>>::Error: Debug,
     <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
 {
     fn is_terminated(&self) -> bool {
         self.closed
     }
 }
 
-impl<I, O> Stream for Socket<I, O>
+impl<I, O, C, T> Stream for Socket<I, O, C, T>
 where
     I: SocketInput,
     O: SocketOutput,
     Message: TryFrom<I>,
     <Message as TryFrom<I>>::Error: Debug,
     <O as TryFrom<Message>>::Error: Debug,
+    C: Connector,
+    Error<I, O>: From<C::Error> + From<<C::Transport as Transport>::Error>,
+    T: Timer,
 {
     type Item = Event<I, O>;
 
@@ -332,21 +944,120 @@ where
             return Poll::Ready(None);
         }
 
+        // Drain online signal changes, registering the waker so we get woken on the next change
+        if let Some(online) = self.online.as_mut() {
+            let mut changed = None;
+            while let Poll::Ready(Some(is_online)) = online.poll_next_unpin(cx) {
+                changed = Some(is_online);
+            }
+
+            if changed == Some(true) && !self.has_connection() {
+                info!("Back online. Reconnecting now");
+                self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
+            }
+        }
+
+        self.poll_suspend(cx);
+
+        #[cfg(feature = "state-events")]
+        if let Some(stats) = self.poll_stats(cx) {
+            return Poll::Ready(Some(Event::Stats(stats)));
+        }
+
+        // Report messages the overflow policy dropped from the outbound queue
+        #[cfg(feature = "state-events")]
+        if let Poll::Ready(message) = self.sink_receiver.poll_dropped(cx) {
+            return Poll::Ready(Some(Event::Dropped(message)));
+        }
+
+        // Pass on messages the sequencer released along with the last one received
+        // or given up on missing ones after the gap timeout
+        if let poll @ Poll::Ready(_) = self.poll_sequenced(cx) {
+            return poll;
+        }
+
         // Reconnect & queue loop
         // Loops in two cases
         // 1. When we disconnected and need to reconnect: socket is none && !self.closed
         // 2. When we sent a queued message and need to re-poll the channel: queued == true &&
         //    self.queued_message.is_none()
         while !self.closed {
+            // The connection isn't open until the handshake is done
+            if self.start_handshake() {
+                continue;
+            }
+
             // Check we have a socket first
             if let Some(socket) = self.socket.as_ref() {
                 // Update our copy of the state and notify if it's changed
-                let current_state = socket.state().into();
+                let current_state = socket.state();
                 if self.state != current_state {
                     self.state = current_state;
+                    if current_state == State::Open {
+                        info!("Connected to endpoint {:?}", self.endpoints.current());
+                        self.opened_at = Some(self.timer.now());
+                        self.connect_deadline = None;
+                        self.endpoints.opened();
+                        self.fail_back_deadline =
+                            self.endpoints.fail_back_after().map(|t| self.timer.delay(t));
+                        self.heartbeat_ping =
+                            self.heartbeat.as_ref().map(|h| self.timer.delay(h.interval));
+                        if let Some(retransmit) = self.retransmit.as_mut() {
+                            // Anything left in the queued slot was sent on the old connection and
+                            // is resent from the buffer with the rest
+                            retransmit.reconnected();
+                            self.queued_message = None;
+                            self.queued_id = None;
+                            self.unsent = None;
+                            self.ping_queued = false;
+                        }
+                        if let Some(subscriptions) = self.subscriptions.as_ref() {
+                            subscriptions.opened();
+                        }
+
+                        #[cfg(feature = "state-events")]
+                        {
+                            self.report_endpoint = self.endpoints.is_multiple();
+                        }
+                    }
+
+                    #[cfg(feature = "state-events")]
+                    return Poll::Ready(Some(self.state.into()));
+                }
+
+                #[cfg(feature = "state-events")]
+                if self.report_endpoint {
+                    self.report_endpoint = false;
+                    return Poll::Ready(Some(Event::Endpoint(self.endpoints.current())));
+                }
+
+                // Go back to the preferred endpoint once the fallback has been stable long enough
+                if self.state == State::Open
+                    && self.fail_back_deadline.as_mut().is_some_and(|d| d.poll_unpin(cx).is_ready())
+                {
+                    info!("Failing back to the preferred endpoint");
+                    self.endpoints.fail_back();
+                    self.drop_socket(Some(1000), Some("failing back"));
+                    self.opened_at = None;
+                    self.timeout = stream::once(self.timer.delay(Duration::ZERO)).fuse();
 
                     #[cfg(feature = "state-events")]
                     return Poll::Ready(Some(self.state.into()));
+
+                    #[cfg(not(feature = "state-events"))]
+                    continue;
+                }
+
+                if self.state == State::Connecting {
+                    if let Some(e) = self.poll_connect_deadline(cx) {
+                        return map_err(e);
+                    }
+                }
+
+                if self.state == State::Open {
+                    if let Some(e) = self.poll_heartbeat(cx) {
+                        return map_err(e);
+                    }
                 }
 
                 // Check if the connection has become stable
@@ -354,22 +1065,114 @@ where
                     trace!("connection is stable. Resetting retries ({} -> 0)", self.retry);
                     self.retry = 0;
                 }
+            } else if let Some(handshake) = self.handshake_future.as_mut() {
+                let result = match handshake.poll_unpin(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => {
+                        // The connect timeout covers the handshake
+                        if let Some(e) = self.poll_connect_deadline(cx) {
+                            return map_err(e);
+                        }
+                        return Poll::Pending;
+                    },
+                };
+
+                self.handshake_future = None;
+                match result {
+                    Ok(socket) => {
+                        debug!("Handshake done");
+                        self.socket = Some(socket);
+                        self.handshaken = true;
+                        // Report it open at the top of the loop
+                        continue;
+                    },
+                    Err(e) => {
+                        error!("Handshake err: {e}");
+                        self.last_error = Some(e.clone());
+                        self.close_socket(None, None);
+                        return map_err(Error::Handshake(e));
+                    },
+                }
             } else {
                 trace!("socket is none");
-                ready!(Pin::new(&mut self.timeout).poll_next(cx));
 
-                if self.retry > self.max_retries {
-                    error!("retries exceeded. Closing");
+                if let Some(close) = self.stopped.take() {
+                    error!("server closed with code {}. Closing", close.code);
+                    self.close(None, None);
+                    return map_err(Error::ClosedByServer(close));
+                }
+
+                if self.gave_up {
+                    error!("reconnect policy gave up. Closing");
                     self.close(None, None);
                     return Poll::Ready(None);
                 }
 
-                info!("Reconnecting socket...");
-                self.retry += 1;
-                match WebSocket::open(&self.url).map_err(Error::<I, O>::from) {
+                // Wait without using up retries until resumed or the visibility or online signal
+                // wakes us
+                let paused = if self.paused {
+                    Some(State::Paused)
+                } else if self.suspended {
+                    Some(State::Suspended)
+                } else if !self.is_online() {
+                    Some(State::Offline)
+                } else {
+                    None
+                };
+                if let Some(paused) = paused {
+                    if self.state != paused {
+                        info!("Pausing reconnects: {paused:?}");
+                        self.state = paused;
+
+                        #[cfg(feature = "state-events")]
+                        return Poll::Ready(Some(self.state.into()));
+                    }
+                    return Poll::Pending;
+                }
+
+                // Skip straight to waiting for the url if it's already being produced
+                if self.url_future.is_none() {
+                    ready!(Pin::new(&mut self.timeout).poll_next(cx));
+
+                    if self.retry > self.max_retries {
+                        error!("retries exceeded. Closing");
+                        self.close(None, None);
+                        return Poll::Ready(None);
+                    }
+
+                    info!("Reconnecting socket to {}...", self.endpoints.url());
+                    self.retry += 1;
+                    let Self { url_provider, url_future, endpoints, .. } = &mut *self;
+                    if let Some(provider) = url_provider.as_mut() {
+                        *url_future = Some(provider.url(&endpoints.current()));
+                    }
+                }
+
+                let url = match self.url_future.as_mut() {
+                    Some(url_future) => {
+                        let result = ready!(url_future.poll_unpin(cx));
+                        self.url_future = None;
+                        match result {
+                            Ok(url) => url,
+                            Err(e) => {
+                                error!("UrlProvider err: {e}");
+                                self.last_error = Some(e.clone());
+                                self.close_socket(None, None);
+                                return map_err(Error::UrlProvider(e));
+                            },
+                        }
+                    },
+                    None => self.endpoints.url().to_string(),
+                };
+
+                let Self { connector, last_error, .. } = &mut *self;
+                match connector.connect(&url).map_err(|e| {
+                    *last_error = Some(format!("{e:?}"));
+                    Error::<I, O>::from(e)
+                }) {
                     Ok(v) => self.socket = Some(v),
                     Err(e) => {
-                        error!("WebSocket::open err: {e:?}");
+                        error!("Connector::connect err: {e:?}");
                         // Reset the connection and set the next retry timeout (although this kind
                         // of error is likely fatal)
                         self.close_socket(None, None);
@@ -380,8 +1183,9 @@ where
                 // Update our state
                 self.state = State::Connecting;
 
-                // Set the stable timeout
-                self.timeout = stream::once(TimeoutFuture::new(self.stable_timeout_millis)).fuse();
+                // Set the stable and connect timeouts
+                self.timeout = stream::once(self.timer.delay(self.stable_timeout)).fuse();
+                self.connect_deadline = self.connect_timeout.map(|t| self.timer.delay(t));
 
                 // Announce it if state events are turned on
                 #[cfg(feature = "state-events")]
@@ -407,6 +1211,10 @@ where
             // returning Poll::Pending
             let queued = self.queued_message.is_some();
 
+            // Set when a message was swallowed or sent and the socket or channel needs polling
+            // again
+            let mut repoll = false;
+
             for next in next_poll_iter {
                 // Update so if we return Ready we resume with the right future
                 self.next_poll = next.next();
@@ -417,8 +1225,31 @@ where
                         // Unwrap ok because we assigned it above if one didn't exist
                         let mut socket = self.socket.as_mut().unwrap();
 
-                        let poll = Pin::new(&mut socket).poll_next(cx).map(Self::map_socket_output);
-                        match poll {
+                        let poll = Pin::new(&mut socket).poll_next(cx);
+                        match &poll {
+                            Poll::Ready(Some(Err(e))) => self.last_error = Some(format!("{e:?}")),
+                            Poll::Ready(Some(Ok(message))) => {
+                                // Any traffic shows the connection is alive
+                                self.heartbeat_deadline = None;
+                                if self.heartbeat.as_ref().is_some_and(|h| h.is_pong(message)) {
+                                    trace!("heartbeat pong: {message:?}");
+                                    if let Some(sent_at) = self.ping_sent_at.take() {
+                                        let rtt = self.timer.now().saturating_sub(sent_at);
+                                        self.rtt.record(rtt);
+                                    }
+                                    repoll = true;
+                                    continue;
+                                }
+                                if self.retransmit.as_mut().is_some_and(|r| r.ack(message)) {
+                                    trace!("acknowledgement: {message:?}");
+                                    repoll = true;
+                                    continue;
+                                }
+                            },
+                            _ => {},
+                        }
+
+                        match poll.map(Self::map_socket_output) {
                             // Just continue to poll the next thing if this is pending
                             Poll::Pending => {},
                             // If it's None (closed) disconnect the socket
@@ -435,6 +1266,23 @@ where
                                     }
                                 }
                             },
+                            Poll::Ready(Some(Ok(message))) => match self.sequencer.as_mut() {
+                                Some(sequencer) => {
+                                    sequencer.push(message);
+                                    if let poll @ Poll::Ready(_) = self.poll_sequenced(cx) {
+                                        return poll;
+                                    }
+                                    // Held back or a duplicate
+                                    repoll = true;
+                                },
+                                None => match self.route(message) {
+                                    Some(message) => {
+                                        return map_poll(Poll::Ready(Some(Ok(message))))
+                                    },
+                                    // Sent to a subscription
+                                    None => repoll = true,
+                                },
+                            },
                             other @ Poll::Ready(Some(_)) => return map_poll(other),
                         }
                     },
@@ -444,7 +1292,7 @@ where
                         // the 2nd poll of the loop and it could have updated in between
 
                         // Unwrap ok because we assigned it above if one didn't exist
-                        if State::Open != self.socket.as_mut().unwrap().state().into() {
+                        if State::Open != self.socket.as_mut().unwrap().state() {
                             // Don't take anything off the incomming message channel if the socket
                             // isn't open because messages sent to WebSocket when it's not yet open
                             // are lost Don't poll the channel because the next time we want to be
@@ -454,21 +1302,18 @@ where
                             continue;
                         }
 
-                        let message_poll = self
-                            .queued_message
-                            // Take the queued message if there is one
-                            .take()
-                            // Map it into a poll result to match the stream result
-                            .map(|m| {
+                        let queued_ping = self.ping_queued && self.queued_message.is_some();
+                        // The correlation id goes with the message
+                        let (message_poll, id) = match self.queued_message.take() {
+                            // Take the queued message if there is one and map it into a poll
+                            // result to match the stream result
+                            Some(m) => {
                                 trace!("attempting to send queued message: {m:?}");
-                                Poll::Ready(Some(Ok(m)))
-                            })
+                                (Poll::Ready(Some(Ok(m))), self.queued_id.take())
+                            },
                             // If there isn't one, poll the stream
-                            .unwrap_or_else(|| {
-                                Pin::new(&mut self.sink_receiver)
-                                    .poll_next(cx)
-                                    .map(Self::map_channel_input)
-                            });
+                            None => (self.poll_outbound(cx), self.dequeued_id.take()),
+                        };
 
                         if let Poll::Ready(message_result) = message_poll {
                             if let Some(try_from_result) = message_result {
@@ -499,6 +1344,7 @@ where
                                              message: {message:?}"
                                         );
                                         self.queued_message = Some(message);
+                                        self.queued_id = id;
                                     },
                                     Poll::Ready(ready) => {
                                         trace!("socket Sink::poll_ready == Poll::Ready");
@@ -513,8 +1359,16 @@ where
                                             {
                                                 Ok(()) => {
                                                     trace!("socket Sink::start_send Ok");
+                                                    if queued_ping {
+                                                        self.ping_sent();
+                                                    }
+                                                    let connection = self.connection;
+                                                    self.written_ids
+                                                        .extend(id.map(|id| (connection, id)));
+                                                    // Unwrap ok because we sent to it above
+                                                    let socket = self.socket.as_mut().unwrap();
                                                     if let Err(e) =
-                                                        ready!(Pin::new(&mut socket).poll_flush(cx))
+                                                        ready!(Pin::new(socket).poll_flush(cx))
                                                             .map_err(Error::<I, O>::from)
                                                     {
                                                         error!(
@@ -522,6 +1376,9 @@ where
                                                         );
                                                         return map_err(e);
                                                     }
+                                                    // Keep draining the channel so senders
+                                                    // waiting for space are woken
+                                                    repoll = true;
                                                 },
                                                 Err(e) => {
                                                     error!("socket Sink::start_send err: {e:?}");
@@ -545,11 +1402,19 @@ where
             if self.socket.is_some()
             // and we didn't dispatch a queued message 
             && !(queued && self.queued_message.is_none())
+            // and we didn't swallow a heartbeat pong or send a message
+            && !repoll
             {
                 break;
             }
         }
 
+        // Report messages that expired while the queue was being drained
+        #[cfg(feature = "state-events")]
+        if let Poll::Ready(message) = self.sink_receiver.poll_dropped(cx) {
+            return Poll::Ready(Some(Event::Dropped(message)));
+        }
+
         Poll::Pending
     }
 }
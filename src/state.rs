@@ -17,6 +17,20 @@ pub enum State {
     Closing,
     /// The connection has been closed or could not be opened.
     Closed,
+    /// The browser is offline so reconnecting is paused until it comes back online.
+    ///
+    /// Only used when an online [`crate::Signal`] is set with
+    /// [`crate::SocketBuilder::set_online_signal`].
+    Offline,
+    /// The page has been hidden for longer than the grace period so the connection is closed and
+    /// reconnecting is paused until it's visible again.
+    ///
+    /// Only used when a visibility [`crate::Signal`] is set with
+    /// [`crate::SocketBuilder::set_visibility_signal`].
+    Suspended,
+    /// Reconnecting is paused by [`crate::Socket::pause`] until [`crate::Socket::resume`] is
+    /// called.
+    Paused,
 }
 
 impl From<GlooState> for State {
@@ -0,0 +1,68 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// Round trip time statistics over the most recent samples
+///
+/// Returned by [`crate::Socket::stats`]. Samples come from heartbeat ping/pong pairs (see
+/// [`crate::Heartbeat`]) and from [`crate::Socket::record_rtt`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttStats {
+    /// How many samples the stats are calculated from
+    pub samples: usize,
+    /// The most recent sample
+    pub last: Duration,
+    /// The smallest sample
+    pub min: Duration,
+    /// The mean of the samples
+    pub mean: Duration,
+    /// The 95th percentile (nearest rank) of the samples
+    pub p95: Duration,
+    /// The mean difference between consecutive samples
+    pub jitter: Duration,
+}
+
+/// A rolling window of round trip time samples
+#[derive(Debug, Clone)]
+pub(crate) struct RttWindow {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl RttWindow {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { samples: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Add a sample, dropping the oldest one if the window is full
+    pub(crate) fn record(&mut self, rtt: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+    }
+
+    /// None until there is at least one sample
+    pub(crate) fn stats(&self) -> Option<RttStats> {
+        let last = *self.samples.back()?;
+        let count = self.samples.len();
+
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let total: Duration = sorted.iter().sum();
+        // Nearest rank, always at least the first sample
+        let p95_rank = (count * 95).div_ceil(100).max(1);
+
+        let jitter_total: Duration =
+            self.samples.iter().zip(self.samples.iter().skip(1)).map(|(a, b)| a.abs_diff(*b)).sum();
+        let jitter = if count > 1 { jitter_total / (count as u32 - 1) } else { Duration::ZERO };
+
+        Some(RttStats {
+            samples: count,
+            last,
+            min: sorted[0],
+            mean: total / count as u32,
+            p95: sorted[p95_rank - 1],
+            jitter,
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{self, Debug},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    task::{Context, Poll, Waker},
+};
+
+use futures::{
+    channel::mpsc,
+    stream::{FusedStream, Stream},
+    StreamExt,
+};
+
+use crate::{debug, trace, MaybeSend};
+
+/// Builds the messages that subscribe to and unsubscribe from topics and finds the topic inbound
+/// messages were published to. See [`MaybeSend`]
+pub trait Topics<I, O>: MaybeSend {
+    /// The message that subscribes to `topic`
+    fn subscribe(&self, topic: &str) -> I;
+
+    /// The message that unsubscribes from `topic`
+    fn unsubscribe(&self, topic: &str) -> I;
+
+    /// The topic an inbound message was published to. None if it wasn't, in which case it's
+    /// passed on to the [`crate::Socket`] stream
+    fn topic(&self, message: &O) -> Option<String>;
+}
+
+/// Errors returned by [`crate::Socket::subscribe`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SubscribeError {
+    /// The socket wasn't built with [`crate::SocketBuilder::set_subscriptions`]
+    #[error("NotEnabled: subscriptions aren't enabled")]
+    NotEnabled,
+
+    /// There's already a [`Subscription`] for the topic
+    #[error("AlreadySubscribed: {0}")]
+    AlreadySubscribed(String),
+}
+
+/// A subscribe or unsubscribe message waiting to be sent
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+/// The state shared by the socket and its [`Subscription`]s
+struct Registry<O> {
+    /// The id of the subscription and where to send its messages by topic
+    active: BTreeMap<String, (u64, mpsc::UnboundedSender<O>)>,
+    next_id: u64,
+    /// Changes to send on the current connection
+    changes: VecDeque<Change>,
+    /// Changes are only queued while connected because every active topic is subscribed to when
+    /// the connection opens
+    open: bool,
+    /// Woken when a change is queued
+    waker: Option<Waker>,
+}
+
+impl<O> Registry<O> {
+    /// Queue a change, cancelling the opposite one if it hasn't been sent yet
+    fn queue(&mut self, change: Change) {
+        if !self.open {
+            return;
+        }
+
+        let opposite = match &change {
+            Change::Subscribe(topic) => Change::Unsubscribe(topic.clone()),
+            Change::Unsubscribe(topic) => Change::Subscribe(topic.clone()),
+        };
+        match self.changes.iter().position(|c| *c == opposite) {
+            Some(index) => {
+                self.changes.remove(index);
+            },
+            None => {
+                self.changes.push_back(change);
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            },
+        }
+    }
+}
+
+fn lock<O>(registry: &Mutex<Registry<O>>) -> MutexGuard<'_, Registry<O>> {
+    registry.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The socket's side of the subscriptions
+pub(crate) struct Subscriptions<I, O> {
+    topics: Box<dyn Topics<I, O>>,
+    registry: Arc<Mutex<Registry<O>>>,
+}
+
+impl<I, O> Subscriptions<I, O> {
+    pub(crate) fn new(topics: impl Topics<I, O> + 'static) -> Self {
+        let registry = Registry {
+            active: BTreeMap::new(),
+            next_id: 0,
+            changes: VecDeque::new(),
+            open: false,
+            waker: None,
+        };
+        Self { topics: Box::new(topics), registry: Arc::new(Mutex::new(registry)) }
+    }
+
+    pub(crate) fn subscribe(&self, topic: &str) -> Result<Subscription<O>, SubscribeError> {
+        let mut registry = lock(&self.registry);
+        if registry.active.contains_key(topic) {
+            return Err(SubscribeError::AlreadySubscribed(topic.to_string()));
+        }
+
+        debug!("Subscribing to {topic}");
+        let id = registry.next_id;
+        registry.next_id += 1;
+        let (sender, receiver) = mpsc::unbounded();
+        registry.active.insert(topic.to_string(), (id, sender));
+        registry.queue(Change::Subscribe(topic.to_string()));
+
+        Ok(Subscription {
+            topic: topic.to_string(),
+            id,
+            receiver,
+            registry: Arc::downgrade(&self.registry),
+        })
+    }
+
+    /// Replace anything left from the last connection with subscribing to every active topic
+    pub(crate) fn opened(&self) {
+        let mut registry = lock(&self.registry);
+        let changes = registry.active.keys().cloned().map(Change::Subscribe).collect();
+        registry.changes = changes;
+        registry.open = true;
+    }
+
+    pub(crate) fn disconnected(&self) {
+        let mut registry = lock(&self.registry);
+        registry.changes.clear();
+        registry.open = false;
+    }
+
+    /// End every subscription's stream
+    pub(crate) fn close(&self) {
+        let mut registry = lock(&self.registry);
+        registry.active.clear();
+        registry.changes.clear();
+        registry.open = false;
+    }
+
+    /// The next subscribe or unsubscribe message to send
+    pub(crate) fn poll_change(&self, cx: &mut Context<'_>) -> Option<I> {
+        let mut registry = lock(&self.registry);
+        let Some(change) = registry.changes.pop_front() else {
+            registry.waker = Some(cx.waker().clone());
+            return None;
+        };
+        drop(registry);
+
+        trace!("sending subscription change: {change:?}");
+        Some(match change {
+            Change::Subscribe(topic) => self.topics.subscribe(&topic),
+            Change::Unsubscribe(topic) => self.topics.unsubscribe(&topic),
+        })
+    }
+
+    /// Send a message to the subscription for its topic. Returns it if there isn't one
+    pub(crate) fn route(&self, message: O) -> Option<O> {
+        let Some(topic) = self.topics.topic(&message) else {
+            return Some(message);
+        };
+        let registry = lock(&self.registry);
+        let Some((_, sender)) = registry.active.get(&topic) else {
+            return Some(message);
+        };
+        sender.unbounded_send(message).err().map(|e| e.into_inner())
+    }
+}
+
+impl<I, O> Debug for Subscriptions<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registry = lock(&self.registry);
+        f.debug_struct("Subscriptions")
+            .field("active", &registry.active.keys().collect::<Vec<_>>())
+            .field("changes", &registry.changes)
+            .field("open", &registry.open)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The messages published to a topic. Created by [`crate::Socket::subscribe`]
+///
+/// Messages are only received while the socket is being polled. Dropping it unsubscribes from
+/// the topic. The stream ends when the socket is closed or dropped
+pub struct Subscription<O> {
+    topic: String,
+    id: u64,
+    receiver: mpsc::UnboundedReceiver<O>,
+    registry: Weak<Mutex<Registry<O>>>,
+}
+
+impl<O> Subscription<O> {
+    /// The topic subscribed to
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+}
+
+impl<O> Debug for Subscription<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription").field("topic", &self.topic).finish_non_exhaustive()
+    }
+}
+
+impl<O> Stream for Subscription<O> {
+    type Item = O;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl<O> FusedStream for Subscription<O> {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+impl<O> Drop for Subscription<O> {
+    fn drop(&mut self) {
+        let Some(registry) = self.registry.upgrade() else {
+            return;
+        };
+        let mut registry = lock(&registry);
+        if registry.active.get(&self.topic).is_some_and(|(id, _)| *id == self.id) {
+            debug!("Unsubscribing from {}", self.topic);
+            registry.active.remove(&self.topic);
+            registry.queue(Change::Unsubscribe(self.topic.clone()));
+        }
+    }
+}
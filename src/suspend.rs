@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use crate::Signal;
+
+/// What [`crate::Socket`] does once the page has been hidden for the grace period set with
+/// [`crate::SocketBuilder::set_visibility_signal`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SuspendMode {
+    /// Close the connection and don't reconnect until the page is visible again
+    #[default]
+    Close,
+    /// Keep the connection while it stays open but don't reconnect if it drops until the page is
+    /// visible again
+    StopReconnecting,
+}
+
+/// The visibility config set on the builder
+#[derive(Debug)]
+pub(crate) struct Suspend {
+    /// True while the page is visible
+    pub(crate) visible: Box<dyn Signal>,
+    /// How long the page has to be hidden before suspending
+    pub(crate) grace: Duration,
+    pub(crate) mode: SuspendMode,
+}
@@ -0,0 +1,253 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::ready;
+use gloo::timers::future::TimeoutFuture;
+use web_sys::{
+    js_sys::{global, Date, Reflect},
+    wasm_bindgen::{JsCast, JsValue},
+    Performance,
+};
+
+use crate::MaybeSend;
+
+/// Provides the delays [`crate::Socket`] uses for the backoff between reconnects and the stable
+/// connection timeout
+///
+/// This lets the socket run on runtimes other than the browser event loop. Implementations must
+/// accept any [`Duration`]. The timer is cloned to stamp the messages queued for sending with
+/// [`Timer::now`]
+pub trait Timer: Clone + MaybeSend + Unpin + 'static {
+    /// Future that completes once the requested duration has elapsed
+    type Delay: Future<Output = ()> + Unpin;
+
+    /// Create a delay that completes after `duration`
+    fn delay(&self, duration: Duration) -> Self::Delay;
+
+    /// The current time measured from an arbitrary fixed point
+    ///
+    /// Only the difference between two calls is meaningful. Used to measure how long connections
+    /// stay open
+    fn now(&self) -> Duration;
+}
+
+/// The default [`Timer`] which uses the browser `setTimeout` via [`TimeoutFuture`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlooTimer;
+
+impl Timer for GlooTimer {
+    type Delay = GlooDelay;
+
+    fn delay(&self, duration: Duration) -> Self::Delay {
+        GlooDelay::new(duration)
+    }
+
+    fn now(&self) -> Duration {
+        // `performance` is monotonic and available in both windows and workers. Date follows the
+        // wall clock, which can jump, so it's only used where there's no `performance`
+        let millis = Reflect::get(&global(), &JsValue::from_str("performance"))
+            .ok()
+            .filter(JsValue::is_object)
+            .map(|performance| performance.unchecked_into::<Performance>().now())
+            .unwrap_or_else(Date::now);
+        Duration::from_secs_f64(millis.max(0.0) / 1000.0)
+    }
+}
+
+/// The longest delay `setTimeout` supports. Browsers store the delay as a signed 32 bit int and
+/// fire immediately if it overflows
+const MAX_TIMEOUT: Duration = Duration::from_millis(i32::MAX as u64);
+
+/// [`Timer::Delay`] for [`GlooTimer`]
+///
+/// Chains [`TimeoutFuture`]s for durations longer than `setTimeout` supports
+#[derive(Debug)]
+pub struct GlooDelay {
+    timeout: TimeoutFuture,
+    /// Time left to wait after `timeout` completes
+    remaining: Duration,
+}
+
+impl GlooDelay {
+    fn new(duration: Duration) -> Self {
+        let next = duration.min(MAX_TIMEOUT);
+        Self { timeout: TimeoutFuture::new(next.as_millis() as u32), remaining: duration - next }
+    }
+}
+
+impl Future for GlooDelay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            ready!(Pin::new(&mut self.timeout).poll(cx));
+            if self.remaining.is_zero() {
+                return Poll::Ready(());
+            }
+            *self = Self::new(self.remaining);
+        }
+    }
+}
+
+#[cfg(feature = "native-tokio")]
+pub use tokio_timer::TokioTimer;
+
+#[cfg(feature = "native-tokio")]
+mod tokio_timer {
+    use std::{pin::Pin, sync::OnceLock, time::Duration};
+
+    use tokio::time::{Instant, Sleep};
+
+    use super::Timer;
+
+    /// [`Timer`] using [`tokio::time::sleep`]. Requires a tokio runtime with the time driver
+    /// enabled
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct TokioTimer;
+
+    impl Timer for TokioTimer {
+        type Delay = Pin<Box<Sleep>>;
+
+        fn delay(&self, duration: Duration) -> Self::Delay {
+            // `sleep` panics if the deadline overflows `Instant`
+            let sleep = match Instant::now().checked_add(duration) {
+                Some(deadline) => tokio::time::sleep_until(deadline),
+                None => tokio::time::sleep_until(Instant::now() + FAR_FUTURE),
+            };
+            Box::pin(sleep)
+        }
+
+        fn now(&self) -> Duration {
+            static START: OnceLock<Instant> = OnceLock::new();
+            START.get_or_init(Instant::now).elapsed()
+        }
+    }
+
+    /// Roughly 30 years, which is what tokio uses internally for "never"
+    const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+}
+
+#[cfg(feature = "test-util")]
+pub use virtual_timer::{VirtualDelay, VirtualTimer};
+
+#[cfg(feature = "test-util")]
+mod virtual_timer {
+    use std::{
+        collections::BTreeMap,
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex, MutexGuard},
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use super::Timer;
+
+    #[derive(Debug, Default)]
+    struct Clock {
+        now: Duration,
+        next_id: u64,
+        /// Deadline and waker of every pending delay that has been polled
+        waiting: BTreeMap<u64, (Duration, Waker)>,
+    }
+
+    /// A [`Timer`] driven by a manually advanced virtual clock
+    ///
+    /// Time only moves when [`VirtualTimer::advance`] is called so reconnect timing can be tested
+    /// without real sleeps. Clones share the same clock
+    ///
+    /// Requires the `test-util` feature
+    #[derive(Debug, Clone, Default)]
+    pub struct VirtualTimer {
+        clock: Arc<Mutex<Clock>>,
+    }
+
+    impl VirtualTimer {
+        /// Create a new clock starting at zero
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        fn lock(&self) -> MutexGuard<'_, Clock> {
+            self.clock.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        /// Move the clock forward by `duration` and wake any delays that have completed
+        pub fn advance(&self, duration: Duration) {
+            let wakers = {
+                let mut clock = self.lock();
+                clock.now = clock.now.saturating_add(duration);
+                let now = clock.now;
+                let expired = clock
+                    .waiting
+                    .iter()
+                    .filter(|(_, (deadline, _))| *deadline <= now)
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+                expired
+                    .into_iter()
+                    .filter_map(|id| clock.waiting.remove(&id))
+                    .map(|(_, waker)| waker)
+                    .collect::<Vec<_>>()
+            };
+            // Wake outside the lock in case the woken task polls straight away
+            wakers.into_iter().for_each(Waker::wake);
+        }
+
+        /// The earliest deadline of the delays that are waiting to complete, if any
+        ///
+        /// Only delays that have been polled at least once are known to the clock
+        pub fn next_deadline(&self) -> Option<Duration> {
+            self.lock().waiting.values().map(|(deadline, _)| *deadline).min()
+        }
+    }
+
+    impl Timer for VirtualTimer {
+        type Delay = VirtualDelay;
+
+        fn delay(&self, duration: Duration) -> Self::Delay {
+            let mut clock = self.lock();
+            let id = clock.next_id;
+            clock.next_id += 1;
+            VirtualDelay { id, deadline: clock.now.saturating_add(duration), timer: self.clone() }
+        }
+
+        /// The time elapsed on the virtual clock
+        fn now(&self) -> Duration {
+            self.lock().now
+        }
+    }
+
+    /// [`Timer::Delay`] for [`VirtualTimer`]
+    #[derive(Debug)]
+    pub struct VirtualDelay {
+        id: u64,
+        deadline: Duration,
+        timer: VirtualTimer,
+    }
+
+    impl Future for VirtualDelay {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut clock = self.timer.lock();
+            if clock.now >= self.deadline {
+                clock.waiting.remove(&self.id);
+                Poll::Ready(())
+            } else {
+                clock.waiting.insert(self.id, (self.deadline, cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Drop for VirtualDelay {
+        fn drop(&mut self) {
+            self.timer.lock().waiting.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    fmt::{self, Debug},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{Sink, Stream};
+use gloo::{
+    net::websocket::{events::CloseEvent, futures::WebSocket, Message, WebSocketError},
+    utils::errors::JsError,
+};
+
+use crate::State;
+
+/// Opens new [`Transport`] connections for [`crate::Socket`]
+///
+/// The connector is kept by the socket for its whole life and is called again for every
+/// reconnect, so it can carry whatever configuration the transport needs
+pub trait Connector: Unpin {
+    /// The connection type this connector opens
+    type Transport: Transport;
+
+    /// Error returned when a connection attempt can't be started at all
+    ///
+    /// Errors that happen while the connection is being established should be reported through
+    /// the [`Transport`] [`Stream`] instead
+    type Error: Debug;
+
+    /// Start connecting to `url`
+    ///
+    /// This should return as soon as the attempt has started. The returned transport should report
+    /// [`State::Connecting`] until the connection is established
+    fn connect(&mut self, url: &str) -> Result<Self::Transport, Self::Error>;
+}
+
+/// A single websocket connection
+///
+/// The [`Stream`] half produces incoming messages and errors and ends when the connection has
+/// closed. The [`Sink`] half sends outgoing messages and should return [`std::task::Poll::Pending`]
+/// from [`Sink::poll_ready`] until the connection is [`State::Open`]
+///
+/// Implementations must wake the task that last polled the [`Stream`] when the state changes
+/// from [`State::Connecting`] to [`State::Open`] even though no message is produced at that point.
+/// [`crate::Socket`] relies on this to start sending queued messages
+pub trait Transport:
+    Stream<Item = Result<Message, <Self as Transport>::Error>>
+    + Sink<Message, Error = <Self as Transport>::Error>
+    + Unpin
+{
+    /// Error produced by the [`Stream`] and [`Sink`]
+    type Error: Debug;
+
+    /// The current state of the connection
+    fn state(&self) -> State;
+
+    /// Close the connection with the given `code` and `reason`
+    ///
+    /// Errors are ignored since the connection could be dead already
+    fn close(self, code: Option<u16>, reason: Option<&str>);
+
+    /// The close code and reason the remote end closed the connection with, once the connection
+    /// has closed. Used to inform the [`crate::ReconnectPolicy`]
+    ///
+    /// The default implementation returns None
+    fn close_event(&self) -> Option<CloseEvent> {
+        None
+    }
+}
+
+/// The default [`Connector`] which opens a browser [`WebSocket`] using
+/// [`WebSocket::open`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlooConnector;
+
+impl Connector for GlooConnector {
+    type Error = JsError;
+    type Transport = GlooTransport;
+
+    fn connect(&mut self, url: &str) -> Result<Self::Transport, Self::Error> {
+        WebSocket::open(url).map(GlooTransport::from)
+    }
+}
+
+/// [`Transport`] wrapping the browser [`WebSocket`]
+///
+/// Remembers the [`WebSocketError::ConnectionClose`] event so it can be returned by
+/// [`Transport::close_event`]
+pub struct GlooTransport {
+    socket: WebSocket,
+    close_event: Option<CloseEvent>,
+}
+
+impl From<WebSocket> for GlooTransport {
+    fn from(socket: WebSocket) -> Self {
+        Self { socket, close_event: None }
+    }
+}
+
+impl fmt::Debug for GlooTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlooTransport")
+            .field("state", &self.state())
+            .field("close_event", &self.close_event)
+            .finish()
+    }
+}
+
+impl Transport for GlooTransport {
+    type Error = WebSocketError;
+
+    fn state(&self) -> State {
+        self.socket.state().into()
+    }
+
+    fn close(self, code: Option<u16>, reason: Option<&str>) {
+        let _ = self.socket.close(code, reason);
+    }
+
+    fn close_event(&self) -> Option<CloseEvent> {
+        self.close_event.clone()
+    }
+}
+
+impl Stream for GlooTransport {
+    type Item = Result<Message, WebSocketError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.socket).poll_next(cx);
+        if let Poll::Ready(Some(Err(WebSocketError::ConnectionClose(event)))) = &poll {
+            self.close_event = Some(event.clone());
+        }
+        poll
+    }
+}
+
+impl Sink<Message> for GlooTransport {
+    type Error = WebSocketError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.socket).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        Pin::new(&mut self.socket).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.socket).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.socket).poll_close(cx)
+    }
+}
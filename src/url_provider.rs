@@ -0,0 +1,44 @@
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+};
+
+use crate::{Endpoint, MaybeSend};
+
+/// A [`Future`] that is [`Send`] on native targets. See [`MaybeSend`]
+pub trait MaybeSendFuture: Future + MaybeSend {}
+impl<F: Future + MaybeSend> MaybeSendFuture for F {}
+
+/// The future returned by [`UrlProvider::url`]. Resolves to the url to connect to or an error
+/// describing why it couldn't be produced
+pub type UrlFuture = Pin<Box<dyn MaybeSendFuture<Output = Result<String, String>>>>;
+
+/// Produces the url for each connection attempt, for example to add a freshly fetched auth token
+/// to the query string
+///
+/// Set with [`crate::SocketBuilder::set_url_provider`]. Implemented for closures taking the
+/// [`Endpoint`] about to be connected to and returning a future of `Result<String, String>`
+pub trait UrlProvider: MaybeSend {
+    /// Start producing the url for a connection attempt to `endpoint`
+    ///
+    /// An error fails the attempt and is passed to the [`crate::ReconnectPolicy`] like any other
+    /// failed attempt
+    fn url(&mut self, endpoint: &Endpoint) -> UrlFuture;
+}
+
+impl<F, Fut> UrlProvider for F
+where
+    F: FnMut(&Endpoint) -> Fut + MaybeSend,
+    Fut: Future<Output = Result<String, String>> + MaybeSend + 'static,
+{
+    fn url(&mut self, endpoint: &Endpoint) -> UrlFuture {
+        Box::pin(self(endpoint))
+    }
+}
+
+impl Debug for dyn UrlProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UrlProvider")
+    }
+}
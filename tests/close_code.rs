@@ -0,0 +1,89 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::stream::FusedStream;
+use reconnecting_websocket::{loopback::LoopbackServer, CloseAction, Error, VirtualTimer};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    poll_messages, poll_ready, virtual_builder, MessageResult, VirtualSocket, VirtualSocketBuilder,
+};
+
+const BACKOFF: Duration = Duration::from_secs(10);
+
+fn open(
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    let (builder, server, timer) = virtual_builder(BACKOFF);
+    server.set_auto_accept(true);
+    let socket = configure(builder).open().expect("open");
+    (socket, server, timer)
+}
+
+/// Close the current connection from the server end and return the messages the socket produced
+fn server_close(
+    socket: &mut VirtualSocket,
+    server: &LoopbackServer,
+    code: u16,
+) -> Vec<MessageResult> {
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(socket);
+    peer.close(code, "test");
+    poll_messages(socket)
+}
+
+#[test]
+fn stop_code_ends_the_stream_with_an_error() {
+    let (mut socket, server, timer) = open(|b| b.set_close_code_action(1008, CloseAction::Stop));
+
+    let messages = server_close(&mut socket, &server, 1008);
+    match messages.as_slice() {
+        [Err(Error::WebSocketError(_)), Err(Error::ClosedByServer(close))] => {
+            assert_eq!(close.code, 1008);
+            assert_eq!(close.reason, "test");
+        },
+        other => panic!("unexpected messages: {other:?}"),
+    }
+    assert!(socket.is_terminated());
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn later_actions_override_earlier_ranges() {
+    let (mut socket, server, timer) = open(|b| {
+        b.set_close_code_range_action(4000..=4999, CloseAction::Stop)
+            .set_close_code_action(4001, CloseAction::Reconnect)
+    });
+
+    server_close(&mut socket, &server, 4001);
+    assert!(!socket.is_terminated());
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+
+    let messages = server_close(&mut socket, &server, 4002);
+    assert!(
+        matches!(messages.last(), Some(Err(Error::ClosedByServer(close))) if close.code == 4002)
+    );
+    assert!(socket.is_terminated());
+}
+
+#[test]
+fn reconnect_immediately_skips_the_backoff() {
+    let (mut socket, server, _) =
+        open(|b| b.set_close_code_action(1012, CloseAction::ReconnectImmediately));
+
+    server_close(&mut socket, &server, 1012);
+    assert_eq!(server.connection_count(), 2);
+
+    // Other codes still wait
+    server_close(&mut socket, &server, 1001);
+    assert_eq!(server.connection_count(), 2);
+}
@@ -0,0 +1,116 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    task::Context,
+    time::Duration,
+};
+
+use futures::{task::noop_waker_ref, Sink};
+use reconnecting_websocket::{loopback::LoopbackServer, Message, OverflowPolicy};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, poll_ready, virtual_builder, Input, VirtualSocket, VirtualSocketBuilder};
+
+/// Numbers from 10 up are keyed by their tens, so 12 replaces 11. Lower ones aren't keyed
+fn key(input: &Input) -> Option<String> {
+    let Input::Bar(n) = input;
+    (*n >= 10).then(|| (n / 10).to_string())
+}
+
+fn builder() -> (VirtualSocketBuilder, LoopbackServer) {
+    let (builder, server, _) = virtual_builder(Duration::from_secs(1));
+    (builder.set_coalesce_key(key), server)
+}
+
+fn send(socket: &mut VirtualSocket, numbers: impl IntoIterator<Item = usize>) {
+    for n in numbers {
+        socket.try_send(Input::Bar(n)).expect("send");
+    }
+}
+
+fn text(n: usize) -> Message {
+    Message::Text(format!("Bar({n})"))
+}
+
+#[test]
+fn only_the_latest_message_for_each_key_is_sent() {
+    let (builder, server) = builder();
+    let mut socket = builder.open().expect("open");
+
+    send(&mut socket, [10, 11, 1, 20, 12, 21, 2]);
+
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    // Replaced messages aren't dropped
+    #[cfg(feature = "state-events")]
+    assert!(!poll_ready(&mut socket)
+        .iter()
+        .any(|event| matches!(event, reconnecting_websocket::Event::Dropped(_))));
+    #[cfg(not(feature = "state-events"))]
+    poll_ready(&mut socket);
+
+    // A replacement goes to the back of the queue
+    assert_eq!(peer.drain(), vec![text(1), text(12), text(21), text(2)]);
+
+    // Messages that have been sent aren't affected
+    send(&mut socket, [13]);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text(13)]);
+}
+
+#[test]
+fn replacing_a_message_doesnt_need_space() {
+    let (builder, server) = builder();
+    let mut socket = builder
+        .set_queue_capacity(2)
+        .set_overflow_policy(OverflowPolicy::RejectNew)
+        .open()
+        .expect("open");
+
+    send(&mut socket, [10, 1]);
+    assert!(socket.try_send(Input::Bar(2)).is_err_and(|e| e.is_full()));
+    send(&mut socket, [11]);
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![text(1), text(11)]);
+}
+
+#[test]
+fn replacing_a_message_doesnt_wait_for_space() {
+    let (builder, server) = builder();
+    let mut socket = builder
+        .set_queue_capacity(2)
+        .set_overflow_policy(OverflowPolicy::Block)
+        .open()
+        .expect("open");
+    let mut sink = socket.get_sink();
+
+    send(&mut socket, [10, 20]);
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert!(pin!(socket.send(Input::Bar(1))).poll(&mut cx).is_pending());
+    assert!(pin!(socket.send(Input::Bar(11))).poll(&mut cx).is_ready());
+    assert!(pin!(sink.send_with_ttl(Input::Bar(21), Duration::from_secs(10)))
+        .poll(&mut cx)
+        .is_ready());
+    // Sink::poll_ready can't tell the next message replaces one
+    assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_pending());
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![text(11), text(21)]);
+}
+
+#[test]
+fn messages_without_a_key_are_all_sent() {
+    let (builder, server) = builder();
+    let mut socket = builder.open().expect("open");
+
+    send(&mut socket, [1, 1, 2]);
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![text(1), text(1), text(2)]);
+}
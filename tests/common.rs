@@ -1,6 +1,23 @@
-use std::{num::ParseIntError, sync::Once};
+use std::{
+    fmt::Debug,
+    num::ParseIntError,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Once,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use reconnecting_websocket::Message;
+use futures::{
+    task::{noop_waker_ref, ArcWake},
+    Stream, StreamExt,
+};
+use reconnecting_websocket::{
+    loopback::{loopback, LoopbackConnector, LoopbackPeer, LoopbackServer},
+    ConstantBackoff, Error, Event, Message, Socket, SocketBuilder, SocketInput, SocketOutput,
+    State, VirtualTimer,
+};
 use time::format_description::well_known::Iso8601;
 use tracing_subscriber::{
     fmt::{format::Pretty, time::UtcTime},
@@ -26,7 +43,7 @@ pub fn configure_tracing() {
 /// Configures tracing inside a Once block so multiple calls don't panic
 pub fn configure_tracing_once() {
     static ONCE: Once = Once::new();
-    ONCE.call_once(|| configure_tracing());
+    ONCE.call_once(configure_tracing);
 }
 
 #[derive(Debug)]
@@ -62,3 +79,165 @@ impl TryFrom<Message> for Output {
         }
     }
 }
+
+pub type MessageResult = Result<Output, Error<Input, Output>>;
+
+/// Split a stream item into the message (if it is one) and the state change (if it is one)
+#[cfg(feature = "state-events")]
+pub fn split_event(event: Event<Input, Output>) -> (Option<MessageResult>, Option<State>) {
+    match event {
+        Event::Message(m) => (Some(m), None),
+        Event::State(s) => (None, Some(s)),
+        _ => (None, None),
+    }
+}
+
+/// Split a stream item into the message (if it is one) and the state change (if it is one)
+#[cfg(not(feature = "state-events"))]
+pub fn split_event(event: Event<Input, Output>) -> (Option<MessageResult>, Option<State>) {
+    (Some(event), None)
+}
+
+/// The message if the stream item is one
+#[cfg(feature = "state-events")]
+pub fn message<I, O>(event: Event<I, O>) -> Option<Result<O, Error<I, O>>>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+{
+    match event {
+        Event::Message(message) => Some(message),
+        _ => None,
+    }
+}
+
+/// The message if the stream item is one
+#[cfg(not(feature = "state-events"))]
+pub fn message<I, O>(event: Event<I, O>) -> Option<Result<O, Error<I, O>>>
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+{
+    Some(event)
+}
+
+/// Counts how many times it's woken
+#[derive(Default)]
+pub struct WakeCount(pub AtomicUsize);
+
+impl ArcWake for WakeCount {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Poll `stream` with a no-op waker until it returns [`Poll::Pending`] or ends and return the items
+/// it produced. Used with [`reconnecting_websocket::VirtualTimer`] where nothing happens between
+/// polls unless the test makes it happen
+pub fn poll_ready<S: Stream + Unpin>(stream: &mut S) -> Vec<S::Item> {
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let mut items = Vec::new();
+    while let Poll::Ready(Some(item)) = stream.poll_next_unpin(&mut cx) {
+        items.push(item);
+    }
+    items
+}
+
+/// Poll the socket and return the messages it produced
+pub fn poll_messages<S>(socket: &mut S) -> Vec<MessageResult>
+where
+    S: Stream<Item = Event<Input, Output>> + Unpin,
+{
+    poll_ready(socket).into_iter().filter_map(|event| split_event(event).0).collect()
+}
+
+/// Poll the socket and return the states it reported. Always empty without `state-events`
+pub fn poll_states<S>(socket: &mut S) -> Vec<State>
+where
+    S: Stream<Item = Event<Input, Output>> + Unpin,
+{
+    poll_ready(socket).into_iter().filter_map(|event| split_event(event).1).collect()
+}
+
+pub type VirtualSocket<I = Input, O = Output> = Socket<I, O, LoopbackConnector, VirtualTimer>;
+pub type VirtualSocketBuilder<I = Input, O = Output> =
+    SocketBuilder<I, O, LoopbackConnector, VirtualTimer>;
+
+pub const LOOPBACK_URL: &str = "ws://loopback";
+
+/// A builder for a socket on a [`loopback`] connection driven by a [`VirtualTimer`] that waits
+/// `backoff` between reconnects
+pub fn virtual_builder<I, O>(
+    backoff: Duration,
+) -> (VirtualSocketBuilder<I, O>, LoopbackServer, VirtualTimer)
+where
+    I: SocketInput,
+    O: SocketOutput,
+    Message: TryFrom<I>,
+    <Message as TryFrom<I>>::Error: Debug,
+    <O as TryFrom<Message>>::Error: Debug,
+{
+    let (connector, server) = loopback();
+    let timer = VirtualTimer::new();
+    let builder = VirtualSocketBuilder::new_with_connector(LOOPBACK_URL.to_string(), connector)
+        .set_timer(timer.clone())
+        .set_reconnect_policy(ConstantBackoff::new(backoff));
+    (builder, server, timer)
+}
+
+/// Open a socket from [`virtual_builder`] with the config under test added by `configure`. The
+/// first connection is left for the test to accept
+pub fn open_virtual(
+    backoff: Duration,
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    let (builder, server, timer) = virtual_builder(backoff);
+    let socket = configure(builder).open().expect("open");
+    (socket, server, timer)
+}
+
+/// Accept the next connection and poll the socket (or whatever wraps it) so it sees it open
+pub fn accept<S: Stream + Unpin>(socket: &mut S, server: &LoopbackServer) -> LoopbackPeer {
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    poll_ready(socket);
+    peer
+}
+
+/// Drop the connection from the server end, wait out `backoff` and accept the reconnect
+pub fn reconnect<S: Stream + Unpin>(
+    socket: &mut S,
+    server: &LoopbackServer,
+    peer: &LoopbackPeer,
+    timer: &VirtualTimer,
+    backoff: Duration,
+) -> LoopbackPeer {
+    peer.close(1001, "going away");
+    poll_ready(socket);
+    timer.advance(backoff);
+    poll_ready(socket);
+    accept(socket, server)
+}
+
+pub fn text(text: &str) -> Message {
+    Message::Text(text.to_string())
+}
+
+/// The numbers of the [`Input::Bar`] messages the peer has received
+pub fn received(peer: &LoopbackPeer) -> Vec<usize> {
+    peer.drain()
+        .into_iter()
+        .map(|message| match message {
+            Message::Text(text) => {
+                text.trim_start_matches("Bar(").trim_end_matches(')').parse().expect("number")
+            },
+            other => panic!("unexpected message {other:?}"),
+        })
+        .collect()
+}
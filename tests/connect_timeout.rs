@@ -0,0 +1,68 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use reconnecting_websocket::{loopback::LoopbackServer, Error, State, VirtualTimer};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{open_virtual, poll_messages, poll_ready, VirtualSocket};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn open() -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    open_virtual(BACKOFF, |b| b.set_connect_timeout(Some(CONNECT_TIMEOUT)))
+}
+
+#[test]
+fn stalled_initial_open_times_out_and_reconnects() {
+    let (mut socket, server, timer) = open();
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(&mut socket);
+
+    timer.advance(CONNECT_TIMEOUT - Duration::from_millis(1));
+    assert!(poll_messages(&mut socket).is_empty());
+
+    timer.advance(Duration::from_millis(1));
+    let messages = poll_messages(&mut socket);
+    assert!(
+        matches!(messages.as_slice(), [Err(Error::ConnectTimeout(timeout))] if *timeout == CONNECT_TIMEOUT)
+    );
+    assert_eq!(peer.state(), State::Closed);
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn stalled_reconnect_times_out() {
+    let (mut socket, server, timer) = open();
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(&mut socket);
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    let peer = server.try_next_connection().expect("reconnection");
+
+    timer.advance(CONNECT_TIMEOUT);
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Err(Error::ConnectTimeout(_))]));
+    assert_eq!(peer.state(), State::Closed);
+}
+
+#[test]
+fn open_connection_is_not_timed_out() {
+    let (mut socket, server, timer) = open();
+    let peer = server.try_next_connection().expect("connection");
+    timer.advance(CONNECT_TIMEOUT / 2);
+    peer.accept();
+    poll_ready(&mut socket);
+
+    timer.advance(CONNECT_TIMEOUT * 2);
+    assert!(poll_messages(&mut socket).is_empty());
+    assert_eq!(peer.state(), State::Open);
+    assert_eq!(server.connection_count(), 1);
+}
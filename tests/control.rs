@@ -0,0 +1,85 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::stream::FusedStream;
+use reconnecting_websocket::{Message, State};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, open_virtual, poll_ready, poll_states, Input};
+
+const BACKOFF: Duration = Duration::from_secs(60);
+
+#[test]
+fn reconnect_now_skips_the_backoff() {
+    let (mut socket, server, _) = open_virtual(BACKOFF, |b| b);
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+
+    socket.reconnect_now();
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn reconnect_now_leaves_a_connection_alone() {
+    let (mut socket, server, _) = open_virtual(BACKOFF, |b| b);
+    let peer = accept(&mut socket, &server);
+
+    socket.reconnect_now();
+    poll_ready(&mut socket);
+    assert_eq!(peer.state(), State::Open);
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn pause_disconnects_and_keeps_queued_input() {
+    let (mut socket, server, timer) = open_virtual(BACKOFF, |b| b);
+    let peer = accept(&mut socket, &server);
+
+    socket.pause();
+    assert_eq!(peer.client_close(), Some((Some(1000), Some("paused".to_string()))));
+    let states = poll_states(&mut socket);
+    if cfg!(feature = "state-events") {
+        assert_eq!(states, vec![State::Paused]);
+    }
+
+    futures::executor::block_on(socket.send(Input::Bar(1))).expect("send");
+    timer.advance(BACKOFF * 10);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+
+    socket.resume();
+    poll_ready(&mut socket);
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![Message::Text("Bar(1)".to_string())]);
+}
+
+#[test]
+fn resume_resets_the_retry_counter() {
+    let (mut socket, server, timer) = open_virtual(BACKOFF, |b| b.set_max_retries(1));
+
+    // Use up the retries apart from the last one
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(&mut socket);
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    server.try_next_connection().expect("retry").refuse();
+    poll_ready(&mut socket);
+
+    socket.pause();
+    socket.resume();
+
+    // Without the reset the socket would close after the second of these
+    for _ in 0..2 {
+        timer.advance(BACKOFF);
+        poll_ready(&mut socket);
+        server.try_next_connection().expect("retry after resume").refuse();
+        poll_ready(&mut socket);
+    }
+    assert!(!socket.is_terminated());
+}
@@ -0,0 +1,115 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use reconnecting_websocket::{
+    loopback::LoopbackServer, Endpoint, EndpointStrategy, Error, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    accept, open_virtual, poll_ready, virtual_builder, Input, Output, VirtualSocket,
+    VirtualSocketBuilder,
+};
+#[cfg(feature = "state-events")]
+use reconnecting_websocket::Event;
+
+const BACKOFF: Duration = Duration::from_secs(1);
+const PRIMARY: &str = "ws://primary";
+const SECONDARY: &str = "ws://secondary";
+
+fn open(
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    open_virtual(BACKOFF, |b| {
+        configure(b.set_endpoints(vec![PRIMARY.to_string(), SECONDARY.to_string()]))
+    })
+}
+
+/// Refuse the next connection and wait out the backoff so the socket reconnects
+fn refuse(socket: &mut VirtualSocket, server: &LoopbackServer, timer: &VirtualTimer) {
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(socket);
+    timer.advance(BACKOFF);
+    poll_ready(socket);
+}
+
+#[test]
+fn fails_over_after_consecutive_failures() {
+    let (mut socket, server, timer) = open(|b| b.set_failover_after(2));
+
+    refuse(&mut socket, &server, &timer);
+    refuse(&mut socket, &server, &timer);
+
+    assert_eq!(server.urls(), vec![PRIMARY, PRIMARY, SECONDARY]);
+    assert_eq!(socket.endpoint(), Endpoint { index: 1, url: SECONDARY.to_string() });
+}
+
+#[test]
+fn round_robin_wraps_around() {
+    let (mut socket, server, timer) = open(|b| {
+        b.set_endpoint_strategy(EndpointStrategy::RoundRobin)
+            .set_failover_after(1)
+            .set_fail_back_after(Some(BACKOFF))
+    });
+
+    refuse(&mut socket, &server, &timer);
+    let peer = accept(&mut socket, &server);
+
+    // Round robin doesn't fail back
+    timer.advance(BACKOFF * 10);
+    poll_ready(&mut socket);
+    assert_eq!(peer.client_close(), None);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut socket);
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    refuse(&mut socket, &server, &timer);
+
+    assert_eq!(server.urls(), vec![PRIMARY, SECONDARY, SECONDARY, PRIMARY]);
+}
+
+#[test]
+fn fails_back_to_the_preferred_endpoint() {
+    let fail_back = Duration::from_secs(60);
+    let (mut socket, server, timer) =
+        open(|b| b.set_failover_after(1).set_fail_back_after(Some(fail_back)));
+
+    refuse(&mut socket, &server, &timer);
+    let peer = accept(&mut socket, &server);
+
+    timer.advance(fail_back - Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(peer.client_close(), None);
+
+    timer.advance(Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(peer.client_close(), Some((Some(1000), Some("failing back".to_string()))));
+    assert_eq!(server.urls(), vec![PRIMARY, SECONDARY, PRIMARY]);
+}
+
+#[test]
+fn reports_the_connected_endpoint() {
+    let (mut socket, server, timer) = open(|b| b.set_failover_after(1));
+
+    refuse(&mut socket, &server, &timer);
+    server.try_next_connection().expect("connection").accept();
+
+    #[cfg_attr(not(feature = "state-events"), allow(unused_variables))]
+    let events = poll_ready(&mut socket);
+    let secondary = Endpoint { index: 1, url: SECONDARY.to_string() };
+    #[cfg(feature = "state-events")]
+    assert!(events.iter().any(|event| matches!(event, Event::Endpoint(e) if *e == secondary)));
+    assert_eq!(socket.endpoint(), secondary);
+}
+
+#[test]
+fn empty_endpoints_is_invalid() {
+    let (builder, _server, _) = virtual_builder::<Input, Output>(BACKOFF);
+    let result = builder.set_endpoints(Vec::new()).open();
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
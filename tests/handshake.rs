@@ -0,0 +1,129 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use reconnecting_websocket::{
+    loopback::{LoopbackPeer, LoopbackServer, LoopbackTransport},
+    Error, Message, State, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    open_virtual, poll_messages, poll_ready, poll_states, split_event, text, Input, VirtualSocket,
+};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends `auth` and waits for `welcome`
+async fn authenticate(mut connection: LoopbackTransport) -> Result<LoopbackTransport, String> {
+    connection.send(text("auth")).await.map_err(|e| format!("{e:?}"))?;
+    match connection.next().await {
+        Some(Ok(Message::Text(reply))) if reply == "welcome" => Ok(connection),
+        other => Err(format!("rejected: {other:?}")),
+    }
+}
+
+fn open() -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    open_virtual(BACKOFF, |b| {
+        b.set_connect_timeout(Some(CONNECT_TIMEOUT)).set_handshake(authenticate)
+    })
+}
+
+/// Accept the next connection and complete the handshake
+fn accept(socket: &mut VirtualSocket, server: &LoopbackServer) -> LoopbackPeer {
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    poll_ready(socket);
+    assert_eq!(peer.drain(), vec![text("auth")]);
+    peer.send(text("welcome"));
+    poll_ready(socket);
+    peer
+}
+
+#[test]
+fn queued_messages_wait_for_the_handshake() {
+    let (mut socket, server, _) = open();
+    socket.try_send(Input::Bar(1)).expect("send");
+
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    let states = poll_states(&mut socket);
+    assert!(!states.contains(&State::Open));
+    assert_eq!(peer.drain(), vec![text("auth")]);
+
+    // The reply goes to the handshake, not the stream
+    peer.send(text("welcome"));
+    let events = poll_ready(&mut socket);
+    assert!(events.into_iter().all(|event| split_event(event).1 == Some(State::Open)));
+    assert_eq!(peer.drain(), vec![text("Bar(1)")]);
+}
+
+#[test]
+fn handshake_runs_on_every_connection() {
+    let (mut socket, server, timer) = open();
+    let peer = accept(&mut socket, &server);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut socket);
+    socket.try_send(Input::Bar(2)).expect("send");
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+
+    let peer = server.try_next_connection().expect("reconnect");
+    peer.accept();
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("auth")]);
+    peer.send(text("welcome"));
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("Bar(2)")]);
+}
+
+#[test]
+fn failed_handshake_is_a_failed_attempt() {
+    let (mut socket, server, timer) = open();
+    socket.try_send(Input::Bar(3)).expect("send");
+
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    poll_ready(&mut socket);
+    peer.send(text("go away"));
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Err(Error::Handshake(e))] if e.contains("go away")));
+    assert_eq!(peer.state(), State::Closed);
+    assert_eq!(peer.drain(), vec![text("auth")]);
+
+    // Retried after the backoff
+    timer.advance(BACKOFF - Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+    timer.advance(Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![text("Bar(3)")]);
+}
+
+#[test]
+fn connect_timeout_covers_the_handshake() {
+    let (mut socket, server, timer) = open();
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    poll_ready(&mut socket);
+
+    timer.advance(CONNECT_TIMEOUT);
+    let messages = poll_messages(&mut socket);
+    assert!(
+        matches!(messages.as_slice(), [Err(Error::ConnectTimeout(timeout))] if *timeout == CONNECT_TIMEOUT)
+    );
+    assert_eq!(peer.state(), State::Closed);
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
@@ -0,0 +1,146 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use reconnecting_websocket::{
+    loopback::{LoopbackPeer, LoopbackServer},
+    Error, Heartbeat, Message, State, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    accept, open_virtual, poll_messages, poll_ready, reconnect, text, Input, Output, VirtualSocket,
+};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+const INTERVAL: Duration = Duration::from_secs(30);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn ping() -> Message {
+    text("ping")
+}
+
+fn pong() -> Message {
+    text("pong")
+}
+
+/// Open a socket with a heartbeat and accept the first connection
+fn open() -> (VirtualSocket, LoopbackServer, LoopbackPeer, VirtualTimer) {
+    let (mut socket, server, timer) = open_virtual(BACKOFF, |b| {
+        b.set_heartbeat(
+            Heartbeat::new(ping(), |message| *message == pong())
+                .set_interval(INTERVAL)
+                .set_timeout(TIMEOUT),
+        )
+    });
+    let peer = accept(&mut socket, &server);
+    (socket, server, peer, timer)
+}
+
+#[test]
+fn sends_pings_and_swallows_pongs() {
+    let (mut socket, _server, peer, timer) = open();
+
+    timer.advance(INTERVAL - Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert!(peer.drain().is_empty());
+
+    timer.advance(Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![ping()]);
+
+    peer.send(pong());
+    peer.send(text("Bar(1)"));
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Ok(Output::Foo(1))]));
+
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![ping()]);
+    assert_eq!(peer.state(), State::Open);
+}
+
+#[test]
+fn reconnects_when_nothing_is_received() {
+    let (mut socket, server, peer, timer) = open();
+
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![ping()]);
+
+    timer.advance(TIMEOUT);
+    let messages = poll_messages(&mut socket);
+    assert!(
+        matches!(messages.as_slice(), [Err(Error::HeartbeatTimeout(timeout))] if *timeout == TIMEOUT)
+    );
+    assert_eq!(peer.state(), State::Closed);
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn any_traffic_counts_as_alive() {
+    let (mut socket, server, peer, timer) = open();
+
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    peer.send(text("Bar(2)"));
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Ok(Output::Foo(2))]));
+
+    timer.advance(TIMEOUT);
+    assert!(poll_messages(&mut socket).is_empty());
+    assert_eq!(peer.state(), State::Open);
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn timeout_starts_when_the_ping_is_sent() {
+    let (mut socket, _server, peer, timer) = open();
+
+    // The ping is due but the transport isn't ready for it
+    peer.set_backpressure(true);
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    timer.advance(TIMEOUT);
+    assert!(poll_messages(&mut socket).is_empty());
+    assert!(peer.drain().is_empty());
+    assert_eq!(peer.state(), State::Open);
+
+    peer.set_backpressure(false);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![ping()]);
+
+    timer.advance(TIMEOUT - Duration::from_millis(1));
+    assert!(poll_messages(&mut socket).is_empty());
+    assert_eq!(peer.state(), State::Open);
+
+    timer.advance(Duration::from_millis(1));
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Err(Error::HeartbeatTimeout(_))]));
+}
+
+#[test]
+fn unsent_pings_are_dropped_with_the_connection() {
+    let (mut socket, server, peer, timer) = open();
+
+    peer.set_backpressure(true);
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    assert!(peer.drain().is_empty());
+
+    let peer = reconnect(&mut socket, &server, &peer, &timer, BACKOFF);
+    socket.try_send(Input::Bar(1)).expect("send");
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("Bar(1)")]);
+
+    // The new connection gets its own ping on schedule
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![ping()]);
+}
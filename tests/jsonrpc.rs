@@ -0,0 +1,288 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::{FutureExt, StreamExt};
+use reconnecting_websocket::{
+    jsonrpc::{Batch, ErrorObject, Incoming, JsonRpcClient, JsonRpcError, Notification, Outgoing},
+    loopback::{LoopbackConnector, LoopbackPeer, LoopbackServer},
+    Event, Message, VirtualTimer,
+};
+use serde_json::{json, Value};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, message, poll_ready, virtual_builder};
+
+type VirtualClient = JsonRpcClient<LoopbackConnector, VirtualTimer>;
+
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Open a client and accept the first connection
+fn open() -> (VirtualClient, LoopbackServer, LoopbackPeer, VirtualTimer) {
+    let (builder, server, timer) = virtual_builder(BACKOFF);
+    let socket = builder.open().expect("open");
+    let mut client = JsonRpcClient::new(socket);
+    let peer = accept(&mut client, &server);
+    (client, server, peer, timer)
+}
+
+/// The JSON the peer has received
+fn received(peer: &LoopbackPeer) -> Vec<Value> {
+    peer.drain()
+        .into_iter()
+        .map(|message| match message {
+            Message::Text(text) => serde_json::from_str(&text).expect("json"),
+            Message::Bytes(_) => panic!("unexpected bytes"),
+        })
+        .collect()
+}
+
+fn send(peer: &LoopbackPeer, value: Value) {
+    peer.send(Message::Text(value.to_string()));
+}
+
+/// The message if the event is one
+fn incoming(event: Event<Outgoing, Incoming>) -> Option<Incoming> {
+    message(event).map(|message| message.expect("message"))
+}
+
+#[test]
+fn calls_resolve_to_typed_results() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut sum = client.call::<u64>("add", [1, 2]);
+    let mut time = client.handle().call::<String>("time", ());
+    assert!((&mut sum).now_or_never().is_none());
+    assert!((&mut time).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(received(&peer), vec![
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "add", "params": [1, 2] }),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "time" }),
+    ]);
+
+    send(&peer, json!({ "jsonrpc": "2.0", "id": 2, "result": "noon" }));
+    send(&peer, json!({ "jsonrpc": "2.0", "id": 1, "result": 3 }));
+    assert!(poll_ready(&mut client).into_iter().filter_map(incoming).next().is_none());
+
+    assert_eq!(sum.now_or_never().expect("complete").expect("sum"), 3);
+    assert_eq!(time.now_or_never().expect("complete").expect("time"), "noon");
+}
+
+#[test]
+fn error_objects_and_bad_results_fail_calls() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut missing = client.call::<Value>("missing", ());
+    let mut mistyped = client.call::<u64>("name", ());
+    assert!((&mut missing).now_or_never().is_none());
+    assert!((&mut mistyped).now_or_never().is_none());
+    poll_ready(&mut client);
+
+    send(
+        &peer,
+        json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32601, "message": "Method not found" } }),
+    );
+    send(&peer, json!({ "jsonrpc": "2.0", "id": 2, "result": "not a number" }));
+    poll_ready(&mut client);
+
+    match missing.now_or_never() {
+        Some(Err(JsonRpcError::Server(e))) => assert_eq!(e, ErrorObject {
+            code: -32601,
+            message: "Method not found".to_string(),
+            data: None
+        }),
+        other => panic!("unexpected {other:?}"),
+    }
+    assert!(matches!(mistyped.now_or_never(), Some(Err(JsonRpcError::Json(_)))));
+}
+
+#[test]
+fn notifications_go_both_ways() {
+    let (mut client, _server, peer, _) = open();
+
+    client.notify("log", ["hello"]).expect("notify");
+    poll_ready(&mut client);
+    assert_eq!(received(&peer), vec![
+        json!({ "jsonrpc": "2.0", "method": "log", "params": ["hello"] })
+    ]);
+
+    // Passed on until the notifications stream is taken
+    send(&peer, json!({ "jsonrpc": "2.0", "method": "tick" }));
+    assert_eq!(poll_ready(&mut client).into_iter().filter_map(incoming).count(), 1);
+
+    let mut notifications = client.notifications();
+    send(&peer, json!({ "jsonrpc": "2.0", "method": "tick", "params": { "n": 7 } }));
+    assert!(poll_ready(&mut client).into_iter().filter_map(incoming).next().is_none());
+    let notification = notifications.next().now_or_never().flatten().expect("notification");
+    assert_eq!(notification, Notification {
+        method: "tick".to_string(),
+        params: Some(json!({ "n": 7 })),
+    });
+    let params: Value = notification.params_as().expect("params");
+    assert_eq!(params["n"], 7);
+
+    // Passed on once nothing is listening
+    drop(notifications);
+    send(&peer, json!({ "jsonrpc": "2.0", "method": "tick" }));
+    let messages: Vec<_> = poll_ready(&mut client).into_iter().filter_map(incoming).collect();
+    assert_eq!(messages, vec![Incoming::Notification(Notification {
+        method: "tick".to_string(),
+        params: None,
+    })]);
+}
+
+#[test]
+fn string_ids_dont_match_number_ids() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut call = client.call::<u64>("add", [1, 2]);
+    assert!((&mut call).now_or_never().is_none());
+    poll_ready(&mut client);
+
+    send(&peer, json!({ "jsonrpc": "2.0", "id": "1", "result": 0 }));
+    assert_eq!(poll_ready(&mut client).into_iter().filter_map(incoming).count(), 1);
+    assert!((&mut call).now_or_never().is_none());
+
+    send(&peer, json!({ "jsonrpc": "2.0", "id": 1, "result": 3 }));
+    poll_ready(&mut client);
+    assert_eq!(call.now_or_never().expect("complete").expect("sum"), 3);
+}
+
+#[test]
+fn batch_results_are_in_call_order() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut batch =
+        client.batch(Batch::new().call("a", [1]).notify("seen", ()).call("b", json!({ "x": 2 })));
+    assert!((&mut batch).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(received(&peer), vec![json!([
+        { "jsonrpc": "2.0", "id": 1, "method": "a", "params": [1] },
+        { "jsonrpc": "2.0", "method": "seen" },
+        { "jsonrpc": "2.0", "id": 2, "method": "b", "params": { "x": 2 } },
+    ])]);
+
+    // A partial batch response doesn't match
+    send(&peer, json!([{ "jsonrpc": "2.0", "id": 2, "result": "b" }]));
+    assert_eq!(poll_ready(&mut client).into_iter().filter_map(incoming).count(), 1);
+    assert!((&mut batch).now_or_never().is_none());
+
+    send(
+        &peer,
+        json!([
+            { "jsonrpc": "2.0", "id": 2, "result": "b" },
+            { "jsonrpc": "2.0", "id": 1, "error": { "code": 1, "message": "nope" } },
+        ]),
+    );
+    poll_ready(&mut client);
+    let results = batch.now_or_never().expect("complete").expect("batch");
+    assert_eq!(results, vec![
+        Err(ErrorObject { code: 1, message: "nope".to_string(), data: None }),
+        Ok(json!("b")),
+    ]);
+
+    // Only notifications so there's nothing to wait for
+    let results = client.batch(Batch::new().notify("seen", ())).now_or_never();
+    assert!(matches!(results, Some(Ok(results)) if results.is_empty()));
+}
+
+#[test]
+fn top_level_errors_fail_batches() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut call = client.call::<u64>("add", [1, 2]);
+    let mut batch = client.batch(Batch::new().call("a", ()).call("b", ()));
+    assert!((&mut call).now_or_never().is_none());
+    assert!((&mut batch).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(received(&peer).len(), 2);
+
+    send(
+        &peer,
+        json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32600, "message": "Invalid Request" } }),
+    );
+    assert!(poll_ready(&mut client).into_iter().filter_map(incoming).next().is_none());
+    match batch.now_or_never() {
+        Some(Err(JsonRpcError::Server(e))) => assert_eq!(e.code, -32600),
+        other => panic!("unexpected {other:?}"),
+    }
+    // Single calls are left waiting for their own response
+    assert!((&mut call).now_or_never().is_none());
+
+    // With no batch to fail the error is passed on
+    send(
+        &peer,
+        json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": "Parse error" } }),
+    );
+    assert_eq!(poll_ready(&mut client).into_iter().filter_map(incoming).count(), 1);
+
+    // With more than one batch waiting it can't tell which the error is for
+    let mut first = client.batch(Batch::new().call("a", ()));
+    let mut second = client.batch(Batch::new().call("b", ()));
+    assert!((&mut first).now_or_never().is_none());
+    assert!((&mut second).now_or_never().is_none());
+    poll_ready(&mut client);
+    send(
+        &peer,
+        json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32600, "message": "Invalid Request" } }),
+    );
+    assert_eq!(poll_ready(&mut client).into_iter().filter_map(incoming).count(), 1);
+    assert!(first.now_or_never().is_none());
+    assert!(second.now_or_never().is_none());
+}
+
+#[test]
+fn batch_responses_without_ids_answer_the_calls_left_over() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut batch = client.batch(Batch::new().call("a", ()).call("b", ()).call("c", ()));
+    assert!((&mut batch).now_or_never().is_none());
+    poll_ready(&mut client);
+    received(&peer);
+
+    send(
+        &peer,
+        json!([
+            { "jsonrpc": "2.0", "id": null, "error": { "code": -32600, "message": "Invalid Request" } },
+            { "jsonrpc": "2.0", "id": 3, "result": "c" },
+            { "jsonrpc": "2.0", "id": 1, "result": "a" },
+        ]),
+    );
+    assert!(poll_ready(&mut client).into_iter().filter_map(incoming).next().is_none());
+    let results = batch.now_or_never().expect("complete").expect("batch");
+    assert_eq!(results, vec![
+        Ok(json!("a")),
+        Err(ErrorObject { code: -32600, message: "Invalid Request".to_string(), data: None }),
+        Ok(json!("c")),
+    ]);
+}
+
+#[test]
+fn idempotent_calls_survive_reconnects() {
+    let (mut client, server, peer, timer) = open();
+
+    let mut call = client.call::<bool>("subscribe", ["prices"]).set_idempotent();
+    let mut other = client.call::<bool>("ping", ());
+    assert!((&mut call).now_or_never().is_none());
+    assert!((&mut other).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(received(&peer).len(), 2);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut client);
+    assert!(matches!(other.now_or_never(), Some(Err(JsonRpcError::Rpc(_)))));
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut client);
+    let peer = accept(&mut client, &server);
+    assert_eq!(received(&peer), vec![
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "subscribe", "params": ["prices"] })
+    ]);
+
+    send(&peer, json!({ "jsonrpc": "2.0", "id": 1, "result": true }));
+    poll_ready(&mut client);
+    assert!(call.now_or_never().expect("complete").expect("call"));
+}
@@ -0,0 +1,86 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::{FutureExt, SinkExt};
+use reconnecting_websocket::{loopback::LoopbackServer, Error, PriorityLanes, SocketSink};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    accept, open_virtual, poll_ready, received, virtual_builder, Input, Output, VirtualSocket,
+};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Open a socket with `lanes` without accepting the connection
+fn open(lanes: PriorityLanes) -> (VirtualSocket, LoopbackServer) {
+    let (socket, server, _) = open_virtual(BACKOFF, |b| b.set_priority_lanes(lanes));
+    (socket, server)
+}
+
+fn send(sink: &mut SocketSink<Input>, numbers: impl IntoIterator<Item = usize>) {
+    for n in numbers {
+        sink.send(Input::Bar(n)).now_or_never().expect("space").expect("send");
+    }
+}
+
+#[test]
+fn higher_priority_lanes_go_first() {
+    let (mut socket, server) = open(PriorityLanes::new(3));
+    let mut urgent = socket.get_lane_sink(0).expect("lane");
+    let mut normal = socket.get_lane_sink(1).expect("lane");
+    assert!(socket.get_lane_sink(3).is_none());
+
+    // The socket's own sink is the lowest priority lane
+    socket.try_send(Input::Bar(1)).expect("send");
+    send(&mut socket.get_sink(), [2]);
+    send(&mut normal, [3, 4]);
+    send(&mut urgent, [5]);
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(received(&peer), vec![5, 3, 4, 1, 2]);
+
+    send(&mut normal, [6]);
+    send(&mut urgent, [7]);
+    poll_ready(&mut socket);
+    assert_eq!(received(&peer), vec![7, 6]);
+}
+
+#[test]
+fn starved_lanes_get_a_turn() {
+    let (mut socket, server) = open(PriorityLanes::new(2).set_starvation_limit(2));
+    let mut urgent = socket.get_lane_sink(0).expect("lane");
+
+    send(&mut socket.get_sink(), 100..103);
+    send(&mut urgent, 1..7);
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(received(&peer), vec![1, 2, 100, 3, 4, 101, 5, 6, 102]);
+}
+
+#[test]
+fn lanes_have_their_own_capacity() {
+    let (mut socket, server, _) = open_virtual(BACKOFF, |b| {
+        b.set_priority_lanes(PriorityLanes::new(2)).set_queue_capacity(1)
+    });
+    let mut urgent = socket.get_lane_sink(0).expect("lane");
+
+    socket.try_send(Input::Bar(1)).expect("send");
+    assert!(socket.try_send(Input::Bar(2)).is_err_and(|e| e.is_full()));
+    send(&mut urgent, [3]);
+
+    let peer = accept(&mut socket, &server);
+    assert_eq!(received(&peer), vec![3, 1]);
+}
+
+#[test]
+fn invalid_lanes_are_rejected() {
+    for lanes in [PriorityLanes::new(0), PriorityLanes::new(2).set_starvation_limit(0)] {
+        let (builder, _server, _) = virtual_builder::<Input, Output>(BACKOFF);
+        let result = builder.set_priority_lanes(lanes).open();
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+}
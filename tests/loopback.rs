@@ -0,0 +1,181 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use gloo::net::websocket::WebSocketError;
+use reconnecting_websocket::{
+    loopback::{loopback, LoopbackConnector, LoopbackServer},
+    Error, Message, Socket, SocketBuilder, State, TokioTimer,
+};
+use tokio::time::timeout;
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{split_event, Input, MessageResult, Output};
+
+type LoopbackSocket = Socket<Input, Output, LoopbackConnector, TokioTimer>;
+
+const URL: &str = "ws://loopback";
+
+fn open() -> (LoopbackSocket, LoopbackServer) {
+    let (connector, server) = loopback();
+    let socket = SocketBuilder::<Input, Output, LoopbackConnector, TokioTimer>::new_with_connector(
+        URL.to_string(),
+        connector,
+    )
+    .set_backoff_min(Duration::from_millis(10))
+    .open()
+    .expect("open");
+    (socket, server)
+}
+
+/// Poll the socket for `duration` of (paused) tokio time and collect what it produced
+async fn drive(
+    socket: &mut LoopbackSocket,
+    duration: Duration,
+) -> (Vec<MessageResult>, Vec<State>) {
+    let mut messages = Vec::new();
+    let mut states = Vec::new();
+    let _ = timeout(duration, async {
+        while let Some(event) = socket.next().await {
+            let (message, state) = split_event(event);
+            messages.extend(message);
+            states.extend(state);
+        }
+    })
+    .await;
+    (messages, states)
+}
+
+const STEP: Duration = Duration::from_millis(5);
+
+#[tokio::test(start_paused = true)]
+async fn loopback_round_trip() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+    assert_eq!(server.urls(), vec![URL.to_string()]);
+
+    peer.accept();
+    socket.send(Input::Bar(1)).await.expect("send");
+    drive(&mut socket, STEP).await;
+    assert_eq!(peer.drain(), vec![Message::Text("Bar(1)".to_string())]);
+
+    assert!(peer.send(Message::Text("Bar(2)".to_string())));
+    let (messages, _) = drive(&mut socket, STEP).await;
+    assert!(matches!(messages.as_slice(), [Ok(Output::Foo(2))]));
+}
+
+#[tokio::test(start_paused = true)]
+async fn loopback_stalled_handshake_holds_messages() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+
+    socket.send(Input::Bar(1)).await.expect("send");
+    drive(&mut socket, Duration::from_secs(10)).await;
+    assert_eq!(peer.state(), State::Connecting);
+    assert_eq!(peer.try_recv(), None);
+
+    peer.accept();
+    drive(&mut socket, STEP).await;
+    assert_eq!(peer.drain(), vec![Message::Text("Bar(1)".to_string())]);
+}
+
+#[cfg(feature = "state-events")]
+#[tokio::test(start_paused = true)]
+async fn loopback_refused_connection_reconnects() {
+    let (mut socket, server) = open();
+    server.try_next_connection().expect("connection").refuse();
+
+    let (messages, states) = drive(&mut socket, STEP).await;
+    assert!(matches!(messages.as_slice(), [
+        Err(Error::WebSocketError(WebSocketError::ConnectionError)),
+        Err(Error::WebSocketError(WebSocketError::ConnectionClose(_)))
+    ]));
+    assert_eq!(states.first(), Some(&State::Closed));
+
+    // The retry happens after the backoff
+    drive(&mut socket, Duration::from_secs(1)).await;
+    assert_eq!(server.connection_count(), 2);
+    let peer = server.try_next_connection().expect("reconnection");
+    peer.accept();
+    let (_, states) = drive(&mut socket, STEP).await;
+    assert_eq!(states, vec![State::Open]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn loopback_server_close_code_is_reported() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    drive(&mut socket, STEP).await;
+
+    peer.close(4000, "go away");
+    let (messages, _) = drive(&mut socket, STEP).await;
+    match messages.as_slice() {
+        [Err(Error::WebSocketError(WebSocketError::ConnectionClose(event)))] => {
+            assert_eq!(event.code, 4000);
+            assert_eq!(event.reason, "go away");
+        },
+        other => panic!("unexpected messages: {other:?}"),
+    }
+
+    drive(&mut socket, Duration::from_secs(1)).await;
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn loopback_injected_error_keeps_connection() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    drive(&mut socket, STEP).await;
+
+    peer.inject_error(WebSocketError::ConnectionError);
+    let (messages, _) = drive(&mut socket, STEP).await;
+    assert!(matches!(messages.as_slice(), [Err(Error::WebSocketError(
+        WebSocketError::ConnectionError
+    ))]));
+
+    socket.send(Input::Bar(3)).await.expect("send");
+    drive(&mut socket, STEP).await;
+    assert_eq!(peer.drain(), vec![Message::Text("Bar(3)".to_string())]);
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn loopback_client_close_code_is_recorded() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+    peer.accept();
+    drive(&mut socket, STEP).await;
+
+    socket.close_socket(Some(1000), Some("test close"));
+    assert_eq!(peer.client_close(), Some((Some(1000), Some("test close".to_string()))));
+    assert_eq!(peer.state(), State::Closed);
+
+    // The socket reconnects
+    drive(&mut socket, Duration::from_secs(1)).await;
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn loopback_auto_accept() {
+    let (connector, mut server) = loopback();
+    server.set_auto_accept(true);
+    let mut socket =
+        SocketBuilder::<Input, Output, LoopbackConnector, TokioTimer>::new_with_connector(
+            URL.to_string(),
+            connector,
+        )
+        .open()
+        .expect("open");
+    let peer = server.next().await.expect("connection");
+    assert_eq!(peer.state(), State::Open);
+
+    socket.send(Input::Bar(4)).await.expect("send");
+    drive(&mut socket, STEP).await;
+    assert_eq!(peer.drain(), vec![Message::Text("Bar(4)".to_string())]);
+}
@@ -0,0 +1,144 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+#[cfg(feature = "state-events")]
+use reconnecting_websocket::State;
+use reconnecting_websocket::{
+    native::{Socket, SocketBuilder},
+    Error,
+};
+use tokio::{net::TcpListener, time::timeout};
+use tokio_tungstenite::accept_async;
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{split_event, Input, Output};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts an echo server on a random local port. Each connection is closed by the server after
+/// `close_after` messages have been echoed (if set)
+async fn echo_server(close_after: Option<usize>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut ws = accept_async(stream).await.expect("accept_async");
+                let mut echoed = 0;
+                while let Some(Ok(message)) = ws.next().await {
+                    if message.is_text() || message.is_binary() {
+                        ws.send(message).await.expect("echo");
+                        echoed += 1;
+                        if Some(echoed) == close_after {
+                            let _ = ws.close(None).await;
+                            // Drain until the client acknowledges the close
+                            while let Some(Ok(_)) = ws.next().await {}
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    format!("ws://{addr}")
+}
+
+async fn send_messages(socket: &mut Socket<Input, Output>, count: usize) {
+    let mut outstanding = Vec::new();
+    for i in 0..count {
+        outstanding.push(i);
+        socket.send(Input::Bar(i)).await.expect("send");
+    }
+
+    timeout(TIMEOUT, async {
+        while !outstanding.is_empty() {
+            let event = socket.next().await.expect("next None");
+            if let (Some(Ok(Output::Foo(n))), _) = split_event(event) {
+                outstanding.retain(|v| *v != n);
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for echoes");
+}
+
+#[cfg(feature = "state-events")]
+async fn wait_for_state(socket: &mut Socket<Input, Output>, state: State) {
+    timeout(TIMEOUT, async {
+        loop {
+            let event = socket.next().await.expect("next None");
+            if split_event(event).1 == Some(state) {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for state");
+}
+
+#[test]
+fn native_socket_is_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Socket<Input, Output>>();
+}
+
+#[tokio::test]
+async fn native_reconnect() {
+    let url = echo_server(None).await;
+    let mut socket = SocketBuilder::<Input, Output>::new(url).open().expect("open");
+
+    send_messages(&mut socket, 10).await;
+
+    // Drop the socket
+    socket.close_socket(None, Some("test close"));
+
+    send_messages(&mut socket, 10).await;
+}
+
+#[cfg(feature = "state-events")]
+#[tokio::test]
+async fn native_server_close_reconnects() {
+    let url = echo_server(Some(3)).await;
+    let mut socket = SocketBuilder::<Input, Output>::new(url)
+        .set_backoff_min(Duration::from_millis(10))
+        .open()
+        .expect("open");
+
+    wait_for_state(&mut socket, State::Open).await;
+    send_messages(&mut socket, 3).await;
+    wait_for_state(&mut socket, State::Closed).await;
+    wait_for_state(&mut socket, State::Open).await;
+    send_messages(&mut socket, 3).await;
+}
+
+#[tokio::test]
+async fn native_invalid_url_fails_fast() {
+    let result = SocketBuilder::<Input, Output>::new("not a url".to_string()).open();
+    assert!(matches!(result, Err(Error::TungsteniteError(_))));
+}
+
+#[tokio::test]
+async fn native_sink_sends() {
+    let url = echo_server(None).await;
+    let mut socket = SocketBuilder::<Input, Output>::new(url).open().expect("open");
+    let mut sink = socket.get_sink();
+    sink.send(Input::Bar(42)).await.expect("send");
+
+    let output = timeout(TIMEOUT, async {
+        loop {
+            if let (Some(m), _) = split_event(socket.next().await.expect("next None")) {
+                break m;
+            }
+        }
+    })
+    .await
+    .expect("timed out");
+    assert!(matches!(output, Ok(Output::Foo(42))));
+}
@@ -0,0 +1,84 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use reconnecting_websocket::{loopback::LoopbackServer, ManualSignal, State, VirtualTimer};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{poll_ready, poll_states, virtual_builder, VirtualSocket, VirtualSocketBuilder};
+
+const BACKOFF: Duration = Duration::from_secs(60);
+
+fn open(
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer, ManualSignal) {
+    let (builder, server, timer) = virtual_builder(BACKOFF);
+    server.set_auto_accept(true);
+    let online = ManualSignal::new(true);
+    let socket = configure(builder.set_online_signal(online.clone())).open().expect("open");
+    (socket, server, timer, online)
+}
+
+/// Close the current connection from the server end
+fn server_close(socket: &mut VirtualSocket, server: &LoopbackServer) {
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(socket);
+    peer.close(1001, "going away");
+}
+
+#[test]
+fn offline_pauses_reconnects_without_using_retries() {
+    let (mut socket, server, timer, online) = open(|b| b.set_max_retries(1));
+
+    online.set(false);
+    server_close(&mut socket, &server);
+    let states = poll_states(&mut socket);
+    if cfg!(feature = "state-events") {
+        assert_eq!(states.last(), Some(&State::Offline));
+    }
+
+    // Hours offline don't burn through retries
+    for _ in 0..10 {
+        timer.advance(BACKOFF * 60);
+        poll_ready(&mut socket);
+    }
+    assert_eq!(server.connection_count(), 1);
+
+    online.set(true);
+    let states = poll_states(&mut socket);
+    if cfg!(feature = "state-events") {
+        assert_eq!(states, vec![State::Connecting, State::Open]);
+    }
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn coming_online_cuts_the_backoff_short() {
+    let (mut socket, server, timer, online) = open(|b| b);
+
+    server_close(&mut socket, &server);
+    poll_ready(&mut socket);
+    timer.advance(BACKOFF / 2);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+
+    online.set(false);
+    poll_ready(&mut socket);
+    online.set(true);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn going_offline_keeps_an_open_connection() {
+    let (mut socket, server, _, online) = open(|b| b);
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(&mut socket);
+
+    online.set(false);
+    assert_eq!(poll_states(&mut socket), vec![]);
+    assert_eq!(peer.state(), State::Open);
+}
@@ -0,0 +1,219 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{atomic::Ordering, Arc},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{executor::block_on, task::waker, SinkExt};
+#[cfg(feature = "state-events")]
+use reconnecting_websocket::Event;
+use reconnecting_websocket::{
+    loopback::{LoopbackPeer, LoopbackServer},
+    Error, OverflowPolicy, SendError, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    open_virtual, poll_ready, received, text, virtual_builder, Input, Output, VirtualSocket,
+    WakeCount,
+};
+
+const CAPACITY: usize = 4;
+const TTL: Duration = Duration::from_secs(10);
+
+fn open(overflow: OverflowPolicy) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    open_virtual(Duration::from_secs(1), |b| {
+        b.set_queue_capacity(CAPACITY).set_overflow_policy(overflow)
+    })
+}
+
+fn fill(socket: &mut VirtualSocket, messages: impl IntoIterator<Item = usize>) {
+    for i in messages {
+        socket.try_send(Input::Bar(i)).expect("queue has space");
+    }
+}
+
+/// Accept the connection and return the numbers of the messages the server received
+fn accept(socket: &mut VirtualSocket, peer: &LoopbackPeer) -> Vec<usize> {
+    peer.accept();
+    poll_ready(socket);
+    received(peer)
+}
+
+/// Poll the socket and return the numbers of the messages reported as dropped
+#[cfg(feature = "state-events")]
+fn poll_dropped(socket: &mut VirtualSocket) -> Vec<usize> {
+    poll_ready(socket)
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Dropped(Input::Bar(i)) => Some(i),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Poll the socket and return the numbers of the messages taken as dropped
+#[cfg(not(feature = "state-events"))]
+fn poll_dropped(socket: &mut VirtualSocket) -> Vec<usize> {
+    poll_ready(socket);
+    socket.take_dropped().into_iter().map(|Input::Bar(i)| i).collect()
+}
+
+#[test]
+fn reject_new_returns_the_message() {
+    let (mut socket, server, _) = open(OverflowPolicy::RejectNew);
+    let peer = server.try_next_connection().expect("connection");
+    fill(&mut socket, 0..CAPACITY);
+
+    let err = socket.try_send(Input::Bar(98)).expect_err("rejected");
+    assert!(matches!(err, SendError::Full(Input::Bar(98))));
+    // Send doesn't wait either
+    let err = block_on(socket.send(Input::Bar(99))).expect_err("rejected");
+    assert!(matches!(err.into_inner(), Input::Bar(99)));
+
+    assert_eq!(accept(&mut socket, &peer), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn drop_oldest_makes_space() {
+    let (mut socket, server, _) = open(OverflowPolicy::DropOldest);
+    let peer = server.try_next_connection().expect("connection");
+    fill(&mut socket, 0..CAPACITY + 2);
+
+    assert_eq!(poll_dropped(&mut socket), vec![0, 1]);
+    assert_eq!(accept(&mut socket, &peer), vec![2, 3, 4, 5]);
+}
+
+#[test]
+fn drop_older_than_drops_expired_messages_to_make_space() {
+    let (mut socket, server, timer) = open(OverflowPolicy::DropOlderThan(TTL));
+    let peer = server.try_next_connection().expect("connection");
+
+    fill(&mut socket, 0..2);
+    timer.advance(TTL / 2);
+    fill(&mut socket, 2..4);
+
+    // Nothing has expired yet so there's no space
+    assert!(socket.try_send(Input::Bar(4)).expect_err("full").is_full());
+
+    timer.advance(TTL / 2);
+    socket.try_send(Input::Bar(4)).expect("expired messages dropped");
+    assert_eq!(poll_dropped(&mut socket), vec![0, 1]);
+    assert_eq!(accept(&mut socket, &peer), vec![2, 3, 4]);
+}
+
+#[test]
+fn drop_older_than_doesnt_send_expired_messages() {
+    let (mut socket, server, timer) = open(OverflowPolicy::DropOlderThan(TTL));
+    let peer = server.try_next_connection().expect("connection");
+
+    fill(&mut socket, 0..1);
+    timer.advance(TTL);
+    fill(&mut socket, 1..2);
+
+    assert_eq!(accept(&mut socket, &peer), vec![1]);
+}
+
+#[test]
+fn senders_are_woken_when_the_whole_queue_expires() {
+    let (mut socket, server, timer) = open(OverflowPolicy::DropOlderThan(TTL));
+    let peer = server.try_next_connection().expect("connection");
+    fill(&mut socket, 0..CAPACITY);
+
+    let count = Arc::new(WakeCount::default());
+    let waker = waker(count.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut sink = socket.get_sink();
+    let mut send = pin!(sink.send(Input::Bar(99)));
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+
+    // Everything queued expires as it comes off the queue, leaving it empty
+    timer.advance(TTL);
+    assert!(accept(&mut socket, &peer).is_empty());
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    assert!(matches!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    poll_ready(&mut socket);
+    assert_eq!(received(&peer), vec![99]);
+}
+
+#[test]
+fn zero_ttl_is_invalid() {
+    let (builder, _server, _) = virtual_builder::<Input, Output>(Duration::from_secs(1));
+    let result = builder.set_overflow_policy(OverflowPolicy::DropOlderThan(Duration::ZERO)).open();
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
+
+#[test]
+fn messages_past_their_ttl_are_dropped_instead_of_sent() {
+    let (mut socket, server, timer) = open(OverflowPolicy::Block);
+    let peer = server.try_next_connection().expect("connection");
+    let mut sink = socket.get_sink();
+
+    block_on(socket.send_with_ttl(Input::Bar(0), TTL / 2)).expect("send");
+    fill(&mut socket, 1..2);
+    block_on(sink.send_with_ttl(Input::Bar(2), TTL)).expect("send");
+    block_on(sink.send_with_ttl(Input::Bar(3), TTL / 2)).expect("send");
+
+    timer.advance(TTL / 2);
+    peer.accept();
+    assert_eq!(poll_dropped(&mut socket), vec![0, 3]);
+    assert_eq!(socket.dropped_count(), 2);
+    assert_eq!(sink.dropped_count(), 2);
+    assert_eq!(peer.drain(), vec![text("Bar(1)"), text("Bar(2)")]);
+}
+
+#[test]
+fn messages_sent_within_their_ttl_are_unaffected() {
+    let (mut socket, server, timer) = open(OverflowPolicy::Block);
+    let peer = server.try_next_connection().expect("connection");
+
+    block_on(socket.send_with_ttl(Input::Bar(0), TTL)).expect("send");
+    timer.advance(TTL - Duration::from_millis(1));
+    assert_eq!(accept(&mut socket, &peer), vec![0]);
+
+    block_on(socket.send_with_ttl(Input::Bar(1), TTL)).expect("send");
+    assert!(poll_dropped(&mut socket).is_empty());
+    assert_eq!(peer.drain(), vec![text("Bar(1)")]);
+    assert_eq!(socket.dropped_count(), 0);
+}
+
+#[test]
+fn ttls_too_long_to_represent_never_expire() {
+    let (mut socket, server, timer) = open(OverflowPolicy::Block);
+    let peer = server.try_next_connection().expect("connection");
+
+    timer.advance(TTL);
+    block_on(socket.send_with_ttl(Input::Bar(0), Duration::MAX)).expect("send");
+    timer.advance(TTL);
+    assert_eq!(accept(&mut socket, &peer), vec![0]);
+    assert_eq!(socket.dropped_count(), 0);
+}
+
+#[test]
+fn only_the_most_recent_dropped_messages_are_kept() {
+    let (mut socket, _server, _) = open(OverflowPolicy::DropOldest);
+    fill(&mut socket, 0..CAPACITY * 3);
+
+    assert_eq!(poll_dropped(&mut socket), vec![4, 5, 6, 7]);
+    assert_eq!(socket.dropped_count(), 8);
+}
+
+#[cfg(not(feature = "state-events"))]
+#[test]
+fn dropped_messages_are_taken_from_the_sink() {
+    let (mut socket, _server, _) = open(OverflowPolicy::DropOldest);
+    let sink = socket.get_sink();
+    fill(&mut socket, 0..CAPACITY + 2);
+
+    assert_eq!(sink.take_dropped().into_iter().map(|Input::Bar(i)| i).collect::<Vec<_>>(), vec![
+        0, 1
+    ]);
+    assert!(socket.take_dropped().is_empty());
+}
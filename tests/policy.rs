@@ -0,0 +1,181 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::stream::FusedStream;
+use reconnecting_websocket::{
+    loopback::LoopbackServer, ConstantBackoff, DecorrelatedJitterBackoff, FibonacciBackoff,
+    LinearBackoff, ReconnectContext, ReconnectDecision, ReconnectPolicy, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, open_virtual, poll_ready, VirtualSocket, VirtualSocketBuilder};
+
+/// Open a socket with the reconnect policy under test. The first connection is left to accept
+fn open(
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    // Every test replaces the backoff with its own policy
+    open_virtual(Duration::ZERO, configure)
+}
+
+/// Records every context it's given and returns a fixed decision
+#[derive(Debug, Clone)]
+struct Recording {
+    contexts: Arc<Mutex<Vec<ReconnectContext>>>,
+    decision: ReconnectDecision,
+}
+
+impl Recording {
+    fn new(decision: ReconnectDecision) -> Self {
+        Self { contexts: Default::default(), decision }
+    }
+
+    fn contexts(&self) -> Vec<ReconnectContext> {
+        self.contexts.lock().unwrap().clone()
+    }
+}
+
+impl ReconnectPolicy for Recording {
+    fn next_delay(&mut self, context: &ReconnectContext) -> ReconnectDecision {
+        self.contexts.lock().unwrap().push(context.clone());
+        self.decision
+    }
+}
+
+fn attempt(attempt: u32) -> ReconnectContext {
+    ReconnectContext { attempt, ..Default::default() }
+}
+
+#[test]
+fn policy_is_told_how_the_connection_ended() {
+    let policy = Recording::new(ReconnectDecision::RetryAfter(Duration::from_secs(1)));
+    let (mut socket, server, timer) = open(|b| b.set_reconnect_policy(policy.clone()));
+
+    let peer = accept(&mut socket, &server);
+    timer.advance(Duration::from_secs(3));
+    peer.close(4001, "bye");
+    poll_ready(&mut socket);
+
+    let contexts = policy.contexts();
+    assert_eq!(contexts.len(), 1);
+    assert_eq!(contexts[0].attempt, 0);
+    let close = contexts[0].close.as_ref().expect("close event");
+    assert_eq!((close.code, close.reason.as_str()), (4001, "bye"));
+    assert!(contexts[0].error.as_deref().expect("error").contains("ConnectionClose"));
+    assert_eq!(contexts[0].connected_for, Some(Duration::from_secs(3)));
+
+    // A refused reconnect never opened
+    timer.advance(Duration::from_secs(1));
+    poll_ready(&mut socket);
+    server.try_next_connection().expect("reconnection").refuse();
+    poll_ready(&mut socket);
+
+    let contexts = policy.contexts();
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[1].attempt, 1);
+    assert_eq!(contexts[1].connected_for, None);
+}
+
+#[test]
+fn policy_give_up_ends_the_stream() {
+    let (mut socket, server, _) =
+        open(|b| b.set_reconnect_policy(Recording::new(ReconnectDecision::GiveUp)));
+
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(&mut socket);
+
+    assert!(socket.is_terminated());
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn constant_policy_waits_the_same_delay() {
+    let delay = Duration::from_secs(1);
+    let (mut socket, server, timer) = open(|b| b.set_reconnect_policy(ConstantBackoff::new(delay)));
+
+    for connections in 1..4 {
+        server.try_next_connection().expect("connection").refuse();
+        poll_ready(&mut socket);
+
+        timer.advance(delay - Duration::from_millis(1));
+        poll_ready(&mut socket);
+        assert_eq!(server.connection_count(), connections);
+
+        timer.advance(Duration::from_millis(1));
+        poll_ready(&mut socket);
+        assert_eq!(server.connection_count(), connections + 1);
+    }
+}
+
+#[test]
+fn max_retries_applies_to_custom_policies() {
+    let (mut socket, server, timer) = open(|b| {
+        b.set_reconnect_policy(ConstantBackoff::new(Duration::from_secs(1))).set_max_retries(2)
+    });
+
+    while !socket.is_terminated() {
+        if let Some(peer) = server.try_next_connection() {
+            peer.refuse();
+        }
+        poll_ready(&mut socket);
+        timer.advance(Duration::from_secs(1));
+    }
+
+    // The initial connection then reconnects until the retry count exceeds max retries, the same
+    // as the default policy
+    assert_eq!(server.connection_count(), 4);
+}
+
+#[test]
+fn linear_policy_delays() {
+    let mut policy = LinearBackoff::new(
+        Duration::from_secs(1),
+        Duration::from_secs(2),
+        Some(Duration::from_secs(6)),
+    );
+    let delays = (0..5).map(|n| policy.next_delay(&attempt(n))).collect::<Vec<_>>();
+    assert_eq!(
+        delays,
+        [1, 3, 5, 6, 6].map(|s| ReconnectDecision::RetryAfter(Duration::from_secs(s)))
+    );
+}
+
+#[test]
+fn fibonacci_policy_delays() {
+    let mut policy = FibonacciBackoff::new(Duration::from_secs(1), Some(Duration::from_secs(10)));
+    let delays = (0..8).map(|n| policy.next_delay(&attempt(n))).collect::<Vec<_>>();
+    assert_eq!(
+        delays,
+        [1, 1, 2, 3, 5, 8, 10, 10].map(|s| ReconnectDecision::RetryAfter(Duration::from_secs(s)))
+    );
+}
+
+#[test]
+fn decorrelated_jitter_policy_stays_in_bounds() {
+    let base = Duration::from_millis(100);
+    let max = Duration::from_secs(5);
+    let mut policy = DecorrelatedJitterBackoff::new(base, max);
+
+    let mut previous = base;
+    for n in 0..50 {
+        let ReconnectDecision::RetryAfter(delay) = policy.next_delay(&attempt(n)) else {
+            panic!("decorrelated jitter gave up");
+        };
+        assert!(delay >= base && delay <= max, "{delay:?} out of bounds");
+        assert!(delay <= previous * 3, "{delay:?} more than 3x {previous:?}");
+        previous = delay;
+    }
+
+    // Attempt 0 starts again from the base
+    let ReconnectDecision::RetryAfter(delay) = policy.next_delay(&attempt(0)) else {
+        panic!("decorrelated jitter gave up");
+    };
+    assert!(delay <= base * 3);
+}
@@ -0,0 +1,123 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::{atomic::Ordering, Arc},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{
+    task::{noop_waker_ref, waker},
+    Sink, SinkExt,
+};
+use reconnecting_websocket::{loopback::LoopbackServer, Error};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    accept, open_virtual, poll_ready, virtual_builder, Input, Output, VirtualSocket, WakeCount,
+};
+
+const CAPACITY: usize = 4;
+
+fn open() -> (VirtualSocket, LoopbackServer) {
+    let (socket, server, _) =
+        open_virtual(Duration::from_secs(1), |b| b.set_queue_capacity(CAPACITY));
+    (socket, server)
+}
+
+#[test]
+fn try_send_fails_when_full() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+
+    for i in 0..CAPACITY {
+        socket.try_send(Input::Bar(i)).expect("queue has space");
+    }
+    let err = socket.try_send(Input::Bar(99)).expect_err("queue is full");
+    assert!(err.is_full());
+
+    // The send future waits instead of failing
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert!(pin!(socket.send(Input::Bar(99))).as_mut().poll(&mut cx).is_pending());
+
+    peer.accept();
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain().len(), CAPACITY);
+    socket.try_send(Input::Bar(100)).expect("queue has space again");
+}
+
+#[test]
+fn sink_applies_backpressure() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+    let mut sink = socket.get_sink();
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let mut queued = 0;
+    while let Poll::Ready(ready) = Pin::new(&mut sink).poll_ready(&mut cx) {
+        ready.expect("poll_ready");
+        Pin::new(&mut sink).start_send(Input::Bar(queued)).expect("start_send");
+        queued += 1;
+        assert!(queued <= CAPACITY, "sink never applied backpressure");
+    }
+    assert_eq!(queued, CAPACITY);
+
+    peer.accept();
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain().len(), queued);
+    assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+}
+
+#[test]
+fn waiting_senders_are_woken_once() {
+    let (mut socket, server) = open();
+    let peer = server.try_next_connection().expect("connection");
+    for i in 0..CAPACITY {
+        socket.try_send(Input::Bar(i)).expect("queue has space");
+    }
+
+    let count = Arc::new(WakeCount::default());
+    let waker = waker(count.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut sink = socket.get_sink();
+    for _ in 0..10 {
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_pending());
+    }
+
+    peer.accept();
+    poll_ready(&mut socket);
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn space_is_only_made_while_the_socket_is_polled() {
+    let (mut socket, server) = open();
+    let peer = accept(&mut socket, &server);
+    for i in 0..CAPACITY {
+        socket.try_send(Input::Bar(i)).expect("queue has space");
+    }
+
+    // Socket::send borrows the socket so this is how another task waits for space
+    let mut sink = socket.get_sink();
+    let mut send = pin!(sink.send(Input::Bar(99)));
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+
+    poll_ready(&mut socket);
+    assert!(matches!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain().len(), CAPACITY + 1);
+}
+
+#[test]
+fn zero_capacity_is_invalid() {
+    let (builder, _server, _) = virtual_builder::<Input, Output>(Duration::from_secs(1));
+    let result = builder.set_queue_capacity(0).open();
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
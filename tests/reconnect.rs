@@ -5,7 +5,9 @@ use gloo::timers::future::TimeoutFuture;
 use reconnecting_websocket::Event;
 use reconnecting_websocket::{Socket, SocketBuilder};
 
-#[path = "./common.rs"]
+// Relative to the crate root so it also resolves when this file is the crate doc example
+#[path = "../tests/common.rs"]
+#[allow(dead_code)]
 mod common;
 
 use common::{configure_tracing_once, Input, Output, ECHO_SERVER};
@@ -55,13 +57,17 @@ async fn reconnect() {
                             match r {
                                 Event::Message(m) => handle_message(m, &mut outstanding_packets),
                                 Event::State(s) => info!("State changed: {s:?}"),
+                                Event::Endpoint(e) => info!("Connected to: {e:?}"),
+                                Event::Stats(s) => info!("Stats: {s:?}"),
+                                Event::Dropped(i) => info!("Dropped: {i:?}"),
+                                Event::Gap(gap) => info!("Gap: {gap:?}"),
                             }
                         } else {
                             handle_message(r, &mut outstanding_packets);
                         }
                     }
 
-                    if outstanding_packets.len() == 0 {
+                    if outstanding_packets.is_empty() {
                         break;
                     }
                 },
@@ -0,0 +1,131 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use reconnecting_websocket::{
+    loopback::{LoopbackPeer, LoopbackServer},
+    Ack, Envelope, Error, Message, Reliable, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{
+    accept, open_virtual, poll_messages, poll_ready, reconnect, text, virtual_builder, Input,
+    Output, VirtualSocket,
+};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Prefixes messages with `<seq>:`. The server acknowledges with `ack:<seq>` for everything up to
+/// seq or `got:<seq>` for one message
+struct TextEnvelope;
+
+impl Envelope for TextEnvelope {
+    fn wrap(&mut self, seq: u64, message: Message) -> Message {
+        match message {
+            Message::Text(text) => Message::Text(format!("{seq}:{text}")),
+            other => other,
+        }
+    }
+
+    fn ack(&mut self, message: &Message) -> Option<Ack> {
+        let Message::Text(text) = message else {
+            return None;
+        };
+        if let Some(seq) = text.strip_prefix("ack:") {
+            seq.parse().ok().map(Ack::Through)
+        } else if let Some(seq) = text.strip_prefix("got:") {
+            seq.parse().ok().map(Ack::Only)
+        } else {
+            None
+        }
+    }
+}
+
+/// Open a reliable socket and accept the first connection
+fn open(capacity: usize) -> (VirtualSocket, LoopbackServer, LoopbackPeer, VirtualTimer) {
+    let (mut socket, server, timer) = open_virtual(BACKOFF, |b| {
+        b.set_reliable(Reliable::new(TextEnvelope).set_capacity(capacity))
+    });
+    let peer = accept(&mut socket, &server);
+    (socket, server, peer, timer)
+}
+
+#[test]
+fn resends_unacknowledged_messages_after_reconnect() {
+    let (mut socket, server, peer, timer) = open(16);
+
+    for i in 1..=3 {
+        socket.try_send(Input::Bar(i)).expect("send");
+    }
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("1:Bar(1)"), text("2:Bar(2)"), text("3:Bar(3)")]);
+
+    // Acknowledgements aren't passed on
+    peer.send(text("ack:1"));
+    peer.send(text("Bar(7)"));
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Ok(Output::Foo(7))]));
+    assert_eq!(socket.unacked(), 2);
+
+    // Sent while disconnected so it goes after the resent messages
+    peer.close(1001, "going away");
+    poll_ready(&mut socket);
+    socket.try_send(Input::Bar(4)).expect("send");
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    let peer = accept(&mut socket, &server);
+
+    assert_eq!(peer.drain(), vec![text("2:Bar(2)"), text("3:Bar(3)"), text("4:Bar(4)")]);
+    assert_eq!(socket.unacked(), 3);
+}
+
+#[test]
+fn individual_acknowledgements() {
+    let (mut socket, server, peer, timer) = open(16);
+
+    for i in 1..=3 {
+        socket.try_send(Input::Bar(i)).expect("send");
+    }
+    poll_ready(&mut socket);
+    peer.drain();
+
+    peer.send(text("got:2"));
+    poll_ready(&mut socket);
+    assert_eq!(socket.unacked(), 2);
+
+    let peer = reconnect(&mut socket, &server, &peer, &timer, BACKOFF);
+    assert_eq!(peer.drain(), vec![text("1:Bar(1)"), text("3:Bar(3)")]);
+
+    peer.send(text("ack:3"));
+    poll_ready(&mut socket);
+    assert_eq!(socket.unacked(), 0);
+
+    let peer = reconnect(&mut socket, &server, &peer, &timer, BACKOFF);
+    assert!(peer.drain().is_empty());
+}
+
+#[test]
+fn waits_for_acknowledgements_when_full() {
+    let (mut socket, _server, peer, _) = open(2);
+
+    for i in 1..=3 {
+        socket.try_send(Input::Bar(i)).expect("send");
+    }
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("1:Bar(1)"), text("2:Bar(2)")]);
+
+    peer.send(text("got:1"));
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("3:Bar(3)")]);
+    assert_eq!(socket.unacked(), 2);
+}
+
+#[test]
+fn zero_capacity_is_invalid() {
+    let (builder, _server, _) = virtual_builder::<Input, Output>(BACKOFF);
+    let result = builder.set_reliable(Reliable::new(TextEnvelope).set_capacity(0)).open();
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
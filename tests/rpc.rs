@@ -0,0 +1,243 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::FutureExt;
+use reconnecting_websocket::{
+    loopback::{LoopbackConnector, LoopbackPeer, LoopbackServer},
+    Correlation, Message, RpcClient, RpcError, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, message, poll_ready, reconnect, text, virtual_builder};
+
+/// Sent as `<id>:<body>`
+#[derive(Debug, Clone)]
+struct Request {
+    id: u64,
+    body: String,
+}
+
+impl TryFrom<Request> for Message {
+    type Error = ();
+
+    fn try_from(request: Request) -> Result<Self, Self::Error> {
+        Ok(Message::Text(format!("{}:{}", request.id, request.body)))
+    }
+}
+
+/// Responses are `<id>:<body>`, anything else is a notification
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Response { id: u64, body: String },
+    Notification(String),
+}
+
+impl TryFrom<Message> for Reply {
+    type Error = ();
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        let Message::Text(text) = message else {
+            return Err(());
+        };
+        let response = text.split_once(':').and_then(|(id, body)| {
+            id.parse().ok().map(|id| Reply::Response { id, body: body.to_string() })
+        });
+        Ok(response.unwrap_or(Reply::Notification(text)))
+    }
+}
+
+struct ById;
+
+impl Correlation<Request, Reply> for ById {
+    fn request_id(&self, request: &Request) -> Option<String> {
+        Some(request.id.to_string())
+    }
+
+    fn response_id(&self, response: &Reply) -> Option<String> {
+        match response {
+            Reply::Response { id, .. } => Some(id.to_string()),
+            Reply::Notification(_) => None,
+        }
+    }
+}
+
+type VirtualClient = RpcClient<Request, Reply, LoopbackConnector, VirtualTimer>;
+
+const BACKOFF: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Open a client and accept the first connection
+fn open() -> (VirtualClient, LoopbackServer, LoopbackPeer, VirtualTimer) {
+    let (builder, server, timer) = virtual_builder(BACKOFF);
+    let socket = builder.open().expect("open");
+    let mut client = RpcClient::new(socket, ById).set_timeout(TIMEOUT);
+    let peer = accept(&mut client, &server);
+    (client, server, peer, timer)
+}
+
+fn request(id: u64) -> Request {
+    Request { id, body: "ping".to_string() }
+}
+
+/// Poll the client and return the replies it passed on
+fn poll_replies(client: &mut VirtualClient) -> Vec<Reply> {
+    poll_ready(client).into_iter().filter_map(message).map(|reply| reply.expect("reply")).collect()
+}
+
+#[test]
+fn responses_complete_calls() {
+    let (mut client, _server, peer, _) = open();
+
+    let mut first = client.call(request(1));
+    let mut second = client.handle().call(request(2));
+    assert!((&mut first).now_or_never().is_none());
+    assert!((&mut second).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(peer.drain(), vec![text("1:ping"), text("2:ping")]);
+
+    peer.send(text("2:pong"));
+    peer.send(text("news"));
+    peer.send(text("1:pong"));
+    // Only the notification reaches the stream
+    assert_eq!(poll_replies(&mut client), vec![Reply::Notification("news".to_string())]);
+
+    let response = first.now_or_never().expect("complete");
+    assert_eq!(response, Ok(Reply::Response { id: 1, body: "pong".to_string() }));
+    let response = second.now_or_never().expect("complete");
+    assert_eq!(response, Ok(Reply::Response { id: 2, body: "pong".to_string() }));
+}
+
+#[test]
+fn calls_time_out() {
+    let (mut client, _server, peer, timer) = open();
+
+    let mut default = client.call(request(1));
+    let mut short = client.call(request(2)).set_timeout(TIMEOUT / 5);
+    assert!((&mut default).now_or_never().is_none());
+    assert!((&mut short).now_or_never().is_none());
+    poll_ready(&mut client);
+
+    timer.advance(TIMEOUT / 5);
+    assert_eq!(short.now_or_never(), Some(Err(RpcError::Timeout(TIMEOUT / 5))));
+    assert!((&mut default).now_or_never().is_none());
+
+    timer.advance(TIMEOUT);
+    assert_eq!(default.now_or_never(), Some(Err(RpcError::Timeout(TIMEOUT))));
+
+    // Late responses aren't matched to anything so they're passed on
+    peer.send(text("1:pong"));
+    assert_eq!(poll_replies(&mut client), vec![Reply::Response {
+        id: 1,
+        body: "pong".to_string()
+    }]);
+}
+
+#[test]
+fn disconnect_fails_pending_calls() {
+    let (mut client, server, peer, timer) = open();
+
+    let mut call = client.call(request(1));
+    assert!((&mut call).now_or_never().is_none());
+    poll_ready(&mut client);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut client);
+    assert_eq!(call.now_or_never(), Some(Err(RpcError::Disconnected)));
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut client);
+    let peer = accept(&mut client, &server);
+    assert!(peer.drain().is_empty());
+}
+
+#[test]
+fn reconnects_the_client_didnt_see_fail_and_reissue_calls() {
+    let (mut client, server, peer, timer) = open();
+
+    let mut call = client.call(request(1));
+    let mut idempotent = client.call(request(2)).set_idempotent();
+    assert!((&mut call).now_or_never().is_none());
+    assert!((&mut idempotent).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(peer.drain(), vec![text("1:ping"), text("2:ping")]);
+
+    // The socket is open again by the time the client looks, like a reconnect within one poll
+    let peer = reconnect(client.socket_mut(), &server, &peer, &timer, BACKOFF);
+    poll_ready(&mut client);
+    assert_eq!(call.now_or_never(), Some(Err(RpcError::Disconnected)));
+    assert_eq!(peer.drain(), vec![text("2:ping")]);
+
+    peer.send(text("2:pong"));
+    poll_ready(&mut client);
+    let response = idempotent.now_or_never().expect("complete");
+    assert_eq!(response, Ok(Reply::Response { id: 2, body: "pong".to_string() }));
+}
+
+#[test]
+fn idempotent_calls_are_reissued_after_reconnect() {
+    let (mut client, server, peer, timer) = open();
+
+    let mut call = client.call(request(1)).set_idempotent();
+    assert!((&mut call).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert_eq!(peer.drain(), vec![text("1:ping")]);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut client);
+    assert!((&mut call).now_or_never().is_none());
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut client);
+    let peer = accept(&mut client, &server);
+    assert_eq!(peer.drain(), vec![text("1:ping")]);
+
+    peer.send(text("1:pong"));
+    poll_ready(&mut client);
+    let response = call.now_or_never().expect("complete");
+    assert_eq!(response, Ok(Reply::Response { id: 1, body: "pong".to_string() }));
+}
+
+#[test]
+fn unsent_calls_survive_a_disconnect() {
+    let (mut client, server, peer, timer) = open();
+
+    peer.set_backpressure(true);
+    let mut call = client.call(request(1));
+    let mut idempotent = client.call(request(2)).set_idempotent();
+    assert!((&mut call).now_or_never().is_none());
+    assert!((&mut idempotent).now_or_never().is_none());
+    poll_ready(&mut client);
+    assert!(peer.drain().is_empty());
+
+    // Neither request reached the connection so both are still queued
+    let peer = reconnect(&mut client, &server, &peer, &timer, BACKOFF);
+    assert!((&mut call).now_or_never().is_none());
+    assert_eq!(peer.drain(), vec![text("1:ping"), text("2:ping")]);
+
+    peer.send(text("1:pong"));
+    peer.send(text("2:pong"));
+    poll_ready(&mut client);
+    let response = call.now_or_never().expect("complete");
+    assert_eq!(response, Ok(Reply::Response { id: 1, body: "pong".to_string() }));
+    let response = idempotent.now_or_never().expect("complete");
+    assert_eq!(response, Ok(Reply::Response { id: 2, body: "pong".to_string() }));
+}
+
+#[test]
+fn duplicate_ids_and_closed_clients_fail() {
+    let (client, _server, _peer, _) = open();
+
+    let mut first = client.call(request(1));
+    assert!((&mut first).now_or_never().is_none());
+    let duplicate = client.call(request(1)).now_or_never();
+    assert_eq!(duplicate, Some(Err(RpcError::DuplicateId("1".to_string()))));
+
+    let handle = client.handle();
+    drop(client);
+    assert_eq!(first.now_or_never(), Some(Err(RpcError::Closed)));
+    assert_eq!(handle.call(request(2)).now_or_never(), Some(Err(RpcError::Closed)));
+}
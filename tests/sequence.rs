@@ -0,0 +1,153 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+#[cfg(not(feature = "state-events"))]
+use reconnecting_websocket::Error;
+#[cfg(feature = "state-events")]
+use reconnecting_websocket::Event;
+use reconnecting_websocket::{
+    loopback::{LoopbackPeer, LoopbackServer},
+    Gap, Message, Sequencing, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, message, open_virtual, poll_ready, Output, VirtualSocket};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Messages from the server are `Bar(n)` where n is the sequence number
+fn seq(output: &Output) -> Option<u64> {
+    let Output::Foo(n) = output;
+    Some(*n as u64)
+}
+
+const GAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Open a sequenced socket and accept the first connection
+fn open(window: usize) -> (VirtualSocket, LoopbackServer, LoopbackPeer, VirtualTimer) {
+    let (mut socket, server, timer) = open_virtual(BACKOFF, |b| {
+        b.set_sequencing(Sequencing::new(seq).set_window(window).set_gap_timeout(Some(GAP_TIMEOUT)))
+    });
+    let peer = accept(&mut socket, &server);
+    (socket, server, peer, timer)
+}
+
+fn send(peer: &LoopbackPeer, numbers: impl IntoIterator<Item = usize>) {
+    for n in numbers {
+        peer.send(Message::Text(format!("Bar({n})")));
+    }
+}
+
+/// Poll the socket and return the numbers of the messages it produced and the gaps it reported
+fn poll_sequence(socket: &mut VirtualSocket) -> Vec<Result<usize, Gap>> {
+    poll_ready(socket)
+        .into_iter()
+        .filter_map(|event| match event {
+            #[cfg(feature = "state-events")]
+            Event::Gap(gap) => Some(Err(gap)),
+            #[cfg(not(feature = "state-events"))]
+            Err(Error::SequenceGap(gap)) => Some(Err(gap)),
+            event => match message(event)? {
+                Ok(Output::Foo(n)) => Some(Ok(n)),
+                Err(e) => panic!("unexpected error {e}"),
+            },
+        })
+        .collect()
+}
+
+/// Poll the socket and return the numbers of the messages it produced, ignoring gaps
+fn poll_numbers(socket: &mut VirtualSocket) -> Vec<usize> {
+    poll_sequence(socket).into_iter().filter_map(Result::ok).collect()
+}
+
+#[test]
+fn drops_duplicates_and_reorders() {
+    let (mut socket, _server, peer, _) = open(8);
+
+    send(&peer, [1, 2, 2, 1, 4, 3, 5, 4]);
+    assert_eq!(poll_numbers(&mut socket), vec![1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn drops_messages_replayed_after_reconnect() {
+    let (mut socket, server, peer, timer) = open(8);
+
+    send(&peer, [1, 2, 3]);
+    assert_eq!(poll_numbers(&mut socket), vec![1, 2, 3]);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut socket);
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    let peer = server.try_next_connection().expect("reconnect");
+    peer.accept();
+
+    send(&peer, [2, 3, 4]);
+    assert_eq!(poll_numbers(&mut socket), vec![4]);
+}
+
+#[test]
+fn gives_up_on_missing_messages_when_the_window_is_full() {
+    let (mut socket, _server, peer, _) = open(2);
+
+    send(&peer, [1, 3, 4]);
+    assert_eq!(poll_numbers(&mut socket), vec![1]);
+
+    send(&peer, [5]);
+    assert_eq!(poll_numbers(&mut socket), vec![3, 4, 5]);
+
+    // Too late
+    send(&peer, [2, 6]);
+    assert_eq!(poll_numbers(&mut socket), vec![6]);
+}
+
+#[test]
+fn gives_up_on_missing_messages_after_the_gap_timeout() {
+    let (mut socket, _server, peer, timer) = open(8);
+
+    send(&peer, [1, 3, 4]);
+    assert_eq!(poll_sequence(&mut socket), vec![Ok(1)]);
+
+    timer.advance(GAP_TIMEOUT / 2);
+    send(&peer, [2, 6]);
+    assert_eq!(poll_sequence(&mut socket), vec![Ok(2), Ok(3), Ok(4)]);
+
+    // Waiting for 5 started when 2 arrived
+    timer.advance(GAP_TIMEOUT / 2);
+    assert_eq!(poll_sequence(&mut socket), vec![]);
+
+    timer.advance(GAP_TIMEOUT / 2);
+    assert_eq!(poll_sequence(&mut socket), vec![Err(Gap { first: 5, last: 5 }), Ok(6)]);
+
+    send(&peer, [7]);
+    assert_eq!(poll_sequence(&mut socket), vec![Ok(7)]);
+}
+
+#[test]
+fn reports_gaps() {
+    let (mut socket, _server, peer, _) = open(0);
+
+    send(&peer, [1, 4, 5]);
+    assert_eq!(poll_sequence(&mut socket), vec![
+        Ok(1),
+        Err(Gap { first: 2, last: 3 }),
+        Ok(4),
+        Ok(5)
+    ]);
+}
+
+#[test]
+fn reset_starts_the_sequence_again() {
+    let (mut socket, _server, peer, _) = open(8);
+
+    send(&peer, [5, 7]);
+    assert_eq!(poll_numbers(&mut socket), vec![5]);
+
+    socket.reset_sequence();
+    send(&peer, [1, 2, 7]);
+    assert_eq!(poll_numbers(&mut socket), vec![1, 2]);
+}
@@ -0,0 +1,138 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+#[cfg(feature = "state-events")]
+use reconnecting_websocket::Event;
+use reconnecting_websocket::{
+    loopback::{LoopbackPeer, LoopbackServer},
+    Heartbeat, Message, RttStats, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, open_virtual, poll_ready, Input, VirtualSocket, VirtualSocketBuilder};
+
+const INTERVAL: Duration = Duration::from_secs(30);
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+/// Open a socket and accept the first connection
+fn open(
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, LoopbackPeer, VirtualTimer) {
+    let (mut socket, server, timer) = open_virtual(Duration::from_secs(1), configure);
+    let peer = accept(&mut socket, &server);
+    (socket, server, peer, timer)
+}
+
+#[test]
+fn heartbeat_pongs_are_measured() {
+    let pong = Message::Text("pong".to_string());
+    let is_pong = pong.clone();
+    let (mut socket, _server, peer, timer) = open(|b| {
+        b.set_heartbeat(
+            Heartbeat::new(Message::Text("ping".to_string()), move |m| *m == is_pong)
+                .set_interval(INTERVAL),
+        )
+    });
+    assert_eq!(socket.stats(), None);
+
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    timer.advance(ms(200));
+    peer.send(pong);
+    poll_ready(&mut socket);
+
+    let stats = socket.stats().expect("stats");
+    assert_eq!(stats.samples, 1);
+    assert_eq!(stats.last, ms(200));
+}
+
+#[test]
+fn round_trip_starts_when_the_ping_is_sent() {
+    let pong = Message::Text("pong".to_string());
+    let is_pong = pong.clone();
+    let (mut socket, _server, peer, timer) = open(|b| {
+        b.set_heartbeat(
+            Heartbeat::new(Message::Text("ping".to_string()), move |m| *m == is_pong)
+                .set_interval(INTERVAL),
+        )
+    });
+
+    // The transport is backed up when the ping is due so it waits with the rest of the traffic
+    peer.set_backpressure(true);
+    timer.advance(INTERVAL);
+    poll_ready(&mut socket);
+    socket.try_send(Input::Bar(1)).expect("send");
+    poll_ready(&mut socket);
+    timer.advance(ms(300));
+    peer.set_backpressure(false);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![
+        Message::Text("ping".to_string()),
+        Message::Text("Bar(1)".to_string())
+    ]);
+
+    timer.advance(ms(200));
+    peer.send(pong);
+    poll_ready(&mut socket);
+    assert_eq!(socket.stats().expect("stats").last, ms(200));
+}
+
+#[test]
+fn stats_are_calculated_from_the_samples() {
+    let (mut socket, ..) = open(|b| b);
+    for rtt in [50, 10, 30, 20, 40] {
+        socket.record_rtt(ms(rtt));
+    }
+
+    assert_eq!(
+        socket.stats(),
+        Some(RttStats {
+            samples: 5,
+            last: ms(40),
+            min: ms(10),
+            mean: ms(30),
+            p95: ms(50),
+            // |10-50| + |30-10| + |20-30| + |40-20| = 90 over 4 gaps
+            jitter: ms(90) / 4,
+        })
+    );
+}
+
+#[test]
+fn old_samples_leave_the_window() {
+    let (mut socket, ..) = open(|b| b.set_rtt_window(2));
+    for rtt in [100, 10, 20] {
+        socket.record_rtt(ms(rtt));
+    }
+
+    let stats = socket.stats().expect("stats");
+    assert_eq!(stats.samples, 2);
+    assert_eq!(stats.min, ms(10));
+    assert_eq!(stats.p95, ms(20));
+    assert_eq!(stats.mean, ms(15));
+}
+
+#[cfg(feature = "state-events")]
+#[test]
+fn stats_events_are_periodic() {
+    let (mut socket, _server, _peer, timer) = open(|b| b.set_stats_interval(Some(INTERVAL)));
+
+    // Nothing to report without samples
+    timer.advance(INTERVAL);
+    assert!(!poll_ready(&mut socket).iter().any(|e| matches!(e, Event::Stats(_))));
+
+    socket.record_rtt(ms(5));
+    timer.advance(INTERVAL - ms(1));
+    assert!(!poll_ready(&mut socket).iter().any(|e| matches!(e, Event::Stats(_))));
+
+    timer.advance(ms(1));
+    let events = poll_ready(&mut socket);
+    assert!(matches!(events.as_slice(), [Event::Stats(stats)] if stats.last == ms(5)));
+}
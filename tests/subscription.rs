@@ -0,0 +1,233 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use futures::{FutureExt, StreamExt};
+use reconnecting_websocket::{
+    loopback::LoopbackServer, Message, SubscribeError, Subscription, Topics, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, message, poll_ready, reconnect, text, virtual_builder};
+
+/// Sent as `sub:<topic>`, `unsub:<topic>` and `say:<text>`
+#[derive(Debug)]
+enum Command {
+    Subscribe(String),
+    Unsubscribe(String),
+    Say(String),
+}
+
+impl TryFrom<Command> for Message {
+    type Error = ();
+
+    fn try_from(command: Command) -> Result<Self, Self::Error> {
+        Ok(Message::Text(match command {
+            Command::Subscribe(topic) => format!("sub:{topic}"),
+            Command::Unsubscribe(topic) => format!("unsub:{topic}"),
+            Command::Say(text) => format!("say:{text}"),
+        }))
+    }
+}
+
+/// Published messages are `<topic>:<body>`, anything else is passed on
+#[derive(Debug, PartialEq, Eq)]
+enum Update {
+    Published { topic: String, body: String },
+    Other(String),
+}
+
+impl TryFrom<Message> for Update {
+    type Error = ();
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        let Message::Text(text) = message else {
+            return Err(());
+        };
+        Ok(match text.split_once(':') {
+            Some((topic, body)) => {
+                Update::Published { topic: topic.to_string(), body: body.to_string() }
+            },
+            None => Update::Other(text),
+        })
+    }
+}
+
+struct PubSub;
+
+impl Topics<Command, Update> for PubSub {
+    fn subscribe(&self, topic: &str) -> Command {
+        Command::Subscribe(topic.to_string())
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Command {
+        Command::Unsubscribe(topic.to_string())
+    }
+
+    fn topic(&self, update: &Update) -> Option<String> {
+        match update {
+            Update::Published { topic, .. } => Some(topic.clone()),
+            Update::Other(_) => None,
+        }
+    }
+}
+
+type VirtualSocket = common::VirtualSocket<Command, Update>;
+
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Open a socket with subscriptions without accepting the connection
+fn open() -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    let (builder, server, timer) = virtual_builder(BACKOFF);
+    let socket = builder.set_subscriptions(PubSub).open().expect("open");
+    (socket, server, timer)
+}
+
+fn published(topic: &str, body: &str) -> Update {
+    Update::Published { topic: topic.to_string(), body: body.to_string() }
+}
+
+/// Poll the socket and return the messages it passed on
+fn poll_messages(socket: &mut VirtualSocket) -> Vec<Update> {
+    poll_ready(socket)
+        .into_iter()
+        .filter_map(message)
+        .map(|message| message.expect("message"))
+        .collect()
+}
+
+/// The messages the subscription has received so far
+fn received(subscription: &mut Subscription<Update>) -> Vec<Update> {
+    std::iter::from_fn(|| subscription.next().now_or_never().flatten()).collect()
+}
+
+#[test]
+fn messages_are_routed_to_subscriptions() {
+    let (mut socket, server, _) = open();
+
+    let mut a = socket.subscribe("a").expect("subscribe");
+    let mut b = socket.subscribe("b").expect("subscribe");
+    assert_eq!(a.topic(), "a");
+    socket.try_send(Command::Say("hi".to_string())).expect("send");
+
+    // Subscriptions go ahead of the queue
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![text("sub:a"), text("sub:b"), text("say:hi")]);
+
+    peer.send(text("a:1"));
+    peer.send(text("c:2"));
+    peer.send(text("hello"));
+    peer.send(text("b:3"));
+    peer.send(text("a:4"));
+    assert_eq!(poll_messages(&mut socket), vec![
+        published("c", "2"),
+        Update::Other("hello".to_string())
+    ]);
+    assert_eq!(received(&mut a), vec![published("a", "1"), published("a", "4")]);
+    assert_eq!(received(&mut b), vec![published("b", "3")]);
+}
+
+#[test]
+fn subscriptions_are_replayed_before_queued_messages_after_reconnect() {
+    let (mut socket, server, timer) = open();
+    let peer = accept(&mut socket, &server);
+
+    let mut a = socket.subscribe("a").expect("subscribe");
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("sub:a")]);
+
+    peer.close(1001, "going away");
+    poll_ready(&mut socket);
+    socket.try_send(Command::Say("queued".to_string())).expect("send");
+    let _b = socket.subscribe("b").expect("subscribe");
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    let peer = accept(&mut socket, &server);
+    assert_eq!(peer.drain(), vec![text("sub:a"), text("sub:b"), text("say:queued")]);
+
+    peer.send(text("a:1"));
+    assert!(poll_messages(&mut socket).is_empty());
+    assert_eq!(received(&mut a), vec![published("a", "1")]);
+}
+
+#[test]
+fn messages_left_unsent_by_the_last_connection_wait_for_the_replay() {
+    let (mut socket, server, timer) = open();
+    let peer = accept(&mut socket, &server);
+
+    let _a = socket.subscribe("a").expect("subscribe");
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("sub:a")]);
+
+    // Taken off the queue but the transport isn't ready for it
+    peer.set_backpressure(true);
+    socket.try_send(Command::Say("stuck".to_string())).expect("send");
+    poll_ready(&mut socket);
+    assert!(peer.drain().is_empty());
+
+    let peer = reconnect(&mut socket, &server, &peer, &timer, BACKOFF);
+    assert_eq!(peer.drain(), vec![text("sub:a"), text("say:stuck")]);
+}
+
+#[test]
+fn dropping_a_subscription_unsubscribes() {
+    let (mut socket, server, timer) = open();
+    let peer = accept(&mut socket, &server);
+
+    let a = socket.subscribe("a").expect("subscribe");
+    let _b = socket.subscribe("b").expect("subscribe");
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("sub:a"), text("sub:b")]);
+
+    drop(a);
+    poll_ready(&mut socket);
+    assert_eq!(peer.drain(), vec![text("unsub:a")]);
+
+    // No longer routed
+    peer.send(text("a:1"));
+    assert_eq!(poll_messages(&mut socket), vec![published("a", "1")]);
+
+    let peer = reconnect(&mut socket, &server, &peer, &timer, BACKOFF);
+    assert_eq!(peer.drain(), vec![text("sub:b")]);
+
+    // Subscribing and unsubscribing before either is sent sends neither
+    let c = socket.subscribe("c").expect("subscribe");
+    drop(c);
+    poll_ready(&mut socket);
+    assert!(peer.drain().is_empty());
+}
+
+#[test]
+fn subscribe_errors() {
+    let (mut socket, _server, _) = open();
+
+    let a = socket.subscribe("a").expect("subscribe");
+    assert_eq!(socket.subscribe("a").err(), Some(SubscribeError::AlreadySubscribed("a".into())));
+    drop(a);
+    assert!(socket.subscribe("a").is_ok());
+
+    let (builder, _server, _) = virtual_builder::<Command, Update>(BACKOFF);
+    let mut socket = builder.open().expect("open");
+    assert_eq!(socket.subscribe("a").err(), Some(SubscribeError::NotEnabled));
+}
+
+#[test]
+fn closing_the_socket_ends_subscriptions() {
+    let (mut socket, server, _) = open();
+    let _peer = accept(&mut socket, &server);
+
+    let mut a = socket.subscribe("a").expect("subscribe");
+    assert!(a.next().now_or_never().is_none());
+    socket.close(None, None);
+    assert_eq!(a.next().now_or_never(), Some(None));
+
+    let mut b = {
+        let (mut socket, _server, _) = open();
+        socket.subscribe("b").expect("subscribe")
+    };
+    assert_eq!(b.next().now_or_never(), Some(None));
+}
@@ -0,0 +1,113 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::time::Duration;
+
+use reconnecting_websocket::{
+    loopback::LoopbackServer, ManualSignal, State, SuspendMode, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{poll_ready, poll_states, virtual_builder, VirtualSocket};
+
+const BACKOFF: Duration = Duration::from_secs(60);
+const GRACE: Duration = Duration::from_secs(30);
+
+fn open(
+    mode: SuspendMode,
+    visible: bool,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer, ManualSignal) {
+    let signal = ManualSignal::new(visible);
+    let (builder, server, timer) = virtual_builder(BACKOFF);
+    server.set_auto_accept(true);
+    let socket = builder.set_visibility_signal(signal.clone(), GRACE, mode).open().expect("open");
+    (socket, server, timer, signal)
+}
+
+#[test]
+fn hidden_past_grace_closes_and_visible_reconnects() {
+    let (mut socket, server, timer, visible) = open(SuspendMode::Close, true);
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(&mut socket);
+
+    visible.set(false);
+    poll_ready(&mut socket);
+    timer.advance(GRACE - Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(peer.state(), State::Open);
+
+    timer.advance(Duration::from_millis(1));
+    let states = poll_states(&mut socket);
+    assert_eq!(peer.state(), State::Closed);
+    assert_eq!(peer.client_close(), Some((Some(1000), Some("page hidden".to_string()))));
+    if cfg!(feature = "state-events") {
+        assert_eq!(states, vec![State::Suspended]);
+    }
+
+    // Nothing happens while hidden
+    timer.advance(BACKOFF * 10);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+
+    visible.set(true);
+    let states = poll_states(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+    if cfg!(feature = "state-events") {
+        assert_eq!(states, vec![State::Connecting, State::Open]);
+    }
+}
+
+#[test]
+fn visible_within_grace_keeps_the_connection() {
+    let (mut socket, server, timer, visible) = open(SuspendMode::Close, true);
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(&mut socket);
+
+    visible.set(false);
+    poll_ready(&mut socket);
+    timer.advance(GRACE / 2);
+    visible.set(true);
+    poll_ready(&mut socket);
+    timer.advance(GRACE);
+    assert_eq!(poll_states(&mut socket), vec![]);
+    assert_eq!(peer.state(), State::Open);
+}
+
+#[test]
+fn stop_reconnecting_keeps_the_connection_until_it_drops() {
+    let (mut socket, server, timer, visible) = open(SuspendMode::StopReconnecting, true);
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(&mut socket);
+
+    visible.set(false);
+    poll_ready(&mut socket);
+    timer.advance(GRACE);
+    poll_ready(&mut socket);
+    assert_eq!(peer.state(), State::Open);
+
+    peer.close(1001, "going away");
+    let states = poll_states(&mut socket);
+    if cfg!(feature = "state-events") {
+        assert_eq!(states.last(), Some(&State::Suspended));
+    }
+    timer.advance(BACKOFF * 10);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+
+    visible.set(true);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn opening_hidden_starts_the_grace_period() {
+    let (mut socket, server, timer, _) = open(SuspendMode::Close, false);
+    let peer = server.try_next_connection().expect("connection");
+    poll_ready(&mut socket);
+
+    timer.advance(GRACE);
+    poll_ready(&mut socket);
+    assert_eq!(peer.state(), State::Closed);
+}
@@ -0,0 +1,93 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    future::Future,
+    pin::pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::task::noop_waker_ref;
+use reconnecting_websocket::{
+    loopback::{loopback, LoopbackServer},
+    Timer, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{accept, poll_ready, VirtualSocket, VirtualSocketBuilder, LOOPBACK_URL};
+
+const BACKOFF_MIN: Duration = Duration::from_millis(100);
+
+/// Open a socket with the default exponential backoff rather than [`common::virtual_builder`]'s
+/// constant one
+fn open(
+    configure: impl FnOnce(VirtualSocketBuilder) -> VirtualSocketBuilder,
+) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    let (connector, server) = loopback();
+    let timer = VirtualTimer::new();
+    let socket = configure(
+        VirtualSocketBuilder::new_with_connector(LOOPBACK_URL.to_string(), connector)
+            .set_timer(timer.clone())
+            .set_backoff_min(BACKOFF_MIN),
+    )
+    .open()
+    .expect("open");
+    (socket, server, timer)
+}
+
+#[test]
+fn virtual_delay_completes_after_advance() {
+    let timer = VirtualTimer::new();
+    let mut delay = pin!(timer.delay(Duration::from_secs(5)));
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    assert_eq!(delay.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(timer.next_deadline(), Some(Duration::from_secs(5)));
+
+    timer.advance(Duration::from_secs(4));
+    assert_eq!(delay.as_mut().poll(&mut cx), Poll::Pending);
+
+    timer.advance(Duration::from_secs(1));
+    assert_eq!(delay.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert_eq!(timer.now(), Duration::from_secs(5));
+    assert_eq!(timer.next_deadline(), None);
+}
+
+#[test]
+fn reconnect_waits_for_backoff() {
+    let (mut socket, server, timer) = open(|b| b);
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(&mut socket);
+
+    // Backoff for the first retry is between min and min + 30% jitter
+    timer.advance(BACKOFF_MIN - Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 1);
+
+    timer.advance(BACKOFF_MIN);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn durations_longer_than_u32_millis_are_accepted() {
+    let huge = Duration::from_millis(u32::MAX as u64) * 1000;
+    let (mut socket, server, timer) =
+        open(|b| b.set_backoff_max(Some(huge)).set_stable_timeout(huge));
+
+    let peer = accept(&mut socket, &server);
+    peer.close(1001, "going away");
+    poll_ready(&mut socket);
+
+    timer.advance(BACKOFF_MIN * 2);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 2);
+    server.try_next_connection().expect("reconnection").accept();
+    poll_ready(&mut socket);
+
+    // The stable timeout is pending and hasn't fired early
+    assert_eq!(timer.next_deadline(), Some(timer.now() + huge));
+}
@@ -0,0 +1,145 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{Sink, Stream};
+use gloo::net::websocket::WebSocketError;
+use reconnecting_websocket::{
+    Connector, ConstantBackoff, Message, Socket, SocketBuilder, State, Transport, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{poll_messages, poll_ready, Input, Output};
+
+/// Opens [`EchoTransport`]s and records the url of each one
+#[derive(Debug, Clone, Default)]
+struct EchoConnector {
+    urls: Arc<Mutex<Vec<String>>>,
+    /// Ends the current connection when set
+    drop_connection: Arc<AtomicBool>,
+}
+
+impl Connector for EchoConnector {
+    type Error = Infallible;
+    type Transport = EchoTransport;
+
+    fn connect(&mut self, url: &str) -> Result<Self::Transport, Self::Error> {
+        self.urls.lock().unwrap().push(url.to_string());
+        self.drop_connection.store(false, Ordering::SeqCst);
+        Ok(EchoTransport { dropped: self.drop_connection.clone(), echoes: VecDeque::new() })
+    }
+}
+
+/// Open straight away and sends back everything written to it
+#[derive(Debug)]
+struct EchoTransport {
+    dropped: Arc<AtomicBool>,
+    echoes: VecDeque<Message>,
+}
+
+impl Transport for EchoTransport {
+    type Error = WebSocketError;
+
+    fn state(&self) -> State {
+        if self.dropped.load(Ordering::SeqCst) {
+            State::Closed
+        } else {
+            State::Open
+        }
+    }
+
+    fn close(self, _code: Option<u16>, _reason: Option<&str>) {}
+}
+
+impl Stream for EchoTransport {
+    type Item = Result<Message, WebSocketError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.dropped.load(Ordering::SeqCst) {
+            return Poll::Ready(None);
+        }
+        match self.echoes.pop_front() {
+            Some(message) => Poll::Ready(Some(Ok(message))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Sink<Message> for EchoTransport {
+    type Error = WebSocketError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        self.echoes.push_back(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+type EchoSocket = Socket<Input, Output, EchoConnector, VirtualTimer>;
+
+const URL: &str = "ws://echo";
+const BACKOFF: Duration = Duration::from_secs(1);
+
+fn open() -> (EchoSocket, EchoConnector, VirtualTimer) {
+    let connector = EchoConnector::default();
+    let timer = VirtualTimer::new();
+    let mut socket = SocketBuilder::new_with_connector(URL.to_string(), connector.clone())
+        .set_timer(timer.clone())
+        .set_reconnect_policy(ConstantBackoff::new(BACKOFF))
+        .open()
+        .expect("open");
+    poll_ready(&mut socket);
+    (socket, connector, timer)
+}
+
+#[test]
+fn custom_transport_round_trip() {
+    let (mut socket, connector, _timer) = open();
+
+    socket.try_send(Input::Bar(1)).expect("send");
+    socket.try_send(Input::Bar(2)).expect("send");
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Ok(Output::Foo(1)), Ok(Output::Foo(2))]));
+    assert_eq!(*connector.urls.lock().unwrap(), vec![URL.to_string()]);
+}
+
+#[test]
+fn custom_transport_reconnects_through_the_connector() {
+    let (mut socket, connector, timer) = open();
+
+    connector.drop_connection.store(true, Ordering::SeqCst);
+    poll_ready(&mut socket);
+    assert_eq!(connector.urls.lock().unwrap().len(), 1);
+
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+    assert_eq!(*connector.urls.lock().unwrap(), vec![URL.to_string(), URL.to_string()]);
+
+    socket.try_send(Input::Bar(3)).expect("send");
+    let messages = poll_messages(&mut socket);
+    assert!(matches!(messages.as_slice(), [Ok(Output::Foo(3))]));
+}
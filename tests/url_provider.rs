@@ -0,0 +1,95 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use futures::{channel::oneshot, future};
+use reconnecting_websocket::{
+    loopback::LoopbackServer, Endpoint, Error, UrlProvider, VirtualTimer,
+};
+
+#[path = "./common.rs"]
+#[allow(dead_code)]
+mod common;
+
+use common::{open_virtual, poll_messages, poll_ready, VirtualSocket};
+
+const BACKOFF: Duration = Duration::from_secs(1);
+
+fn open(provider: impl UrlProvider + 'static) -> (VirtualSocket, LoopbackServer, VirtualTimer) {
+    open_virtual(BACKOFF, |b| b.set_url_provider(provider))
+}
+
+#[test]
+fn called_for_every_connect() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let provider_calls = calls.clone();
+    let (mut socket, server, timer) = open(move |endpoint: &Endpoint| {
+        let n = provider_calls.fetch_add(1, Ordering::SeqCst) + 1;
+        future::ready(Ok(format!("{}?token={n}", endpoint.url)))
+    });
+
+    // Open leaves the first connect to the socket
+    assert_eq!(server.connection_count(), 0);
+    poll_ready(&mut socket);
+    server.try_next_connection().expect("connection").refuse();
+    poll_ready(&mut socket);
+    timer.advance(BACKOFF);
+    poll_ready(&mut socket);
+
+    assert_eq!(server.urls(), vec!["ws://loopback?token=1", "ws://loopback?token=2"]);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn errors_are_retried_after_the_backoff() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let provider_calls = calls.clone();
+    let (mut socket, server, timer) = open(move |endpoint: &Endpoint| {
+        let result = match provider_calls.fetch_add(1, Ordering::SeqCst) {
+            0 => Err("token endpoint unavailable".to_string()),
+            _ => Ok(endpoint.url.clone()),
+        };
+        future::ready(result)
+    });
+
+    let messages = poll_messages(&mut socket);
+    assert!(
+        matches!(messages.as_slice(), [Err(Error::UrlProvider(e))] if e == "token endpoint unavailable")
+    );
+    assert_eq!(server.connection_count(), 0);
+
+    timer.advance(BACKOFF - Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 0);
+
+    timer.advance(Duration::from_millis(1));
+    poll_ready(&mut socket);
+    assert_eq!(server.urls(), vec!["ws://loopback"]);
+}
+
+#[test]
+fn connect_waits_for_the_url() {
+    let senders = Arc::new(Mutex::new(Vec::new()));
+    let provider_senders = senders.clone();
+    let (mut socket, server, _) = open(move |_: &Endpoint| {
+        let (sender, receiver) = oneshot::channel::<String>();
+        provider_senders.lock().unwrap().push(sender);
+        async move { receiver.await.map_err(|e| e.to_string()) }
+    });
+
+    poll_ready(&mut socket);
+    poll_ready(&mut socket);
+    assert_eq!(server.connection_count(), 0);
+
+    let sender = senders.lock().unwrap().pop().expect("provider called");
+    sender.send("ws://fetched".to_string()).expect("send url");
+    poll_ready(&mut socket);
+    assert_eq!(server.urls(), vec!["ws://fetched"]);
+    assert!(senders.lock().unwrap().is_empty());
+}